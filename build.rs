@@ -0,0 +1,405 @@
+// Checks every shader in `shader/` at build time and generates what the renderer
+// embeds from them.
+//
+// Each program (a `.vert` or `.frag` file with its `#include`s expanded) is parsed and
+// validated by naga, then written back out as GLSL 3.30 and GLSL ES 3.00 to make sure it
+// only uses what both targets support. naga reads Vulkan-style GLSL 4.50, so the source
+// is lowered first, keeping every line where it was: samplers get bindings, varyings
+// get locations, and other uniforms become plain globals.
+//
+// Outputs, in OUT_DIR:
+// - `shader_es/`: every file of `shader/` as GLSL ES 3.00, with `#version 300 es` and
+//   without uniform initializers, which ES does not allow. The wasm build embeds these
+//   (`src/shader_syntax.rs` explains why they are not naga's output).
+// - `uniform_defaults.rs`: the values of those initializers for each program, which the
+//   renderer sets after linking so both targets start from the same values.
+// - `asset_manifest.rs`: every file of `assets/` and `shader/` keyed by its path relative
+//   to the project root, for the builds that embed them (`src/assets.rs`). Shaders point
+//   at their `shader_es/` versions on wasm.
+
+use anyhow::{Context, bail};
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[path = "src/shader_include.rs"]
+mod shader_include;
+#[path = "src/shader_syntax.rs"]
+mod shader_syntax;
+
+use shader_syntax::{
+    Declaration, apply_edits, declarations, strip_comments, to_es, tokenize, version_directive,
+};
+
+fn main() -> anyhow::Result<()> {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/shader_include.rs");
+    println!("cargo:rerun-if-changed=src/shader_syntax.rs");
+    println!("cargo:rerun-if-changed=shader");
+    println!("cargo:rerun-if-changed=assets");
+    let shader_dir = Path::new("shader");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+
+    let mut files = Vec::new();
+    list_files(shader_dir, "", &mut files)?;
+    files.sort();
+
+    let mut defaults = String::from(
+        "// Generated by build.rs from the uniform initializers in shader/.\n\
+         pub const UNIFORM_DEFAULTS: &[(&str, &[(&str, UniformValue)])] = &[\n",
+    );
+    for name in &files {
+        let path = shader_dir.join(name);
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read shader {}", path.display()))?;
+        let es_path = out_dir.join("shader_es").join(name);
+        fs::create_dir_all(es_path.parent().context("Shader path has no parent")?)?;
+        fs::write(&es_path, to_es(&text))?;
+
+        let stage = match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+            Some("vert") => naga::ShaderStage::Vertex,
+            Some("frag") => naga::ShaderStage::Fragment,
+            _ => continue,
+        };
+        let program = Program::expand(shader_dir, name)?;
+        let values = program
+            .validate(stage)
+            .with_context(|| format!("shader/{} is not valid", name))?;
+        if !values.is_empty() {
+            writeln!(defaults, "    ({:?}, &[", name)?;
+            for (uniform, value) in values {
+                writeln!(defaults, "        ({:?}, {}),", uniform, value)?;
+            }
+            writeln!(defaults, "    ]),")?;
+        }
+    }
+    defaults.push_str("];\n");
+    fs::write(out_dir.join("uniform_defaults.rs"), defaults)?;
+
+    write_manifest(&out_dir, &files)?;
+    Ok(())
+}
+
+fn write_manifest(out_dir: &Path, shader_files: &[String]) -> anyhow::Result<()> {
+    let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR")?);
+    let wasm = std::env::var("CARGO_CFG_TARGET_ARCH")? == "wasm32";
+
+    let mut entries = Vec::new();
+    let mut asset_files = Vec::new();
+    list_files(&root.join("assets"), "", &mut asset_files)?;
+    for name in asset_files {
+        entries.push((format!("assets/{}", name), root.join("assets").join(&name)));
+    }
+    for name in shader_files {
+        let file = if wasm {
+            out_dir.join("shader_es").join(name)
+        } else {
+            root.join("shader").join(name)
+        };
+        entries.push((format!("shader/{}", name), file));
+    }
+    // Sorted so `assets::embedded` can binary search.
+    entries.sort();
+
+    let mut manifest = String::from(
+        "// Generated by build.rs from the files in assets/ and shader/.\n\
+         pub const MANIFEST: &[(&str, &[u8])] = &[\n",
+    );
+    for (key, file) in entries {
+        let file = file.to_str().with_context(|| format!("Non UTF-8 path {}", file.display()))?;
+        writeln!(manifest, "    ({:?}, include_bytes!({:?})),", key, file)?;
+    }
+    manifest.push_str("];\n");
+    fs::write(out_dir.join("asset_manifest.rs"), manifest)?;
+    Ok(())
+}
+
+// Paths of all files under `dir`, relative to it.
+fn list_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to list {}", dir.display()))? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+fn is_opaque(ty: &str) -> bool {
+    ty.contains("sampler")
+}
+
+// A program with its includes expanded, and the file and line each line came from.
+// The expansion is the renderer's own (`src/shader_include.rs`).
+struct Program {
+    text: String,
+    origins: Vec<(String, u32)>,
+}
+
+impl Program {
+    fn expand(dir: &Path, name: &str) -> anyhow::Result<Self> {
+        let read = |file: &str| -> anyhow::Result<Cow<'static, str>> {
+            let path = dir.join(file);
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read shader {}", path.display()))?;
+            Ok(Cow::Owned(text))
+        };
+        let expanded = shader_include::expand(name, &read)?;
+        let mut program = Program {
+            text: String::new(),
+            origins: Vec::new(),
+        };
+        for (line, file, line_number) in expanded.lines {
+            program.text.push_str(&line);
+            program.text.push('\n');
+            program.origins.push((expanded.files[file].clone(), line_number));
+        }
+        Ok(program)
+    }
+
+    // `file:line` of a span in the lowered source, which has the same lines.
+    fn locate(&self, lowered: &str, span: naga::Span) -> String {
+        let line = span.location(lowered).line_number as usize;
+        match line
+            .checked_sub(1)
+            .and_then(|index| self.origins.get(index))
+        {
+            Some((file, line)) => format!("{}:{}", file, line),
+            None => "?".to_string(),
+        }
+    }
+
+    // The program in the dialect naga reads.
+    fn lower(&self, code: &str, declarations: &[Declaration]) -> anyhow::Result<String> {
+        let mut edits = Vec::new();
+        let mut samplers = Vec::new();
+        let (mut binding, mut input, mut output) = (0, 0, 0);
+        for decl in declarations {
+            match (decl.storage.as_str(), &decl.body) {
+                // A block's members become globals: the layout of the block is checked
+                // against the Rust struct when the program is linked.
+                ("uniform", Some(body)) => {
+                    edits.push((decl.span.start..body.start, String::new()));
+                    edits.push((body.end..decl.span.end, String::new()));
+                }
+                // naga has no combined samplers: each becomes a texture and a sampler,
+                // put back together where it is used.
+                ("uniform", None) if is_opaque(&decl.ty) => {
+                    let mut split = String::new();
+                    for declarator in &decl.declarators {
+                        let name = &declarator.name;
+                        write!(
+                            split,
+                            "layout(binding = {}) uniform {} {}_texture; \
+                             layout(binding = {}) uniform sampler {}_sampler; ",
+                            binding,
+                            decl.ty.replacen("sampler", "texture", 1),
+                            name,
+                            binding + 1,
+                            name
+                        )
+                        .ok();
+                        binding += 2;
+                        samplers.push((name.as_str(), decl.ty.as_str()));
+                    }
+                    edits.push((decl.span.clone(), split));
+                }
+                // Initializers stay, so naga evaluates them.
+                ("uniform", None) => {
+                    edits.push((decl.span.start..decl.keyword.end, String::new()));
+                }
+                (_, None) if decl.layout.is_none() => {
+                    let location = if decl.storage == "in" {
+                        &mut input
+                    } else {
+                        &mut output
+                    };
+                    let qualifier = format!("layout(location = {}) ", location);
+                    *location += 1;
+                    edits.push((decl.span.start..decl.span.start, qualifier));
+                }
+                _ => {}
+            }
+        }
+
+        let declared = |at: usize| declarations.iter().any(|decl| decl.span.contains(&at));
+        for token in tokenize(code)
+            .into_iter()
+            .filter(|token| !declared(token.start))
+        {
+            let word = &code[token.clone()];
+            if let Some(&(name, ty)) = samplers.iter().find(|(name, _)| *name == word) {
+                let combined = format!("{}({}_texture, {}_sampler)", ty, name, name);
+                edits.push((token, combined));
+            } else if is_opaque(word) {
+                let line = self.locate(
+                    &self.text,
+                    naga::Span::new(token.start as u32, token.end as u32),
+                );
+                bail!(
+                    "{}: samplers can only be used as uniforms, not passed to functions",
+                    line
+                );
+            }
+        }
+
+        if let Some(directive) = version_directive(&self.text) {
+            edits.push((directive, "#version 450 core".to_string()));
+        }
+        Ok(apply_edits(&self.text, edits))
+    }
+
+    // Validates the program for both targets and returns the uniforms with
+    // initializers, with their values as `UniformValue` expressions.
+    fn validate(&self, stage: naga::ShaderStage) -> anyhow::Result<Vec<(String, String)>> {
+        let code = strip_comments(&self.text);
+        let declarations = declarations(&code);
+        let lowered = self.lower(&code, &declarations)?;
+
+        let module = naga::front::glsl::Frontend::default()
+            .parse(&naga::front::glsl::Options::from(stage), &lowered)
+            .map_err(|errors| {
+                let messages: Vec<String> = errors
+                    .errors
+                    .iter()
+                    .map(|e| format!("{}: {}", self.locate(&lowered, e.meta), e.kind))
+                    .collect();
+                anyhow::anyhow!(messages.join("\n"))
+            })?;
+        let flags = naga::valid::ValidationFlags::all();
+        let info = naga::valid::Validator::new(flags, naga::valid::Capabilities::default())
+            .validate(&module)
+            .map_err(|e| {
+                let location = e
+                    .spans()
+                    .next()
+                    .map(|(span, _)| self.locate(&lowered, *span));
+                let mut message = e.as_inner().to_string();
+                let mut source = std::error::Error::source(e.as_inner());
+                while let Some(inner) = source {
+                    write!(message, ": {}", inner).ok();
+                    source = inner.source();
+                }
+                anyhow::anyhow!("{}: {}", location.as_deref().unwrap_or("?"), message)
+            })?;
+
+        for version in [
+            naga::back::glsl::Version::Desktop(330),
+            naga::back::glsl::Version::new_gles(300),
+        ] {
+            let options = naga::back::glsl::Options {
+                version,
+                ..Default::default()
+            };
+            let pipeline = naga::back::glsl::PipelineOptions {
+                shader_stage: stage,
+                entry_point: "main".to_string(),
+                multiview: None,
+            };
+            let mut out = String::new();
+            naga::back::glsl::Writer::new(
+                &mut out,
+                &module,
+                &info,
+                &options,
+                &pipeline,
+                naga::proc::BoundsCheckPolicies::default(),
+            )
+            .and_then(|mut writer| writer.write())
+            .with_context(|| format!("Not expressible in GLSL {}", version))?;
+        }
+
+        let mut values = Vec::new();
+        for decl in &declarations {
+            if decl.storage != "uniform" || decl.body.is_some() || is_opaque(&decl.ty) {
+                continue;
+            }
+            for declarator in decl.declarators.iter().filter(|d| d.initializer.is_some()) {
+                let init = module
+                    .global_variables
+                    .iter()
+                    .find(|(_, var)| var.name.as_deref() == Some(declarator.name.as_str()))
+                    .and_then(|(_, var)| var.init)
+                    .with_context(|| {
+                        format!("`{}` has no evaluated initializer", declarator.name)
+                    })?;
+                let value = uniform_value(&module, &decl.ty, init)
+                    .with_context(|| format!("Default of `{}`", declarator.name))?;
+                values.push((declarator.name.clone(), value));
+            }
+        }
+        Ok(values)
+    }
+}
+
+// A constant expression as a `UniformValue` expression for the generated table.
+fn uniform_value(
+    module: &naga::Module,
+    ty: &str,
+    init: naga::Handle<naga::Expression>,
+) -> anyhow::Result<String> {
+    let mut literals = Vec::new();
+    flatten(module, init, &mut literals)?;
+    let value = match (ty, literals.as_slice()) {
+        ("float", [naga::Literal::F32(v)]) => format!("UniformValue::Float({:?})", v),
+        ("int", [naga::Literal::I32(v)]) => format!("UniformValue::Int({})", v),
+        ("bool", [naga::Literal::Bool(v)]) => format!("UniformValue::Bool({})", v),
+        ("vec2", _) | ("vec3", _) | ("vec4", _) | ("mat3", _) | ("mat4", _) => {
+            let floats = literals
+                .iter()
+                .map(|literal| match literal {
+                    naga::Literal::F32(v) => Ok(format!("{:?}", v)),
+                    other => bail!("{:?} in a float type", other),
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let variant = format!("{}{}", ty[..1].to_uppercase(), &ty[1..]);
+            format!("UniformValue::{}([{}])", variant, floats.join(", "))
+        }
+        _ => bail!("{} uniforms cannot have a default", ty),
+    };
+    Ok(value)
+}
+
+// The scalar components of a constant expression, columns first for matrices.
+fn flatten(
+    module: &naga::Module,
+    handle: naga::Handle<naga::Expression>,
+    out: &mut Vec<naga::Literal>,
+) -> anyhow::Result<()> {
+    match module.global_expressions[handle] {
+        naga::Expression::Literal(literal) => out.push(literal),
+        naga::Expression::Constant(constant) => {
+            flatten(module, module.constants[constant].init, out)?
+        }
+        naga::Expression::Compose { ref components, .. } => {
+            for &component in components {
+                flatten(module, component, out)?;
+            }
+        }
+        naga::Expression::Splat { size, value } => {
+            for _ in 0..size as usize {
+                flatten(module, value, out)?;
+            }
+        }
+        naga::Expression::ZeroValue(ty) => {
+            let (scalar, count) = match module.types[ty].inner {
+                naga::TypeInner::Scalar(scalar) => (scalar, 1),
+                naga::TypeInner::Vector { size, scalar } => (scalar, size as usize),
+                naga::TypeInner::Matrix {
+                    columns,
+                    rows,
+                    scalar,
+                } => (scalar, columns as usize * rows as usize),
+                ref other => bail!("Zero value of {:?}", other),
+            };
+            let zero = naga::Literal::zero(scalar).context("Zero value of an abstract type")?;
+            out.extend(std::iter::repeat_n(zero, count));
+        }
+        ref other => bail!("Unsupported constant expression {:?}", other),
+    }
+    Ok(())
+}
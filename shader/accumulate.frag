@@ -0,0 +1,15 @@
+#version 330 core
+
+out vec4 fragColor;
+
+uniform sampler2D texture0; // new sample
+uniform sampler2D texture1; // average of the previous samples
+
+uniform float sampleWeight = 1.0; // 1 / sample count
+
+void main() {
+  ivec2 pixel = ivec2(gl_FragCoord.xy);
+  vec3 current = texelFetch(texture0, pixel, 0).rgb;
+  vec3 average = texelFetch(texture1, pixel, 0).rgb;
+  fragColor = vec4(mix(average, current, sampleWeight), 1.0);
+}
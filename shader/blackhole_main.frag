@@ -7,34 +7,65 @@ const float NOISE_DOMAIN = 128.0;
 
 out vec4 fragColor;
 
+// Feature switches. The renderer compiles one program per combination it needs and
+// defines all of these (`blackhole_defines` in src/renderer.rs); the defaults only
+// apply when the file is compiled on its own.
+#ifndef GRAVITATIONAL_LENSING
+#define GRAVITATIONAL_LENSING 1
+#endif
+#ifndef RENDER_BLACK_HOLE
+#define RENDER_BLACK_HOLE 1
+#endif
+#ifndef ADISK_ENABLED
+#define ADISK_ENABLED 1
+#endif
+#ifndef ADISK_PARTICLE
+#define ADISK_PARTICLE 1
+#endif
+// Number of noise octaves sampled per disk step.
+#ifndef ADISK_NOISE_LOD
+#define ADISK_NOISE_LOD 5
+#endif
+
 uniform vec2 resolution; // viewport resolution in pixels
-uniform float mouseX;
-uniform float mouseY;
 
-uniform float time; // time elapsed in seconds
 uniform samplerCube galaxy;
 uniform sampler2D colorMap;
 uniform sampler3D noiseTex;
 
-uniform float frontView = 0.0;
-uniform float topView = 0.0;
-uniform float cameraRoll = 0.0;
-
-uniform float gravatationalLensing = 1.0;
-uniform float renderBlackHole = 1.0;
-uniform float mouseControl = 0.0;
-uniform float fovScale = 1.0;
-uniform float spin = 0.0;
-
-uniform float adiskEnabled = 1.0;
-uniform float adiskParticle = 1.0;
-uniform float adiskHeight = 0.2;
-uniform float adiskLit = 0.5;
-uniform float adiskDensityV = 1.0;
-uniform float adiskDensityH = 1.0;
-uniform float adiskNoiseScale = 1.0;
-uniform float adiskNoiseLOD = 5.0;
-uniform float adiskSpeed = 0.5;
+#include "common/blue_noise.glsl"
+
+// Filled from `BlackHoleParams` in src/uniform_blocks.rs; keep both in the same order.
+layout(std140) uniform BlackHoleParams {
+  vec3 cameraPos;
+  float fovScale;
+  mat3 view; // camera-to-world: columns are left, up, forward
+  // Temporal anti-aliasing: subpixel offset of this frame's rays (in pixels).
+  vec2 jitter;
+  float time; // time elapsed in seconds
+  float spin;
+
+  float adiskDensityV;
+  float adiskDensityH;
+  float adiskHeight;
+  float adiskLit;
+  float adiskNoiseScale;
+  float adiskSpeed;
+
+  // Whether the march start is pushed forward by a per-pixel, per-frame fraction of
+  // a step.
+  bool stepJitter;
+  float frameIndex;
+  // Output dither amplitude; one 8-bit step where the target is RGBA8 (wasm).
+  float ditherStrength;
+
+  // March quality. Idle-time accumulation shortens the steps (`stepScale` < 1, a
+  // smaller `minStep`) and allows more of them; the disk's per-step contribution is
+  // weighted by step length so brightness does not change with the step size.
+  float stepScale;
+  float minStep;
+  float maxSteps;
+};
 
 struct Ring {
   vec3 center;
@@ -67,11 +98,6 @@ float ringDistance(vec3 rayOrigin, vec3 rayDir, Ring ring) {
   }
 }
 
-vec3 panoramaColor(sampler2D tex, vec3 dir) {
-  vec2 uv = vec2(0.5 - atan(dir.z, dir.x) / PI * 0.5, 0.5 - asin(dir.y) / PI);
-  return texture(tex, uv).rgb;
-}
-
 vec3 accel(float h2, vec3 pos) {
   float r2 = dot(pos, pos);
   float r5 = pow(r2, 2.5);
@@ -173,18 +199,10 @@ void ringColor(vec3 rayOrigin, vec3 rayDir, Ring ring, inout float minDistance,
   }
 }
 
-mat3 lookAt(vec3 origin, vec3 target, float roll) {
-  vec3 rr = vec3(sin(roll), cos(roll), 0.0);
-  vec3 ww = normalize(target - origin);
-  vec3 uu = normalize(cross(ww, rr));
-  vec3 vv = normalize(cross(uu, ww));
-
-  return mat3(uu, vv, ww);
-}
-
 float sqrLength(vec3 a) { return dot(a, a); }
 
-void adiskColor(vec3 pos, vec3 viewDir, inout vec3 color, inout float alpha) {
+void adiskColor(vec3 pos, vec3 viewDir, float stepWeight, inout vec3 color,
+                inout float alpha) {
   float innerRadius = 2.6;
   float outerRadius = 12.0;
 
@@ -230,19 +248,17 @@ void adiskColor(vec3 pos, vec3 viewDir, inout vec3 color, inout float alpha) {
   vec3 viewDirNorm = viewLen > EPSILON ? viewDir / viewLen : vec3(0.0, 0.0, 1.0);
   float dopplerFactor = dot(discVelocity, viewDirNorm);
   float intensity = 1.0 - (dopplerFactor * 0.25);
-  density *= intensity;
+  density *= intensity * stepWeight;
 
   float redshift = clamp(1.0 - (1.0 / (radius + 0.5)), 0.0, 1.0);
   vec3 redshiftColor = vec3(1.0, redshift, redshift);
 
-  if (adiskParticle < 0.5) {
-    vec3 particleColor = vec3(0.0, 1.0, 0.0) * density * 0.02;
-    color += particleColor * redshiftColor;
-    return;
-  }
-
+#if !ADISK_PARTICLE
+  vec3 particleColor = vec3(0.0, 1.0, 0.0) * density * 0.02;
+  color += particleColor * redshiftColor;
+#else
   float noise = 1.0;
-  for (int i = 0; i < int(adiskNoiseLOD); i++) {
+  for (int i = 0; i < ADISK_NOISE_LOD; i++) {
     float scale = pow(float(i), 2.0);
     float noise_sample = 0.5;
     if (scale > 0.0) {
@@ -262,9 +278,10 @@ void adiskColor(vec3 pos, vec3 viewDir, inout vec3 color, inout float alpha) {
   dustColor *= redshiftColor;
 
   color += density * adiskLit * dustColor * alpha * abs(noise);
+#endif
 }
 
-vec3 traceColor(vec3 pos, vec3 dir) {
+vec3 traceColor(vec3 pos, vec3 dir, float stepOffset) {
   vec3 color = vec3(0.0);
   float alpha = 1.0;
 
@@ -273,45 +290,50 @@ vec3 traceColor(vec3 pos, vec3 dir) {
   vec3 h = cross(pos, dir);
   float h2 = dot(h, h);
 
-  for (int i = 0; i < 300; i++) {
+  pos += dir * max(minStep, length(pos) * 0.05 * stepScale) * stepOffset;
+
+  for (int i = 0; i < 1200; i++) {
+    if (float(i) >= maxSteps) {
+      break;
+    }
     float dist = length(pos);
-    float stepSize = max(0.02, dist * 0.05);
-
-    if (renderBlackHole > 0.5) {
-      // If gravatational lensing is applied
-      if (gravatationalLensing > 0.5) {
-        vec3 acc = accel(h2, pos);
-        if (spin > 0.0) {
-          float r3 = dist * dist * dist + EPSILON;
-          acc += cross(dir, spinAxis) * (spin / r3);
-        }
-        dir += acc * stepSize;
-      }
-
-      // Reach event horizon
-      float spinDot = dot(normalize(dir), spinAxis);
-      float hitRadius = 1.0 - (spin * 0.5 * spinDot);
-      hitRadius = max(0.5, hitRadius);
-      if (dist < hitRadius) {
-        return color;
-      }
-
-      float minDistance = INFINITY;
-
-      if (false) {
-        Ring ring;
-        ring.center = vec3(0.0, 0.05, 0.0);
-        ring.normal = vec3(0.0, 1.0, 0.0);
-        ring.innerRadius = 2.0;
-        ring.outerRadius = 6.0;
-        ring.rotateSpeed = 0.08;
-        ringColor(pos, dir, ring, minDistance, color);
-      } else {
-        if (adiskEnabled > 0.5) {
-          adiskColor(pos, dir, color, alpha);
-        }
-      }
+    float stepSize = max(minStep, dist * 0.05 * stepScale);
+    float stepWeight = stepSize / max(0.02, dist * 0.05);
+
+#if RENDER_BLACK_HOLE
+#if GRAVITATIONAL_LENSING
+    vec3 acc = accel(h2, pos);
+    if (spin > 0.0) {
+      float r3 = dist * dist * dist + EPSILON;
+      acc += cross(dir, spinAxis) * (spin / r3);
+    }
+    dir += acc * stepSize;
+#endif
+
+    // Reach event horizon
+    float spinDot = dot(normalize(dir), spinAxis);
+    float hitRadius = 1.0 - (spin * 0.5 * spinDot);
+    hitRadius = max(0.5, hitRadius);
+    if (dist < hitRadius) {
+      return color;
+    }
+
+    float minDistance = INFINITY;
+
+    if (false) {
+      Ring ring;
+      ring.center = vec3(0.0, 0.05, 0.0);
+      ring.normal = vec3(0.0, 1.0, 0.0);
+      ring.innerRadius = 2.0;
+      ring.outerRadius = 6.0;
+      ring.rotateSpeed = 0.08;
+      ringColor(pos, dir, ring, minDistance, color);
+    } else {
+#if ADISK_ENABLED
+      adiskColor(pos, dir, stepWeight, color, alpha);
+#endif
     }
+#endif
 
     pos += dir * stepSize;
   }
@@ -328,32 +350,17 @@ vec3 traceColor(vec3 pos, vec3 dir) {
 }
 
 void main() {
-  mat3 view;
-
-  vec3 cameraPos;
-  if (mouseControl > 0.5) {
-    vec2 mouse = clamp(vec2(mouseX, mouseY) / resolution.xy, 0.0, 1.0) - 0.5;
-    cameraPos = vec3(-cos(mouse.x * 10.0) * 15.0, mouse.y * 30.0,
-                     sin(mouse.x * 10.0) * 15.0);
-
-  } else if (frontView > 0.5) {
-    cameraPos = vec3(10.0, 1.0, 10.0);
-  } else if (topView > 0.5) {
-    cameraPos = vec3(15.0, 15.0, 0.0);
-  } else {
-    cameraPos = vec3(-cos(time * 0.1) * 15.0, sin(time * 0.1) * 15.0,
-                     sin(time * 0.1) * 15.0);
-  }
-
-  vec3 target = vec3(0.0, 0.0, 0.0);
-  view = lookAt(cameraPos, target, radians(cameraRoll));
-
-  vec2 uv = gl_FragCoord.xy / resolution.xy - vec2(0.5);
+  vec2 uv = (gl_FragCoord.xy + jitter) / resolution.xy - vec2(0.5);
   uv.x *= resolution.x / resolution.y;
 
   vec3 dir = normalize(vec3(-uv.x * fovScale, uv.y * fovScale, 1.0));
   vec3 pos = cameraPos;
   dir = view * dir;
 
-  fragColor.rgb = traceColor(pos, dir);
+  // Spreads the march start so neighbouring pixels sample the disk at different
+  // depths and TAA or accumulation averages the step banding away.
+  float stepOffset = stepJitter ? blueNoiseAt(gl_FragCoord.xy, ivec2(0), frameIndex) : 0.0;
+  vec3 color = traceColor(pos, dir, stepOffset);
+  float dither = blueNoiseAt(gl_FragCoord.xy, ivec2(32, 17), frameIndex) - 0.5;
+  fragColor.rgb = max(color + dither * ditherStrength, vec3(0.0));
 }
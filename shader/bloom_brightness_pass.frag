@@ -6,8 +6,9 @@ out vec4 fragColor;
 
 uniform sampler2D texture0;
 
+#include "common/color.glsl"
+
 const float brightPassThreshold = 1.0;
-const vec3 luminanceVector = vec3(0.2125, 0.7154, 0.0721);
 
 void main() {
   vec4 c = texture(texture0, uv);
@@ -0,0 +1,11 @@
+// Tiled blue-noise threshold map from src/blue_noise.rs.
+
+uniform sampler2D blueNoise;
+
+// Blue noise at `pixel`, advanced by the golden ratio each `phase` so the values of
+// one pixel are also well spread over time. `tileOffset` decorrelates separate uses.
+float blueNoiseAt(vec2 pixel, ivec2 tileOffset, float phase) {
+  ivec2 size = textureSize(blueNoise, 0);
+  float n = texelFetch(blueNoise, (ivec2(pixel) + tileOffset) % size, 0).r;
+  return fract(n + 0.61803398875 * phase);
+}
@@ -0,0 +1,14 @@
+// Filled from `PostParams` in src/uniform_blocks.rs; keep both in the same order.
+layout(std140) uniform PostParams {
+  bool tonemappingEnabled;
+  float gamma;
+  float time;
+  float chromaStrength;
+  float grainStrength;
+  float saturation;
+  float outputDither; // one step of the 8-bit output
+  float tone;
+  float bloomStrength;
+  float flareStrength;
+  int filterMode; // 0 bilinear, 1 bicubic, 2 edge-aware
+};
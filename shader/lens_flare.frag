@@ -7,7 +7,7 @@ out vec4 fragColor;
 uniform sampler2D texture0;
 uniform vec2 resolution;
 
-const vec3 luminanceVector = vec3(0.2125, 0.7154, 0.0721);
+#include "common/color.glsl"
 
 void main() {
   float texel = 1.0 / max(resolution.x, 1.0);
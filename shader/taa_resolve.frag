@@ -0,0 +1,137 @@
+#version 330 core
+
+in vec2 uv;
+
+out vec4 fragColor;
+
+const float INFINITY = 1000000.0;
+
+uniform vec2 resolution;
+
+uniform sampler2D texture0; // this frame's jittered ray march
+uniform sampler2D texture1; // last frame's resolved output
+
+uniform vec3 cameraPos;
+uniform mat3 view;
+uniform float fovScale = 1.0;
+uniform vec3 prevCameraPos;
+uniform mat3 prevView;
+uniform float prevFovScale = 1.0;
+
+uniform bool historyValid = false;
+uniform float blendFactor = 0.1; // weight of the new frame
+
+vec3 toYCoCg(vec3 c) {
+  return vec3(dot(c, vec3(0.25, 0.5, 0.25)), dot(c, vec3(0.5, 0.0, -0.5)),
+              dot(c, vec3(-0.25, 0.5, -0.25)));
+}
+
+vec3 fromYCoCg(vec3 c) {
+  return vec3(c.x + c.y - c.z, c.x + c.z, c.x - c.y - c.z);
+}
+
+// Karis weighting keeps a few very bright samples (the photon ring, disk hot
+// spots) from dominating the blend and flickering.
+float hdrWeight(vec3 c) {
+  return 1.0 / (1.0 + dot(c, vec3(0.2126, 0.7152, 0.0722)));
+}
+
+// Catmull-Rom history fetch in five bilinear taps. Plain bilinear would blur the
+// history a little more every frame the camera moves.
+vec3 sampleHistory(vec2 p) {
+  vec2 size = vec2(textureSize(texture1, 0));
+  vec2 pos = p * size;
+  vec2 center = floor(pos - 0.5) + 0.5;
+  vec2 f = pos - center;
+  vec2 w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+  vec2 w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+  vec2 w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+  vec2 w3 = f * f * (-0.5 + 0.5 * f);
+  vec2 w12 = w1 + w2;
+  vec2 tc0 = (center - 1.0) / size;
+  vec2 tc12 = (center + w2 / w12) / size;
+  vec2 tc3 = (center + 2.0) / size;
+
+  vec3 color = texture(texture1, vec2(tc12.x, tc0.y)).rgb * (w12.x * w0.y) +
+               texture(texture1, vec2(tc0.x, tc12.y)).rgb * (w0.x * w12.y) +
+               texture(texture1, tc12).rgb * (w12.x * w12.y) +
+               texture(texture1, vec2(tc3.x, tc12.y)).rgb * (w3.x * w12.y) +
+               texture(texture1, vec2(tc12.x, tc3.y)).rgb * (w12.x * w3.y);
+  float weight = w12.x * w0.y + w0.x * w12.y + w12.x * w12.y + w3.x * w12.y + w12.x * w3.y;
+  return max(color / weight, vec3(0.0));
+}
+
+vec2 toScreen(vec3 local, float aspect) {
+  vec2 p = vec2(-local.x, local.y) / (local.z * prevFovScale);
+  return p / vec2(aspect, 1.0) + 0.5;
+}
+
+bool onScreen(vec2 p) {
+  return all(greaterThanEqual(p, vec2(0.0))) && all(lessThanEqual(p, vec2(1.0)));
+}
+
+// Where this pixel's ray pointed in the previous frame's image. There is no depth
+// buffer, so two guesses are made: the ray ends at the distance of the hole (right
+// for the disk) or at infinity (right for the sky). `main` keeps whichever history
+// sample agrees better with the current frame.
+void reproject(vec2 screenUv, out vec2 nearUv, out vec2 farUv) {
+  float aspect = resolution.x / resolution.y;
+  vec2 p = (screenUv - 0.5) * vec2(aspect, 1.0);
+  vec3 dir = view * normalize(vec3(-p.x * fovScale, p.y * fovScale, 1.0));
+  mat3 toPrev = transpose(prevView);
+
+  vec3 nearPoint = toPrev * (cameraPos + dir * length(cameraPos) - prevCameraPos);
+  nearUv = nearPoint.z > 0.0 ? toScreen(nearPoint, aspect) : vec2(-1.0);
+  vec3 farDir = toPrev * dir;
+  farUv = farDir.z > 0.0 ? toScreen(farDir, aspect) : vec2(-1.0);
+}
+
+void main() {
+  ivec2 pixel = ivec2(gl_FragCoord.xy);
+  ivec2 maxPixel = textureSize(texture0, 0) - 1;
+  vec3 current = texelFetch(texture0, pixel, 0).rgb;
+
+  vec2 nearUv;
+  vec2 farUv;
+  reproject(uv, nearUv, farUv);
+  if (!historyValid || !(onScreen(nearUv) || onScreen(farUv))) {
+    fragColor = vec4(current, 1.0);
+    return;
+  }
+
+  // Mean and variance of the 3x3 neighbourhood; history outside that colour box is
+  // stale (disocclusion, disk motion) and gets pulled back in.
+  vec3 m1 = vec3(0.0);
+  vec3 m2 = vec3(0.0);
+  for (int y = -1; y <= 1; y++) {
+    for (int x = -1; x <= 1; x++) {
+      ivec2 p = clamp(pixel + ivec2(x, y), ivec2(0), maxPixel);
+      vec3 c = toYCoCg(texelFetch(texture0, p, 0).rgb);
+      m1 += c;
+      m2 += c * c;
+    }
+  }
+  m1 /= 9.0;
+  vec3 sigma = sqrt(max(m2 / 9.0 - m1 * m1, vec3(0.0)));
+  vec3 boxMin = m1 - 1.25 * sigma;
+  vec3 boxMax = m1 + 1.25 * sigma;
+
+  vec3 nearHistory = toYCoCg(sampleHistory(nearUv));
+  vec3 farHistory = toYCoCg(sampleHistory(farUv));
+  float nearError = onScreen(nearUv) ? length(nearHistory - m1) : INFINITY;
+  float farError = onScreen(farUv) ? length(farHistory - m1) : INFINITY;
+  bool useNear = nearError <= farError;
+  vec3 history = useNear ? nearHistory : farHistory;
+  history = fromYCoCg(clamp(history, boxMin, boxMax));
+  history = max(history, vec3(0.0));
+
+  // Trust the new frame more while the image is moving fast; lensing warps the sky
+  // in ways neither reprojection guess follows.
+  float motion = length(((useNear ? nearUv : farUv) - uv) * resolution);
+  float blend = mix(blendFactor, 0.5, clamp(motion / 16.0, 0.0, 1.0));
+
+  float wCurrent = blend * hdrWeight(current);
+  float wHistory = (1.0 - blend) * hdrWeight(history);
+  vec3 color = (current * wCurrent + history * wHistory) / (wCurrent + wHistory);
+  fragColor = vec4(color, 1.0);
+}
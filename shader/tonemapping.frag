@@ -4,37 +4,11 @@ in vec2 uv;
 
 out vec4 fragColor;
 
-uniform float gamma = 2.2;
-uniform float tonemappingEnabled;
-uniform float time;
-uniform float chromaStrength = 0.005;
-uniform float grainStrength = 0.02;
-uniform float saturation = 1.0;
 uniform sampler2D texture0;
-uniform vec2 resolution;
 
-const vec3 luminanceVector = vec3(0.2125, 0.7154, 0.0721);
-
-///----
-/// Narkowicz 2015, "ACES Filmic Tone Mapping Curve"
-vec3 aces(vec3 x) {
-  const float a = 2.51;
-  const float b = 0.03;
-  const float c = 2.43;
-  const float d = 0.59;
-  const float e = 0.14;
-  return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
-}
-
-float aces(float x) {
-  const float a = 2.51;
-  const float b = 0.03;
-  const float c = 2.43;
-  const float d = 0.59;
-  const float e = 0.14;
-  return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
-}
-///----
+#include "common/post_params.glsl"
+#include "common/color.glsl"
+#include "common/blue_noise.glsl"
 
 void main() {
   vec2 center = vec2(0.5);
@@ -48,7 +22,7 @@ void main() {
   color.g = texture(texture0, uv).g;
   color.b = texture(texture0, uv + offset).b;
 
-  if (tonemappingEnabled > 0.5) {
+  if (tonemappingEnabled) {
     // ACES filmic tone mapping
     color = aces(color);
 
@@ -59,9 +33,16 @@ void main() {
   float luma = dot(color, luminanceVector);
   color = mix(vec3(luma), color, saturation);
 
-  vec2 noiseUv = uv * resolution + vec2(time * 60.0, time * 37.0);
-  float grain = fract(sin(dot(noiseUv, vec2(12.9898, 78.233))) * 43758.5453);
+  // Grain moves at 60 Hz of scene time, so it holds still while paused.
+  float phase = floor(time * 60.0);
+  vec2 pixel = gl_FragCoord.xy;
+  float grain = blueNoiseAt(pixel, ivec2(0), phase);
   color += (grain - 0.5) * grainStrength;
 
+  // Triangular dither of about one output step hides banding in dark gradients.
+  float dither =
+      blueNoiseAt(pixel, ivec2(32, 17), phase) + blueNoiseAt(pixel, ivec2(11, 45), phase) - 1.0;
+  color += dither * outputDither;
+
   fragColor = vec4(clamp(color, 0.0, 1.0), 1.0);
 }
@@ -0,0 +1,79 @@
+#version 330 core
+
+in vec2 uv;
+
+out vec4 fragColor;
+
+uniform sampler2D texture0;
+
+#include "common/post_params.glsl"
+
+vec3 fetch(ivec2 p) {
+  ivec2 size = textureSize(texture0, 0);
+  return texelFetch(texture0, clamp(p, ivec2(0), size - 1), 0).rgb;
+}
+
+// Catmull-Rom weights for the four taps around fractional position t.
+vec4 catmullRom(float t) {
+  float t2 = t * t;
+  float t3 = t2 * t;
+  return vec4(-0.5 * t3 + t2 - 0.5 * t,
+              1.5 * t3 - 2.5 * t2 + 1.0,
+              -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+              0.5 * t3 - 0.5 * t2);
+}
+
+vec3 bicubic(vec2 pos) {
+  vec2 base = floor(pos - 0.5);
+  vec2 f = pos - 0.5 - base;
+  vec4 wx = catmullRom(f.x);
+  vec4 wy = catmullRom(f.y);
+  vec3 color = vec3(0.0);
+  for (int y = 0; y < 4; y++) {
+    vec3 row = vec3(0.0);
+    for (int x = 0; x < 4; x++) {
+      row += fetch(ivec2(base) + ivec2(x - 1, y - 1)) * wx[x];
+    }
+    color += row * wy[y];
+  }
+  // The negative lobes can ring below zero next to bright edges.
+  return max(color, vec3(0.0));
+}
+
+float logLuminance(vec3 c) {
+  return log2(1.0 + dot(c, vec3(0.2126, 0.7152, 0.0722)));
+}
+
+// Bilinear over the 2x2 footprint, but taps whose brightness differs strongly from
+// the nearest texel lose their weight, so the photon ring and disk edge stay crisp
+// instead of smearing across the upscale.
+vec3 edgeAware(vec2 pos) {
+  vec2 base = floor(pos - 0.5);
+  vec2 f = pos - 0.5 - base;
+  ivec2 p = ivec2(base);
+  vec3 c00 = fetch(p);
+  vec3 c10 = fetch(p + ivec2(1, 0));
+  vec3 c01 = fetch(p + ivec2(0, 1));
+  vec3 c11 = fetch(p + ivec2(1, 1));
+  vec4 w = vec4((1.0 - f.x) * (1.0 - f.y), f.x * (1.0 - f.y), (1.0 - f.x) * f.y, f.x * f.y);
+
+  vec4 lum = vec4(logLuminance(c00), logLuminance(c10), logLuminance(c01), logLuminance(c11));
+  float nearest = lum[int(f.x >= 0.5) + 2 * int(f.y >= 0.5)];
+  vec4 diff = (lum - nearest) / 0.5;
+  w *= exp(-diff * diff);
+
+  return (c00 * w.x + c10 * w.y + c01 * w.z + c11 * w.w) / max(dot(w, vec4(1.0)), 1e-5);
+}
+
+void main() {
+  vec2 pos = uv * vec2(textureSize(texture0, 0));
+  vec3 color;
+  if (filterMode == 2) {
+    color = edgeAware(pos);
+  } else if (filterMode == 1) {
+    color = bicubic(pos);
+  } else {
+    color = texture(texture0, uv).rgb;
+  }
+  fragColor = vec4(color, 1.0);
+}
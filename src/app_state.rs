@@ -1,11 +1,13 @@
+use serde::{Deserialize, Serialize};
 
+use crate::camera::Camera;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppState {
     // Toggles
     pub gravitational_lensing: bool,
     pub render_black_hole: bool,
-    pub mouse_control: bool,
-    pub front_view: bool,
-    pub top_view: bool,
     pub adisk_enabled: bool,
     pub adisk_particle: bool,
     pub tonemapping_enabled: bool,
@@ -27,9 +29,72 @@ pub struct AppState {
     pub gamma: f32,
     pub spin: f32,
 
-    // Mouse state
-    pub mouse_x: f32,
-    pub mouse_y: f32,
+    pub camera: Camera,
+    pub quality: QualitySettings,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpscaleFilter {
+    Bilinear,
+    #[default]
+    Bicubic,
+    EdgeAware,
+}
+
+impl UpscaleFilter {
+    pub const ALL: [UpscaleFilter; 3] = [Self::Bilinear, Self::Bicubic, Self::EdgeAware];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Bilinear => "Bilinear",
+            Self::Bicubic => "Bicubic",
+            Self::EdgeAware => "Edge-Aware",
+        }
+    }
+
+    // `filterMode` in upscale.frag.
+    pub fn shader_mode(self) -> i32 {
+        match self {
+            Self::Bilinear => 0,
+            Self::Bicubic => 1,
+            Self::EdgeAware => 2,
+        }
+    }
+}
+
+// Performance trade-offs rather than part of the look, so presets leave them alone.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualitySettings {
+    // Fraction of the window resolution the black hole pass is ray-marched at,
+    // 0.25 to 1. Bloom, tonemapping and the GUI always run at full resolution.
+    pub render_scale: f32,
+    pub upscale_filter: UpscaleFilter,
+    // Let the dynamic resolution controller drive `render_scale` to keep frames
+    // within `target_frame_ms`.
+    pub dynamic_resolution: bool,
+    pub target_frame_ms: f32,
+    // Jitter the ray march every frame and blend it with the reprojected history.
+    pub taa: bool,
+    // Once nothing has changed for a frame, average jittered, finer-stepped samples
+    // until `progressive_samples` is reached, then stop redrawing.
+    pub progressive: bool,
+    pub progressive_samples: u32,
+}
+
+impl Default for QualitySettings {
+    fn default() -> Self {
+        Self {
+            render_scale: 1.0,
+            upscale_filter: UpscaleFilter::default(),
+            dynamic_resolution: false,
+            target_frame_ms: 16.6,
+            taa: true,
+            progressive: true,
+            progressive_samples: 256,
+        }
+    }
 }
 
 impl Default for AppState {
@@ -37,9 +102,6 @@ impl Default for AppState {
         Self {
             gravitational_lensing: true,
             render_black_hole: true,
-            mouse_control: false,
-            front_view: true,
-            top_view: false,
             adisk_enabled: true,
             adisk_particle: true,
             tonemapping_enabled: true,
@@ -60,8 +122,41 @@ impl Default for AppState {
             gamma: 2.0,
             spin: 0.20,
 
-            mouse_x: 400.0,
-            mouse_y: 300.0,
+            camera: Camera::default(),
+            quality: QualitySettings::default(),
         }
     }
 }
+
+impl AppState {
+    // The continuous settings, in GUI order. The camera pose is interpolated
+    // separately.
+    pub fn numeric_fields_mut(&mut self) -> [(&'static str, &mut f32); 15] {
+        [
+            ("camera_roll", &mut self.camera_roll),
+            ("bloom_strength", &mut self.bloom_strength),
+            ("gamma", &mut self.gamma),
+            ("spin", &mut self.spin),
+            ("flare_strength", &mut self.flare_strength),
+            ("chroma_aberration", &mut self.chroma_aberration),
+            ("grain_strength", &mut self.grain_strength),
+            ("saturation", &mut self.saturation),
+            ("adisk_density_v", &mut self.adisk_density_v),
+            ("adisk_density_h", &mut self.adisk_density_h),
+            ("adisk_height", &mut self.adisk_height),
+            ("adisk_lit", &mut self.adisk_lit),
+            ("adisk_speed", &mut self.adisk_speed),
+            ("adisk_noise_scale", &mut self.adisk_noise_scale),
+            ("adisk_noise_lod", &mut self.adisk_noise_lod),
+        ]
+    }
+
+    // Switches the toggles to those of `other`.
+    pub fn copy_toggles(&mut self, other: &AppState) {
+        self.gravitational_lensing = other.gravitational_lensing;
+        self.render_black_hole = other.render_black_hole;
+        self.adisk_enabled = other.adisk_enabled;
+        self.adisk_particle = other.adisk_particle;
+        self.tonemapping_enabled = other.tonemapping_enabled;
+    }
+}
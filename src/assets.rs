@@ -0,0 +1,138 @@
+// Read-only files the renderer needs: the `shader/` sources and the `assets/`
+// textures, addressed by their path relative to the project root
+// (`assets/color_map.png`, `shader/common/color.glsl`).
+//
+// The wasm build always reads them from the binary. Native builds read them from disk,
+// from the directory given with `--assets` or else the nearest directory above the
+// executable that has both `assets/` and `shader/`. With the `embed-assets` feature a
+// native build carries them too and only reads from disk when `--assets` is given, so
+// the binary runs on its own.
+
+use anyhow::Context;
+use std::borrow::Cow;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::OnceLock;
+
+#[cfg(not(target_arch = "wasm32"))]
+enum Source {
+    Dir(PathBuf),
+    #[cfg(feature = "embed-assets")]
+    Embedded,
+}
+
+// Decided on first use, or by `set_dir` before that.
+#[cfg(not(target_arch = "wasm32"))]
+static SOURCE: OnceLock<Source> = OnceLock::new();
+
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "embed-assets")))]
+fn find_asset_root() -> anyhow::Result<PathBuf> {
+    let exe_path = std::env::current_exe().context("Failed to resolve executable path")?;
+    let mut dir = exe_path
+        .parent()
+        .context("Executable has no parent directory")?
+        .to_path_buf();
+
+    for _ in 0..5 {
+        if is_asset_root(&dir) {
+            return Ok(dir);
+        }
+        match dir.parent() {
+            Some(parent) => dir = parent.to_path_buf(),
+            None => break,
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Unable to locate `assets/` and `shader/`. Place them next to the binary, run from \
+         the project root, pass `--assets <dir>`, or build with `--features embed-assets`."
+    ))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn is_asset_root(dir: &Path) -> bool {
+    dir.join("assets").is_dir() && dir.join("shader").is_dir()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn source() -> anyhow::Result<&'static Source> {
+    if let Some(source) = SOURCE.get() {
+        return Ok(source);
+    }
+    #[cfg(feature = "embed-assets")]
+    let source = Source::Embedded;
+    #[cfg(not(feature = "embed-assets"))]
+    let source = Source::Dir(find_asset_root()?);
+    Ok(SOURCE.get_or_init(|| source))
+}
+
+// Reads everything from `dir` (which holds `assets/` and `shader/`) instead. Has to be
+// called before the first read.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn set_dir(dir: PathBuf) -> anyhow::Result<()> {
+    if !is_asset_root(&dir) {
+        anyhow::bail!("{} does not contain `assets/` and `shader/`", dir.display());
+    }
+    SOURCE
+        .set(Source::Dir(dir))
+        .map_err(|_| anyhow::anyhow!("The asset directory was already chosen"))
+}
+
+// The directory files are read from, or `None` when they come from the binary.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn dir() -> anyhow::Result<Option<&'static Path>> {
+    Ok(match source()? {
+        Source::Dir(dir) => Some(dir),
+        #[cfg(feature = "embed-assets")]
+        Source::Embedded => None,
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(path: &str) -> anyhow::Result<Cow<'static, [u8]>> {
+    match source()? {
+        Source::Dir(dir) => {
+            let file = dir.join(path);
+            let bytes = std::fs::read(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            Ok(Cow::Owned(bytes))
+        }
+        #[cfg(feature = "embed-assets")]
+        Source::Embedded => read_embedded(path),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read(path: &str) -> anyhow::Result<Cow<'static, [u8]>> {
+    read_embedded(path)
+}
+
+pub fn read_to_string(path: &str) -> anyhow::Result<Cow<'static, str>> {
+    Ok(match read(path)? {
+        Cow::Borrowed(bytes) => Cow::Borrowed(
+            std::str::from_utf8(bytes).with_context(|| format!("{} is not UTF-8", path))?,
+        ),
+        Cow::Owned(bytes) => Cow::Owned(
+            String::from_utf8(bytes).with_context(|| format!("{} is not UTF-8", path))?,
+        ),
+    })
+}
+
+#[cfg(any(target_arch = "wasm32", feature = "embed-assets"))]
+fn read_embedded(path: &str) -> anyhow::Result<Cow<'static, [u8]>> {
+    embedded(path)
+        .map(Cow::Borrowed)
+        .with_context(|| format!("Unknown embedded asset: {}", path))
+}
+
+// Every file of `assets/` and `shader/`, sorted by path. Generated by build.rs, so new
+// files are picked up by rebuilding.
+#[cfg(any(target_arch = "wasm32", feature = "embed-assets"))]
+include!(concat!(env!("OUT_DIR"), "/asset_manifest.rs"));
+
+#[cfg(any(target_arch = "wasm32", feature = "embed-assets"))]
+fn embedded(path: &str) -> Option<&'static [u8]> {
+    let index = MANIFEST.binary_search_by_key(&path, |&(key, _)| key).ok()?;
+    Some(MANIFEST[index].1)
+}
@@ -0,0 +1,7 @@
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> anyhow::Result<()> {
+    blackhole_web::offline::run(std::env::args().skip(1))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
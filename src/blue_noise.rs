@@ -0,0 +1,142 @@
+// Tileable blue-noise threshold map built with Ulichney's void-and-cluster method.
+//
+// Every pixel gets a unique rank; ranks are spread so that any threshold of the map
+// gives evenly spaced points with no low-frequency clumps. Used as a dither pattern
+// it leaves only fine, high-frequency noise, which TAA and accumulation average away
+// much faster than white noise.
+
+pub const BLUE_NOISE_SIZE: usize = 64;
+
+// Width of the Gaussian that measures how clustered a pixel's neighbourhood is.
+const SIGMA: f32 = 1.5;
+// Fraction of pixels in the initial random pattern.
+const INITIAL_DENSITY: f32 = 0.1;
+const SEED: u32 = 0x9e37_79b9;
+
+// Sum of Gaussian weights from every set pixel, with wrap-around distances so the
+// result tiles.
+#[derive(Clone)]
+struct Energy {
+    size: usize,
+    kernel: Vec<f32>,
+    values: Vec<f32>,
+}
+
+impl Energy {
+    fn new(size: usize) -> Self {
+        let mut kernel = Vec::with_capacity(size * size);
+        for dy in 0..size {
+            for dx in 0..size {
+                let wx = dx.min(size - dx) as f32;
+                let wy = dy.min(size - dy) as f32;
+                kernel.push((-(wx * wx + wy * wy) / (2.0 * SIGMA * SIGMA)).exp());
+            }
+        }
+        Self {
+            size,
+            kernel,
+            values: vec![0.0; size * size],
+        }
+    }
+
+    fn add(&mut self, index: usize, sign: f32) {
+        let size = self.size;
+        let (px, py) = (index % size, index / size);
+        for y in 0..size {
+            let dy = (y + size - py) % size;
+            for x in 0..size {
+                let dx = (x + size - px) % size;
+                self.values[y * size + x] += sign * self.kernel[dy * size + dx];
+            }
+        }
+    }
+
+    // Set pixel with the most set neighbours.
+    fn tightest_cluster(&self, pattern: &[bool]) -> usize {
+        self.extreme(pattern, true, |a, b| a > b)
+    }
+
+    // Empty pixel farthest from every set one.
+    fn largest_void(&self, pattern: &[bool]) -> usize {
+        self.extreme(pattern, false, |a, b| a < b)
+    }
+
+    fn extreme(&self, pattern: &[bool], set: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best = None;
+        for (index, &value) in self.values.iter().enumerate() {
+            if pattern[index] != set {
+                continue;
+            }
+            if best.is_none_or(|(_, best_value)| better(value, best_value)) {
+                best = Some((index, value));
+            }
+        }
+        best.map(|(index, _)| index).unwrap_or(0)
+    }
+}
+
+// Small deterministic generator so the map is identical on every run and platform.
+fn xorshift(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state
+}
+
+// Returns BLUE_NOISE_SIZE² bytes, row by row. Each value 0..=255 occurs equally often.
+pub fn generate_blue_noise() -> Vec<u8> {
+    let size = BLUE_NOISE_SIZE;
+    let count = size * size;
+
+    // Random starting pattern.
+    let mut pattern = vec![false; count];
+    let mut energy = Energy::new(size);
+    let mut rng = SEED;
+    let mut ones = 0;
+    let target = ((count as f32 * INITIAL_DENSITY) as usize).max(1);
+    while ones < target {
+        let index = xorshift(&mut rng) as usize % count;
+        if !pattern[index] {
+            pattern[index] = true;
+            energy.add(index, 1.0);
+            ones += 1;
+        }
+    }
+
+    // Move points out of clusters into voids until the pattern is evenly spread.
+    loop {
+        let cluster = energy.tightest_cluster(&pattern);
+        pattern[cluster] = false;
+        energy.add(cluster, -1.0);
+        let void = energy.largest_void(&pattern);
+        pattern[void] = true;
+        energy.add(void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; count];
+
+    // Ranks below the starting pattern: take points out, tightest cluster first.
+    let mut removing = pattern.clone();
+    let mut removing_energy = energy.clone();
+    for r in (0..ones).rev() {
+        let cluster = removing_energy.tightest_cluster(&removing);
+        removing[cluster] = false;
+        removing_energy.add(cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Ranks above it: fill the largest void first. Past half full this is the same
+    // as Ulichney's inverted phase, since the densest cluster of empty pixels is where
+    // the set pixels' energy is lowest.
+    for r in ones..count {
+        let void = energy.largest_void(&pattern);
+        pattern[void] = true;
+        energy.add(void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.iter().map(|&r| (r * 256 / count) as u8).collect()
+}
@@ -0,0 +1,322 @@
+// CPU-side camera. The shader only receives `cameraPos`, `view` (camera-to-world,
+// columns left/up/forward like the shader's old `lookAt`) and `fovScale`; where the
+// camera sits and how input moves it is decided here.
+//
+// A pose is a target point plus yaw/pitch/distance: the camera sits `distance`
+// behind the target along its forward vector. Orbit mode keeps the target on the
+// black hole, inspect mode orbits (and pans) an arbitrary target, and free-fly
+// moves target and camera together. Interpolating poses therefore swings around
+// the hole instead of cutting through it.
+
+use glam::{Mat3, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+// Vertical FOV that reproduces the shader's original fovScale of 1.0.
+pub const DEFAULT_FOV: f32 = 53.130104;
+const MIN_DISTANCE: f32 = 1.5;
+const MAX_DISTANCE: f32 = 500.0;
+const MAX_PITCH: f32 = 89.5;
+const DRAG_DEGREES_PER_PIXEL: f32 = 0.25;
+const SCROLL_ZOOM: f32 = 0.9;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraMode {
+    #[default]
+    Orbit,
+    FreeFly,
+    Inspect,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 3] = [Self::Orbit, Self::FreeFly, Self::Inspect];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Orbit => "Orbit",
+            Self::FreeFly => "Free Fly",
+            Self::Inspect => "Inspect",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub target: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub fov: f32,
+}
+
+impl Default for CameraPose {
+    fn default() -> Self {
+        Self::looking_at(Vec3::new(10.0, 1.0, 10.0), Vec3::ZERO)
+    }
+}
+
+impl CameraPose {
+    pub fn looking_at(position: Vec3, target: Vec3) -> Self {
+        let offset = target - position;
+        let distance = offset.length().max(MIN_DISTANCE);
+        let forward = offset / distance;
+        Self {
+            target: target.to_array(),
+            yaw: forward.x.atan2(forward.z).to_degrees(),
+            pitch: forward.y.clamp(-1.0, 1.0).asin().to_degrees(),
+            distance,
+            fov: DEFAULT_FOV,
+        }
+    }
+
+    pub fn target(&self) -> Vec3 {
+        Vec3::from_array(self.target)
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        Vec3::new(
+            pitch.cos() * yaw.sin(),
+            pitch.sin(),
+            pitch.cos() * yaw.cos(),
+        )
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.target() - self.forward() * self.distance
+    }
+
+    // Camera-to-world rotation with columns (left, up, forward), rolled about the
+    // forward axis. Matches the basis the shader's `lookAt` produced.
+    pub fn view_matrix(&self, roll_degrees: f32) -> Mat3 {
+        let ww = self.forward();
+        let up = if ww.y.abs() > 0.999 { Vec3::Z } else { Vec3::Y };
+        let uu = ww.cross(up).normalize();
+        let vv = uu.cross(ww).normalize();
+        let (sin, cos) = roll_degrees.to_radians().sin_cos();
+        Mat3::from_cols(uu * cos + vv * sin, vv * cos - uu * sin, ww)
+    }
+
+    // `fovScale` in the shader: the image plane half-height at unit distance, times two.
+    pub fn fov_scale(&self) -> f32 {
+        2.0 * (self.fov.to_radians() * 0.5).tan()
+    }
+
+    // Flat view used for interpolation: target, yaw, pitch, distance, FOV.
+    pub fn components(&self) -> [f32; 7] {
+        let [x, y, z] = self.target;
+        [x, y, z, self.yaw, self.pitch, self.distance, self.fov]
+    }
+
+    pub fn from_components(c: [f32; 7]) -> Self {
+        Self {
+            target: [c[0], c[1], c[2]],
+            yaw: c[3],
+            pitch: c[4],
+            distance: c[5],
+            fov: c[6],
+        }
+    }
+
+    fn clamp(&mut self) {
+        self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        self.distance = self.distance.clamp(MIN_DISTANCE, MAX_DISTANCE);
+        self.fov = self.fov.clamp(5.0, 150.0);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub pose: CameraPose,
+}
+
+// Fixed viewpoints that always exist. "Front" and "Top" are the shader's old
+// `frontView`/`topView` positions.
+pub fn builtin_bookmarks() -> [(&'static str, CameraPose); 4] {
+    [
+        (
+            "Front",
+            CameraPose::looking_at(Vec3::new(10.0, 1.0, 10.0), Vec3::ZERO),
+        ),
+        (
+            "Top",
+            CameraPose::looking_at(Vec3::new(15.0, 15.0, 0.0), Vec3::ZERO),
+        ),
+        (
+            "Edge-on",
+            CameraPose::looking_at(Vec3::new(0.0, 0.15, 20.0), Vec3::ZERO),
+        ),
+        (
+            "Face-on",
+            CameraPose::looking_at(Vec3::new(0.0, 20.0, 0.01), Vec3::ZERO),
+        ),
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub mode: CameraMode,
+    pub pose: CameraPose,
+    pub auto_orbit: f32, // degrees of yaw per second in orbit mode
+    pub fly_speed: f32,  // units per second in free-fly mode
+    pub bookmarks: Vec<Bookmark>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::Orbit,
+            pose: CameraPose::default(),
+            auto_orbit: 0.0,
+            fly_speed: 5.0,
+            bookmarks: Vec::new(),
+        }
+    }
+}
+
+impl Camera {
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            let position = self.pose.position();
+            let fov = self.pose.fov;
+            self.pose = CameraPose {
+                fov,
+                ..CameraPose::looking_at(position, Vec3::ZERO)
+            };
+        }
+        self.mode = mode;
+    }
+
+    // Rotates the view. Orbit/inspect swing the camera around the target; free-fly
+    // turns the camera in place.
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        let position = self.pose.position();
+        self.pose.yaw += yaw;
+        self.pose.pitch += pitch;
+        self.pose.clamp();
+        if self.mode == CameraMode::FreeFly {
+            self.pose.target = (position + self.pose.forward() * self.pose.distance).to_array();
+        }
+    }
+
+    pub fn zoom(&mut self, factor: f32) {
+        match self.mode {
+            CameraMode::FreeFly => {
+                self.pose.fov *= factor;
+            }
+            _ => {
+                self.pose.distance *= factor;
+            }
+        }
+        self.pose.clamp();
+    }
+
+    // Moves the target in camera space: x to the right, y up, z forward.
+    pub fn translate(&mut self, local: Vec3) {
+        let view = self.pose.view_matrix(0.0);
+        let world = -view.x_axis * local.x + view.y_axis * local.y + view.z_axis * local.z;
+        self.pose.target = (self.pose.target() + world).to_array();
+    }
+}
+
+// Transient mouse/keyboard state driving a `Camera`. Fed the window events egui
+// did not consume.
+#[derive(Default)]
+pub struct CameraController {
+    cursor: Option<(f64, f64)>,
+    dragging: Option<MouseButton>,
+    keys: HashSet<KeyCode>,
+}
+
+impl CameraController {
+    // `over_ui` is true while the pointer is over an egui window; presses there are
+    // not camera drags.
+    pub fn handle_event(&mut self, camera: &mut Camera, event: &WindowEvent, over_ui: bool) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let (x, y) = (position.x, position.y);
+                if let (Some(button), Some((last_x, last_y))) = (self.dragging, self.cursor) {
+                    let dx = (x - last_x) as f32;
+                    let dy = (y - last_y) as f32;
+                    let pan = camera.mode == CameraMode::Inspect
+                        && matches!(button, MouseButton::Right | MouseButton::Middle);
+                    if pan {
+                        let scale = camera.pose.distance * camera.pose.fov_scale() * 0.001;
+                        camera.translate(Vec3::new(-dx * scale, dy * scale, 0.0));
+                    } else if camera.mode != CameraMode::Inspect || button == MouseButton::Left {
+                        camera.rotate(dx * DRAG_DEGREES_PER_PIXEL, -dy * DRAG_DEGREES_PER_PIXEL);
+                    }
+                }
+                self.cursor = Some((x, y));
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                self.dragging = None;
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed if !over_ui && self.dragging.is_none() => {
+                    self.dragging = Some(*button);
+                }
+                ElementState::Released if self.dragging == Some(*button) => {
+                    self.dragging = None;
+                }
+                _ => {}
+            },
+            WindowEvent::MouseWheel { delta, .. } if !over_ui => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32 / 40.0,
+                };
+                camera.zoom(SCROLL_ZOOM.powf(lines));
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    match event.state {
+                        ElementState::Pressed => self.keys.insert(code),
+                        ElementState::Released => self.keys.remove(&code),
+                    };
+                }
+            }
+            WindowEvent::Focused(false) => {
+                self.keys.clear();
+                self.dragging = None;
+            }
+            _ => {}
+        }
+    }
+
+    // Per-frame motion: WASD/QE flight (Shift for 4x) over the real frame time `dt`,
+    // and the orbit auto-rotation over the animation clock's `animation_dt`.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32, animation_dt: f32) {
+        match camera.mode {
+            CameraMode::FreeFly => {
+                let axis = |positive: KeyCode, negative: KeyCode| {
+                    self.keys.contains(&positive) as i32 as f32
+                        - self.keys.contains(&negative) as i32 as f32
+                };
+                let direction = Vec3::new(
+                    axis(KeyCode::KeyD, KeyCode::KeyA),
+                    axis(KeyCode::KeyE, KeyCode::KeyQ),
+                    axis(KeyCode::KeyW, KeyCode::KeyS),
+                );
+                if direction != Vec3::ZERO {
+                    let boost = if self.keys.contains(&KeyCode::ShiftLeft) {
+                        4.0
+                    } else {
+                        1.0
+                    };
+                    camera.translate(direction.normalize() * camera.fly_speed * boost * dt);
+                }
+            }
+            CameraMode::Orbit if self.dragging.is_none() && camera.auto_orbit != 0.0 => {
+                camera.rotate(camera.auto_orbit * animation_dt, 0.0);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Animation clock behind the shader's `time` uniform. Wall-clock frame times go in,
+// animation time comes out: it can be paused, sped up, run backwards, scrubbed, or
+// put in fixed-step mode where every frame advances exactly `speed / fps` seconds
+// no matter how long it took to render, which makes captures reproducible.
+
+pub struct Clock {
+    time: f64,
+    pub paused: bool,
+    pub speed: f32,
+    pub fixed_step: bool,
+    pub fps: f32,
+    pending_frames: i32,
+}
+
+impl Clock {
+    pub fn new(time: f32) -> Self {
+        Self {
+            time: time as f64,
+            paused: false,
+            speed: 1.0,
+            fixed_step: false,
+            fps: 60.0,
+            pending_frames: 0,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time as f32
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time as f64;
+    }
+
+    pub fn reverse(&mut self) {
+        self.speed = -self.speed;
+    }
+
+    // Length of one fixed step in animation seconds, before `speed`.
+    pub fn frame_step(&self) -> f32 {
+        1.0 / self.fps.max(1.0)
+    }
+
+    // Queues single-frame steps (negative steps back) for the next `tick`; works
+    // while paused.
+    pub fn step_frames(&mut self, frames: i32) {
+        self.pending_frames += frames;
+    }
+
+    // Advances by one rendered frame that took `real_dt` seconds and returns the
+    // change in animation time.
+    pub fn tick(&mut self, real_dt: f32) -> f32 {
+        let frame = if self.fixed_step {
+            self.frame_step()
+        } else {
+            real_dt
+        };
+        let mut dt = if self.paused { 0.0 } else { frame * self.speed };
+        dt += std::mem::take(&mut self.pending_frames) as f32 * self.frame_step();
+        self.time += dt as f64;
+        dt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_follows_real_time() {
+        let mut clock = Clock::new(2.0);
+        assert_eq!(clock.tick(0.25), 0.25);
+        assert_eq!(clock.time(), 2.25);
+    }
+
+    #[test]
+    fn paused_clock_stands_still() {
+        let mut clock = Clock::new(1.0);
+        clock.paused = true;
+        assert_eq!(clock.tick(0.5), 0.0);
+        assert_eq!(clock.time(), 1.0);
+
+        clock.paused = false;
+        clock.tick(0.5);
+        assert_eq!(clock.time(), 1.5);
+    }
+
+    #[test]
+    fn speed_scales_and_reverse_runs_backwards() {
+        let mut clock = Clock::new(10.0);
+        clock.speed = 2.0;
+        assert_eq!(clock.tick(0.5), 1.0);
+        clock.reverse();
+        assert_eq!(clock.speed, -2.0);
+        assert_eq!(clock.tick(0.25), -0.5);
+        assert_eq!(clock.time(), 10.5);
+    }
+
+    #[test]
+    fn step_frames_while_paused() {
+        let mut clock = Clock::new(0.0);
+        clock.paused = true;
+        clock.fps = 25.0;
+        clock.step_frames(1);
+        clock.step_frames(2);
+        assert_eq!(clock.tick(0.5), 3.0 / 25.0);
+        // Steps are used up by the tick that applies them.
+        assert_eq!(clock.tick(0.5), 0.0);
+
+        clock.step_frames(-1);
+        clock.tick(0.5);
+        assert!((clock.time() - 2.0 / 25.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fixed_step_ignores_frame_time() {
+        let mut clock = Clock::new(0.0);
+        clock.fixed_step = true;
+        clock.fps = 30.0;
+        for real_dt in [0.001, 0.1, 2.0] {
+            assert_eq!(clock.tick(real_dt), 1.0 / 30.0);
+        }
+        assert!((clock.time() - 0.1).abs() < 1e-6);
+
+        clock.speed = -0.5;
+        assert_eq!(clock.tick(1.0), -1.0 / 60.0);
+    }
+
+    #[test]
+    fn fixed_step_covers_whole_seconds_exactly() {
+        // 24 fps for 10 s is 240 frames; accumulating in f64 lands on 10 s.
+        let mut clock = Clock::new(0.0);
+        clock.fixed_step = true;
+        clock.fps = 24.0;
+        for _ in 0..240 {
+            clock.tick(0.0);
+        }
+        assert!((clock.time() - 10.0).abs() < 1e-5);
+    }
+}
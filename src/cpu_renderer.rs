@@ -0,0 +1,583 @@
+// Headless CPU port of the full render pipeline: `traceColor`/`adiskColor` from
+// `shader/blackhole_main.frag`, the precomputed noise volumes, and the bloom, lens
+// flare and tonemapping chain driven by `Renderer::render`. Needs no GL context, so
+// stills and regression images can be produced on machines without a GPU.
+//
+// Render targets mirror the native HDR path (float color, no clamping until the
+// final tonemap) and are stored bottom row first like GL textures.
+
+use anyhow::Context;
+use glam::{Vec2, Vec3};
+use image::RgbaImage;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::app_state::AppState;
+use crate::blue_noise::{self, BLUE_NOISE_SIZE};
+use crate::geodesic::RayCamera;
+use crate::noise_gen;
+use crate::renderer::MAX_BLOOM_ITER;
+
+const EPSILON: f32 = 0.0001;
+const TILE_SIZE: usize = 32;
+
+const LUMINANCE: Vec3 = Vec3::new(0.2125, 0.7154, 0.0721);
+const BRIGHT_PASS_THRESHOLD: f32 = 1.0;
+const GOLDEN_RATIO_FRACT: f32 = 0.618_034;
+const OUTPUT_DITHER: f32 = 1.0 / 255.0;
+
+#[derive(Clone)]
+struct FloatImage {
+    width: usize,
+    height: usize,
+    data: Vec<Vec3>,
+}
+
+impl FloatImage {
+    fn from_rgba8(img: &image::RgbaImage) -> Self {
+        let data = img
+            .pixels()
+            .map(|p| Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32) / 255.0)
+            .collect();
+        Self {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            data,
+        }
+    }
+
+    fn fetch(&self, x: i64, y: i64) -> Vec3 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.data[y * self.width + x]
+    }
+
+    // Bilinear lookup with CLAMP_TO_EDGE, matching a GL_LINEAR 2D texture.
+    fn sample(&self, uv: Vec2) -> Vec3 {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.fetch(x0, y0).lerp(self.fetch(x0 + 1, y0), fx);
+        let bottom = self.fetch(x0, y0 + 1).lerp(self.fetch(x0 + 1, y0 + 1), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+struct NoiseVolume {
+    size: usize,
+    data: Vec<f32>,
+}
+
+impl NoiseVolume {
+    fn new() -> Self {
+        let data = noise_gen::generate_noise_3d()
+            .into_iter()
+            .map(|v| v as f32 / 255.0)
+            .collect();
+        Self {
+            size: noise_gen::NOISE_SIZE,
+            data,
+        }
+    }
+
+    fn fetch(&self, x: i64, y: i64, z: i64) -> f32 {
+        let n = self.size as i64;
+        let (x, y, z) = (x.rem_euclid(n), y.rem_euclid(n), z.rem_euclid(n));
+        self.data[((z * n + y) * n + x) as usize]
+    }
+
+    // Trilinear lookup with REPEAT wrapping, matching `noiseTex`.
+    fn sample(&self, coord: Vec3) -> f32 {
+        let p = coord * self.size as f32 - Vec3::splat(0.5);
+        let base = p.floor();
+        let f = p - base;
+        let (x, y, z) = (base.x as i64, base.y as i64, base.z as i64);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let c00 = lerp(self.fetch(x, y, z), self.fetch(x + 1, y, z), f.x);
+        let c10 = lerp(self.fetch(x, y + 1, z), self.fetch(x + 1, y + 1, z), f.x);
+        let c01 = lerp(self.fetch(x, y, z + 1), self.fetch(x + 1, y, z + 1), f.x);
+        let c11 = lerp(self.fetch(x, y + 1, z + 1), self.fetch(x + 1, y + 1, z + 1), f.x);
+        lerp(lerp(c00, c10, f.y), lerp(c01, c11, f.y), f.z)
+    }
+}
+
+struct Cubemap {
+    // +X, -X, +Y, -Y, +Z, -Z
+    faces: Vec<FloatImage>,
+}
+
+impl Cubemap {
+    fn sample(&self, dir: Vec3) -> Vec3 {
+        let a = dir.abs();
+        // Face selection and (sc, tc, ma) per the GL spec cube map table.
+        let (face, sc, tc, ma) = if a.x >= a.y && a.x >= a.z {
+            if dir.x > 0.0 {
+                (0, -dir.z, -dir.y, a.x)
+            } else {
+                (1, dir.z, -dir.y, a.x)
+            }
+        } else if a.y >= a.z {
+            if dir.y > 0.0 {
+                (2, dir.x, dir.z, a.y)
+            } else {
+                (3, dir.x, -dir.z, a.y)
+            }
+        } else if dir.z > 0.0 {
+            (4, dir.x, -dir.y, a.z)
+        } else {
+            (5, -dir.x, -dir.y, a.z)
+        };
+        if ma <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let uv = Vec2::new(sc / ma + 1.0, tc / ma + 1.0) * 0.5;
+        self.faces[face].sample(uv)
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn glsl_fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn to_spherical(p: Vec3) -> Vec3 {
+    let rho = p.length();
+    let theta = p.z.atan2(p.x);
+    let phi = (p.y / rho).asin();
+    Vec3::new(rho, theta, phi)
+}
+
+// `rotateVector` in the shader takes the angle in degrees.
+fn rotate_vector(position: Vec3, axis: Vec3, angle_degrees: f32) -> Vec3 {
+    glam::Quat::from_axis_angle(axis, angle_degrees.to_radians()) * position
+}
+
+fn aces(x: Vec3) -> Vec3 {
+    const A: f32 = 2.51;
+    const B: f32 = 0.03;
+    const C: f32 = 2.43;
+    const D: f32 = 0.59;
+    const E: f32 = 0.14;
+    ((x * (A * x + Vec3::splat(B))) / (x * (C * x + Vec3::splat(D)) + Vec3::splat(E)))
+        .clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+// The camera uniforms `Renderer` passes to the shader.
+pub fn shader_camera(state: &AppState) -> RayCamera {
+    let pose = &state.camera.pose;
+    RayCamera {
+        position: pose.position().as_dvec3(),
+        view: pose.view_matrix(state.camera_roll).as_dmat3(),
+        fov_scale: pose.fov_scale() as f64,
+    }
+}
+
+// Runs `shade(x, y)` for every pixel of a `width` x `height` target, handing out
+// square tiles to worker threads. `y` counts rows from the bottom like gl_FragCoord.
+fn render_tiles(
+    width: usize,
+    height: usize,
+    threads: usize,
+    shade: impl Fn(usize, usize) -> Vec3 + Sync,
+) -> FloatImage {
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let tiles_y = height.div_ceil(TILE_SIZE);
+    let tile_count = tiles_x * tiles_y;
+    let next_tile = AtomicUsize::new(0);
+
+    let finished: Vec<(usize, Vec<Vec3>)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, tile_count.max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if tile >= tile_count {
+                            break;
+                        }
+                        let x0 = (tile % tiles_x) * TILE_SIZE;
+                        let y0 = (tile / tiles_x) * TILE_SIZE;
+                        let x1 = (x0 + TILE_SIZE).min(width);
+                        let y1 = (y0 + TILE_SIZE).min(height);
+                        let mut pixels = Vec::with_capacity((x1 - x0) * (y1 - y0));
+                        for y in y0..y1 {
+                            for x in x0..x1 {
+                                pixels.push(shade(x, y));
+                            }
+                        }
+                        done.push((tile, pixels));
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("CPU render worker panicked"))
+            .collect()
+    });
+
+    let mut data = vec![Vec3::ZERO; width * height];
+    for (tile, pixels) in finished {
+        let x0 = (tile % tiles_x) * TILE_SIZE;
+        let y0 = (tile / tiles_x) * TILE_SIZE;
+        let tile_w = (x0 + TILE_SIZE).min(width) - x0;
+        for (row, chunk) in pixels.chunks(tile_w).enumerate() {
+            let start = (y0 + row) * width + x0;
+            data[start..start + tile_w].copy_from_slice(chunk);
+        }
+    }
+    FloatImage {
+        width,
+        height,
+        data,
+    }
+}
+
+fn pixel_uv(x: usize, y: usize, width: usize, height: usize) -> Vec2 {
+    Vec2::new(
+        (x as f32 + 0.5) / width as f32,
+        (y as f32 + 0.5) / height as f32,
+    )
+}
+
+pub struct CpuRenderer {
+    color_map: FloatImage,
+    galaxy: Cubemap,
+    noise: NoiseVolume,
+    blue_noise: Vec<u8>,
+    threads: usize,
+}
+
+impl CpuRenderer {
+    pub fn new() -> anyhow::Result<Self> {
+        let load = |path: &str| -> anyhow::Result<FloatImage> {
+            let bytes = crate::assets::read(path)?;
+            let img = image::load_from_memory(&bytes)
+                .with_context(|| format!("Failed to load texture: {}", path))?;
+            Ok(FloatImage::from_rgba8(&img.to_rgba8()))
+        };
+
+        let color_map = load("assets/color_map.png")?;
+        let faces = ["right.png", "left.png", "top.png", "bottom.png", "front.png", "back.png"]
+            .iter()
+            .map(|name| load(&format!("assets/skybox_nebula_dark/{}", name)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+
+        Ok(Self {
+            color_map,
+            galaxy: Cubemap { faces },
+            noise: NoiseVolume::new(),
+            blue_noise: blue_noise::generate_blue_noise(),
+            threads,
+        })
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn render(&self, state: &AppState, time: f32, width: u32, height: u32) -> RgbaImage {
+        let (w, h) = (width.max(1) as usize, height.max(1) as usize);
+
+        let blackhole = self.pass_blackhole(state, time, w, h);
+        let brightness = self.pass_brightness(&blackhole);
+        let lens_flare = self.pass_lens_flare(&brightness);
+        let bloom = self.pass_bloom(&brightness);
+        let bloom = bloom.as_ref().unwrap_or(&blackhole);
+        let composite = self.pass_composite(state, &blackhole, bloom, &lens_flare);
+        let tonemapped = self.pass_tonemapping(state, time, &composite);
+
+        // Render targets are stored bottom-up; images are top-down.
+        let mut output = RgbaImage::new(w as u32, h as u32);
+        for (x, y, pixel) in output.enumerate_pixels_mut() {
+            let c = tonemapped.data[(h - 1 - y as usize) * w + x as usize];
+            let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            *pixel = image::Rgba([to_u8(c.x), to_u8(c.y), to_u8(c.z), 255]);
+        }
+        output
+    }
+
+    fn pass_blackhole(&self, state: &AppState, time: f32, w: usize, h: usize) -> FloatImage {
+        let camera = shader_camera(state);
+        let origin = camera.position.as_vec3();
+        render_tiles(w, h, self.threads, |x, y| {
+            let dir = camera
+                .ray_direction(x as f64 + 0.5, (h - y) as f64 - 0.5, w as u32, h as u32)
+                .as_vec3();
+            self.trace_color(state, time, origin, dir)
+        })
+    }
+
+    fn trace_color(&self, state: &AppState, time: f32, mut pos: Vec3, mut dir: Vec3) -> Vec3 {
+        let mut color = Vec3::ZERO;
+        let mut alpha = 1.0;
+        let spin = state.spin;
+
+        let spin_axis = Vec3::Y;
+        let h2 = pos.cross(dir).length_squared();
+
+        for _ in 0..300 {
+            let dist = pos.length();
+            let step_size = (dist * 0.05).max(0.02);
+
+            if state.render_black_hole {
+                if state.gravitational_lensing {
+                    let r5 = pos.length_squared().powf(2.5);
+                    let mut acc = -1.5 * h2 * pos / r5;
+                    if spin > 0.0 {
+                        let r3 = dist * dist * dist + EPSILON;
+                        acc += dir.cross(spin_axis) * (spin / r3);
+                    }
+                    dir += acc * step_size;
+                }
+
+                let spin_dot = dir.normalize().dot(spin_axis);
+                let hit_radius = (1.0 - spin * 0.5 * spin_dot).max(0.5);
+                if dist < hit_radius {
+                    return color;
+                }
+
+                if state.adisk_enabled {
+                    self.adisk_color(state, time, pos, dir, &mut color, &mut alpha);
+                }
+            }
+
+            pos += dir * step_size;
+        }
+
+        dir = rotate_vector(dir, Vec3::Y, time);
+        if spin > 0.0 {
+            let radius = pos.length().max(EPSILON);
+            let phi_shift = (spin * 2.0) / radius;
+            dir = rotate_vector(dir, spin_axis, (-phi_shift).to_degrees());
+        }
+        color + self.galaxy.sample(dir) * alpha
+    }
+
+    fn adisk_color(
+        &self,
+        state: &AppState,
+        time: f32,
+        pos: Vec3,
+        view_dir: Vec3,
+        color: &mut Vec3,
+        alpha: &mut f32,
+    ) {
+        let inner_radius = 2.6;
+        let outer_radius = 12.0;
+
+        let mut density = (1.0
+            - (pos / Vec3::new(outer_radius, state.adisk_height, outer_radius)).length())
+        .max(0.0);
+        if density < 0.001 {
+            return;
+        }
+
+        density *= (1.0 - pos.y.abs() / state.adisk_height).powf(state.adisk_density_v);
+        density *= smoothstep(inner_radius, inner_radius * 1.1, pos.length());
+        if density < 0.001 {
+            return;
+        }
+
+        let radius = pos.length().max(EPSILON);
+        let mut spherical = to_spherical(pos);
+        spherical.y -= (state.spin * 2.0) / radius;
+        spherical.y *= 2.0;
+        spherical.z *= 4.0;
+
+        density *= 1.0 / spherical.x.powf(state.adisk_density_h);
+        density *= 16000.0;
+
+        let disc_velocity = Vec3::Y.cross(pos);
+        let velocity_len = disc_velocity.length();
+        let disc_velocity = if velocity_len > EPSILON {
+            disc_velocity / velocity_len
+        } else {
+            Vec3::ZERO
+        };
+        let view_len = view_dir.length();
+        let view_dir_norm = if view_len > EPSILON {
+            view_dir / view_len
+        } else {
+            Vec3::Z
+        };
+        let doppler_factor = disc_velocity.dot(view_dir_norm);
+        density *= 1.0 - doppler_factor * 0.25;
+
+        let redshift = (1.0 - 1.0 / (radius + 0.5)).clamp(0.0, 1.0);
+        let redshift_color = Vec3::new(1.0, redshift, redshift);
+
+        if !state.adisk_particle {
+            *color += Vec3::new(0.0, 1.0, 0.0) * density * 0.02 * redshift_color;
+            return;
+        }
+
+        let mut noise = 1.0;
+        for i in 0..state.adisk_noise_lod as i32 {
+            let scale = (i as f32).powi(2);
+            let mut noise_sample = 0.5;
+            if scale > 0.0 {
+                let noise_coord = spherical * scale * state.adisk_noise_scale;
+                noise_sample = self.noise.sample(noise_coord / noise_gen::NOISE_DOMAIN);
+            }
+            noise *= noise_sample;
+            if i % 2 == 0 {
+                spherical.y += time * state.adisk_speed;
+            } else {
+                spherical.y -= time * state.adisk_speed;
+            }
+        }
+
+        let dust_color = self
+            .color_map
+            .sample(Vec2::new(spherical.x / outer_radius, 0.5))
+            * redshift_color;
+
+        *color += density * state.adisk_lit * dust_color * *alpha * f32::abs(noise);
+    }
+
+    fn pass_brightness(&self, source: &FloatImage) -> FloatImage {
+        render_tiles(source.width, source.height, self.threads, |x, y| {
+            let c = source.data[y * source.width + x];
+            let luminance = (LUMINANCE.dot(c) - BRIGHT_PASS_THRESHOLD).max(0.0);
+            if luminance > 0.0 { c } else { Vec3::ZERO }
+        })
+    }
+
+    fn pass_lens_flare(&self, source: &FloatImage) -> FloatImage {
+        const WEIGHTS: [f32; 7] = [0.07, 0.12, 0.17, 0.28, 0.17, 0.12, 0.07];
+        let (w, h) = (source.width, source.height);
+        let texel = 1.0 / w.max(1) as f32;
+        render_tiles(w, h, self.threads, |x, y| {
+            let uv = pixel_uv(x, y, w, h);
+            let mut color = Vec3::ZERO;
+            for (i, weight) in WEIGHTS.iter().enumerate() {
+                let offset = (i as f32 - 3.0) * texel;
+                let sample = source.sample(uv + Vec2::new(offset, 0.0));
+                let highlight = smoothstep(0.9, 1.6, sample.dot(LUMINANCE));
+                color += sample * (weight * highlight);
+            }
+            color * Vec3::new(0.6, 0.85, 1.2)
+        })
+    }
+
+    // Same mip chain as `Renderer`: downsample from the brightness pass, then
+    // upsample back while adding the next larger level. `None` if the target is too
+    // small for a single mip.
+    fn pass_bloom(&self, brightness: &FloatImage) -> Option<FloatImage> {
+        let (width, height) = (brightness.width, brightness.height);
+        let mut downsampled: Vec<FloatImage> = Vec::new();
+        for i in 0..MAX_BLOOM_ITER {
+            let (w, h) = (width >> (i + 1), height >> (i + 1));
+            if w == 0 || h == 0 {
+                break;
+            }
+            let source = downsampled.last().unwrap_or(brightness);
+            let level = render_tiles(w, h, self.threads, |x, y| {
+                let uv = pixel_uv(x, y, w, h);
+                let o = Vec2::new(0.5 / w as f32, 0.5 / h as f32);
+                0.25 * (source.sample(uv + Vec2::new(-o.x, -o.y))
+                    + source.sample(uv + Vec2::new(o.x, -o.y))
+                    + source.sample(uv + Vec2::new(-o.x, o.y))
+                    + source.sample(uv + Vec2::new(o.x, o.y)))
+            });
+            downsampled.push(level);
+        }
+
+        let chain_len = downsampled.len();
+        let mut upsampled: Option<FloatImage> = None;
+        for i in (0..chain_len).rev() {
+            let tex0 = match &upsampled {
+                Some(previous) => previous,
+                None => &downsampled[i],
+            };
+            let tex1 = if i == 0 { brightness } else { &downsampled[i - 1] };
+            let (w, h) = (width >> i, height >> i);
+            let level = render_tiles(w, h, self.threads, |x, y| {
+                let uv = pixel_uv(x, y, w, h);
+                let o = Vec2::new(0.5 / w as f32, 0.5 / h as f32);
+                0.25 * (tex0.sample(uv + Vec2::new(-o.x, -o.y))
+                    + tex0.sample(uv + Vec2::new(o.x, -o.y))
+                    + tex0.sample(uv + Vec2::new(-o.x, o.y))
+                    + tex0.sample(uv + Vec2::new(o.x, o.y)))
+                    + tex1.sample(uv)
+            });
+            upsampled = Some(level);
+        }
+        upsampled
+    }
+
+    fn pass_composite(
+        &self,
+        state: &AppState,
+        scene: &FloatImage,
+        bloom: &FloatImage,
+        flare: &FloatImage,
+    ) -> FloatImage {
+        let (w, h) = (scene.width, scene.height);
+        render_tiles(w, h, self.threads, |x, y| {
+            let uv = pixel_uv(x, y, w, h);
+            scene.sample(uv)
+                + bloom.sample(uv) * state.bloom_strength
+                + flare.sample(uv) * state.flare_strength
+        })
+    }
+
+    // `blueNoiseAt` from `tonemapping.frag`.
+    fn blue_noise_at(&self, x: usize, y: usize, tile_offset: (usize, usize), phase: f32) -> f32 {
+        let tx = (x + tile_offset.0) % BLUE_NOISE_SIZE;
+        let ty = (y + tile_offset.1) % BLUE_NOISE_SIZE;
+        let n = self.blue_noise[ty * BLUE_NOISE_SIZE + tx] as f32 / 255.0;
+        glsl_fract(n + GOLDEN_RATIO_FRACT * phase)
+    }
+
+    fn pass_tonemapping(&self, state: &AppState, time: f32, source: &FloatImage) -> FloatImage {
+        let (w, h) = (source.width, source.height);
+        let phase = (time * 60.0).floor();
+        render_tiles(w, h, self.threads, |x, y| {
+            let uv = pixel_uv(x, y, w, h);
+            let to_center = uv - Vec2::splat(0.5);
+            let dist = to_center.length();
+            let dir = if dist > 0.0 { to_center / dist } else { Vec2::ZERO };
+            let offset = dir * dist * state.chroma_aberration;
+
+            let mut color = Vec3::new(
+                source.sample(uv - offset).x,
+                source.sample(uv).y,
+                source.sample(uv + offset).z,
+            );
+
+            if state.tonemapping_enabled {
+                color = aces(color);
+                color = color.powf(1.0 / state.gamma);
+            }
+
+            let luma = color.dot(LUMINANCE);
+            color = Vec3::splat(luma).lerp(color, state.saturation);
+
+            let grain = self.blue_noise_at(x, y, (0, 0), phase);
+            color += Vec3::splat((grain - 0.5) * state.grain_strength);
+
+            let dither = self.blue_noise_at(x, y, (32, 17), phase)
+                + self.blue_noise_at(x, y, (11, 45), phase)
+                - 1.0;
+            color += Vec3::splat(dither * OUTPUT_DITHER);
+
+            color.clamp(Vec3::ZERO, Vec3::ONE)
+        })
+    }
+}
+
@@ -0,0 +1,323 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+// Adjusts `QualitySettings::render_scale` to keep frames inside a time budget.
+//
+// Frame cost is measured with GPU timer queries on native builds; WebGL has no
+// portable timer query, so the web build (and any driver without one) falls back to
+// the wall-clock frame time. Vsync caps that fallback at the display refresh, so it
+// can only tell when a frame is over budget, never how much headroom is left.
+//
+// Samples are averaged over a window before a decision and the window restarts
+// after every change, so the old resolution never gets judged by the new frames.
+// Single hitches (a page load, the first frame after the loop slept) are left out of
+// the average. The budget has a dead band: scale drops once the average is clearly
+// over the target, and only climbs back once it is comfortably under it. The margin
+// above matters for the wall-clock fallback, where a 60 Hz display reports 16.67 ms
+// frames against the default 16.6 ms target no matter how much headroom is left.
+//
+// Since vsync hides the headroom, the wall-clock fallback can't grow on the average
+// alone. After a while at a steady lowered scale it tries one step up, and goes back
+// (waiting twice as long before the next try) if that frame rate doesn't hold.
+
+use glow::HasContext;
+
+use crate::app_state::QualitySettings;
+
+// Frames averaged per decision.
+const WINDOW: usize = 12;
+// Queries in flight; results arrive a couple of frames late.
+#[cfg(not(target_arch = "wasm32"))]
+const QUERIES: usize = 4;
+// Drop only above this fraction of the target, grow only below this one.
+const DROP_ABOVE: f32 = 1.1;
+const GROW_BELOW: f32 = 0.8;
+const SETTLE_FRAMES: usize = 4;
+const MIN_SCALE: f32 = 0.25;
+const MAX_SCALE: f32 = 1.0;
+const SCALE_STEP: f32 = 0.05;
+// Largest change in one decision, up and down.
+const MAX_DROP: f32 = 0.25;
+const MAX_GROW: f32 = 0.1;
+// Samples this many times the window median are hitches, not load.
+const OUTLIER: f32 = 2.0;
+// Steady windows before the wall-clock fallback probes a step up, at first and at most.
+const PROBE_WINDOWS: usize = 10;
+const MAX_PROBE_WINDOWS: usize = 160;
+
+// GL_TIME_ELAPSED queries wrapped around each frame's GPU work.
+pub struct GpuTimer {
+    #[cfg(not(target_arch = "wasm32"))]
+    queries: Vec<glow::Query>,
+    #[cfg(not(target_arch = "wasm32"))]
+    next: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    pending: std::collections::VecDeque<glow::Query>,
+    #[cfg(not(target_arch = "wasm32"))]
+    timing: bool,
+    latest_ms: Option<f32>,
+}
+
+impl GpuTimer {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn new(gl: &glow::Context) -> Self {
+        let mut queries = Vec::new();
+        for _ in 0..QUERIES {
+            match gl.create_query() {
+                Ok(query) => queries.push(query),
+                Err(_) => break,
+            }
+        }
+        if queries.len() < QUERIES || gl.get_error() != glow::NO_ERROR {
+            for query in queries.drain(..) {
+                gl.delete_query(query);
+            }
+        }
+        Self {
+            queries,
+            next: 0,
+            pending: std::collections::VecDeque::new(),
+            timing: false,
+            latest_ms: None,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub unsafe fn new(_gl: &glow::Context) -> Self {
+        Self { latest_ms: None }
+    }
+
+    pub fn is_available(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            !self.queries.is_empty()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            false
+        }
+    }
+
+    // Starts timing a frame. Skipped while every query is still waiting on the GPU.
+    pub unsafe fn begin(&mut self, gl: &glow::Context) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.is_available() && self.pending.len() < self.queries.len() {
+            let query = self.queries[self.next];
+            self.next = (self.next + 1) % self.queries.len();
+            gl.begin_query(glow::TIME_ELAPSED, query);
+            self.pending.push_back(query);
+            self.timing = true;
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = gl;
+    }
+
+    // Ends the frame started by `begin` and collects whichever earlier frames have
+    // finished. Returns the most recent GPU time in milliseconds, if any is known.
+    pub unsafe fn end(&mut self, gl: &glow::Context) -> Option<f32> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.is_available() {
+            if std::mem::take(&mut self.timing) {
+                gl.end_query(glow::TIME_ELAPSED);
+            }
+            while let Some(&query) = self.pending.front() {
+                if gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) == 0 {
+                    break;
+                }
+                // A u32 result wraps after 4.3 s; with no query buffer bound, glow's
+                // "offset" is the address the 64-bit result is written to.
+                let mut nanos = 0u64;
+                gl.get_query_parameter_u64_with_offset(
+                    query,
+                    glow::QUERY_RESULT,
+                    &mut nanos as *mut u64 as usize,
+                );
+                // Some drivers report garbage for the first query or after a GPU
+                // reset; no real frame takes a second.
+                let ms = nanos as f32 * 1e-6;
+                if ms < 1000.0 {
+                    self.latest_ms = Some(ms);
+                }
+                self.pending.pop_front();
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = gl;
+        self.latest_ms.take()
+    }
+}
+
+pub struct DynamicResolution {
+    samples: Vec<f32>,
+    // Samples still to ignore after a change; timer results lag a few frames.
+    settle: usize,
+    // Frame times are wall-clock, capped by vsync, so headroom has to be probed.
+    wall_clock: bool,
+    // Windows in the dead band since the last change, and how many to wait for
+    // before probing.
+    steady: usize,
+    probe_after: usize,
+    // The scale to go back to while a probe runs.
+    probe_from: Option<f32>,
+}
+
+impl DynamicResolution {
+    pub fn new(wall_clock: bool) -> Self {
+        Self {
+            samples: Vec::new(),
+            settle: 0,
+            wall_clock,
+            steady: 0,
+            probe_after: PROBE_WINDOWS,
+            probe_from: None,
+        }
+    }
+
+    // Drops the current window, e.g. after a resize or once the loop has slept.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.settle = SETTLE_FRAMES;
+    }
+
+    // Feeds one frame's cost. Every `WINDOW` samples the average is compared with
+    // `quality.target_frame_ms` and `quality.render_scale` may move one step.
+    pub fn update(&mut self, quality: &mut QualitySettings, frame_ms: f32) {
+        if !quality.dynamic_resolution {
+            self.samples.clear();
+            self.probe_from = None;
+            return;
+        }
+        if self.settle > 0 {
+            self.settle -= 1;
+            return;
+        }
+        self.samples.push(frame_ms);
+        if self.samples.len() < WINDOW {
+            return;
+        }
+        self.samples.sort_by(f32::total_cmp);
+        let median = self.samples[WINDOW / 2];
+        let kept = self.samples.iter().filter(|&&ms| ms <= median * OUTLIER);
+        let average = kept.clone().sum::<f32>() / kept.count() as f32;
+        self.samples.clear();
+
+        let target = quality.target_frame_ms.max(1.0);
+        let scale = quality.render_scale;
+        // Ray-march cost goes with the pixel count, i.e. the square of the scale.
+        let ideal = scale * (target / average.max(0.01)).sqrt();
+        let snap = |s: f32| (s / SCALE_STEP).round() * SCALE_STEP;
+        let next = if average > target * DROP_ABOVE {
+            snap(ideal.max(scale - MAX_DROP)).min(scale - SCALE_STEP)
+        } else if average < target * GROW_BELOW {
+            // Aim just under the budget rather than right at it.
+            snap((ideal * GROW_BELOW.sqrt()).min(scale + MAX_GROW)).max(scale)
+        } else {
+            scale
+        };
+        let mut next = next.clamp(MIN_SCALE, MAX_SCALE);
+
+        if let Some(from) = self.probe_from.take() {
+            if next < scale {
+                next = from;
+                self.probe_after = (self.probe_after * 2).min(MAX_PROBE_WINDOWS);
+            } else {
+                self.probe_after = PROBE_WINDOWS;
+            }
+        } else if self.wall_clock && next == scale && scale < MAX_SCALE {
+            self.steady += 1;
+            if self.steady >= self.probe_after {
+                self.probe_from = Some(scale);
+                next = snap(scale + SCALE_STEP).min(MAX_SCALE);
+            }
+        }
+
+        if next != scale {
+            quality.render_scale = next;
+            self.steady = 0;
+            self.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VSYNC_MS: f32 = 1000.0 / 60.0;
+
+    fn quality() -> QualitySettings {
+        QualitySettings {
+            dynamic_resolution: true,
+            ..QualitySettings::default()
+        }
+    }
+
+    fn run(frame_ms: f32, frames: usize) -> f32 {
+        let mut quality = quality();
+        let mut controller = DynamicResolution::new(false);
+        for _ in 0..frames {
+            controller.update(&mut quality, frame_ms);
+        }
+        quality.render_scale
+    }
+
+    #[test]
+    fn vsync_capped_frames_keep_full_scale() {
+        assert_eq!(run(VSYNC_MS, 50 * WINDOW), 1.0);
+    }
+
+    #[test]
+    fn missed_vsync_drops_scale() {
+        assert!(run(1000.0 / 30.0, 2 * WINDOW) < 1.0);
+    }
+
+    #[test]
+    fn single_hitch_is_ignored() {
+        let mut quality = quality();
+        let mut controller = DynamicResolution::new(true);
+        controller.update(&mut quality, 250.0);
+        for _ in 0..50 * WINDOW {
+            controller.update(&mut quality, VSYNC_MS);
+        }
+        assert_eq!(quality.render_scale, 1.0);
+    }
+
+    #[test]
+    fn wall_clock_scale_recovers_after_a_slow_start() {
+        // The first frame after an idle wait, then a few slow ones while a page
+        // loads, are enough to drop the scale; vsync-capped frames bring it back.
+        let mut quality = quality();
+        let mut controller = DynamicResolution::new(true);
+        controller.update(&mut quality, 250.0);
+        for _ in 0..2 * WINDOW {
+            controller.update(&mut quality, 40.0);
+        }
+        assert!(quality.render_scale < 1.0);
+        for _ in 0..500 * WINDOW {
+            controller.update(&mut quality, VSYNC_MS);
+        }
+        assert_eq!(quality.render_scale, 1.0);
+    }
+
+    #[test]
+    fn failed_probe_goes_back() {
+        // Vsync halves the frame rate above 0.7, so the probes up from there fail.
+        let over = |scale: f32| scale > 0.72;
+        let mut quality = quality();
+        let mut controller = DynamicResolution::new(true);
+        let mut probed = 0;
+        for frame in 0..1000 * WINDOW {
+            let frame_ms = if over(quality.render_scale) {
+                2.0 * VSYNC_MS
+            } else {
+                VSYNC_MS
+            };
+            controller.update(&mut quality, frame_ms);
+            if frame > 100 * WINDOW && over(quality.render_scale) {
+                probed += 1;
+            }
+        }
+        // It climbs back to the highest scale that holds, or is probing just above it.
+        assert!(quality.render_scale >= 0.69 && quality.render_scale <= 0.76);
+        // Probes get rarer the more often they fail.
+        assert!(probed < 50 * WINDOW, "{} frames over budget", probed);
+    }
+}
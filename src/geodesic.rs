@@ -0,0 +1,545 @@
+// CPU reference ray tracer for null geodesics around a Schwarzschild black hole.
+//
+// Units follow `shader/blackhole_main.frag`: the Schwarzschild radius is 1, so the
+// mass is M = 0.5 and the photon sphere sits at r = 1.5. Positions and directions
+// live in the same world space the shader uses (spin axis along +Y).
+
+use glam::{DMat3, DQuat, DVec3};
+
+pub const SCHWARZSCHILD_RADIUS: f64 = 1.0;
+pub const MASS: f64 = 0.5 * SCHWARZSCHILD_RADIUS;
+pub const PHOTON_SPHERE_RADIUS: f64 = 3.0 * MASS;
+// b_c = 3 * sqrt(3) * M
+pub const CRITICAL_IMPACT_PARAMETER: f64 = 5.196_152_422_706_632 * MASS;
+
+// Matches the inner/outer radius used by `adiskColor` in the shader.
+const DISK_INNER_RADIUS: f64 = 2.6;
+const DISK_OUTER_RADIUS: f64 = 12.0;
+
+// Shader constants mirrored by `ShaderModel`.
+const SHADER_MAX_STEPS: usize = 300;
+const SHADER_EPSILON: f64 = 0.0001;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Disk {
+    pub inner_radius: f64,
+    pub outer_radius: f64,
+}
+
+impl Default for Disk {
+    fn default() -> Self {
+        Self {
+            inner_radius: DISK_INNER_RADIUS,
+            outer_radius: DISK_OUTER_RADIUS,
+        }
+    }
+}
+
+impl Disk {
+    // Thin disk in the y = 0 plane. Returns the crossing point of the segment `a -> b`
+    // if it lands between the inner and outer radius.
+    fn crossing(&self, a: DVec3, b: DVec3) -> Option<(DVec3, f64)> {
+        if a.y == b.y || a.y * b.y > 0.0 {
+            return None;
+        }
+        let t = a.y / (a.y - b.y);
+        let hit = a.lerp(b, t);
+        let radius = hit.length();
+        if radius >= self.inner_radius && radius <= self.outer_radius {
+            Some((hit, radius))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayOutcome {
+    Escaped { direction: DVec3 },
+    Horizon { position: DVec3 },
+    Disk { position: DVec3, radius: f64 },
+    // The step budget ran out before the ray resolved.
+    Unresolved { position: DVec3, direction: DVec3 },
+}
+
+impl RayOutcome {
+    pub fn escape_direction(&self) -> Option<DVec3> {
+        match self {
+            RayOutcome::Escaped { direction } => Some(*direction),
+            _ => None,
+        }
+    }
+
+    pub fn is_captured(&self) -> bool {
+        matches!(self, RayOutcome::Horizon { .. })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TraceSettings {
+    pub tolerance: f64,
+    pub max_steps: usize,
+    pub escape_radius: f64,
+    pub disk: Option<Disk>,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-10,
+            max_steps: 200_000,
+            escape_radius: 1.0e4,
+            disk: None,
+        }
+    }
+}
+
+// Pinhole camera matching `lookAt` and the ray setup in the shader's `main()`.
+#[derive(Clone, Copy, Debug)]
+pub struct RayCamera {
+    pub position: DVec3,
+    pub view: DMat3,
+    pub fov_scale: f64,
+}
+
+impl RayCamera {
+    pub fn look_at(position: DVec3, target: DVec3, roll_degrees: f64, fov_scale: f64) -> Self {
+        let roll = roll_degrees.to_radians();
+        let rr = DVec3::new(roll.sin(), roll.cos(), 0.0);
+        let ww = (target - position).normalize();
+        let uu = ww.cross(rr).normalize();
+        let vv = uu.cross(ww).normalize();
+        Self {
+            position,
+            view: DMat3::from_cols(uu, vv, ww),
+            fov_scale,
+        }
+    }
+
+    // `x`/`y` are continuous pixel coordinates with the origin at the top-left corner,
+    // so pixel (i, j) is centered at (i + 0.5, j + 0.5).
+    pub fn ray_direction(&self, x: f64, y: f64, width: u32, height: u32) -> DVec3 {
+        let width = width.max(1) as f64;
+        let height = height.max(1) as f64;
+        let frag_y = height - y;
+        let mut uv_x = x / width - 0.5;
+        let uv_y = frag_y / height - 0.5;
+        uv_x *= width / height;
+
+        let local = DVec3::new(-uv_x * self.fov_scale, uv_y * self.fov_scale, 1.0).normalize();
+        self.view * local
+    }
+
+    pub fn pixel_direction(&self, px: u32, py: u32, width: u32, height: u32) -> DVec3 {
+        self.ray_direction(px as f64 + 0.5, py as f64 + 0.5, width, height)
+    }
+}
+
+pub trait GeodesicTracer {
+    fn trace(&self, origin: DVec3, direction: DVec3) -> RayOutcome;
+
+    fn trace_pixel(
+        &self,
+        camera: &RayCamera,
+        px: u32,
+        py: u32,
+        width: u32,
+        height: u32,
+    ) -> RayOutcome {
+        let direction = camera.pixel_direction(px, py, width, height);
+        self.trace(camera.position, direction)
+    }
+}
+
+// One embedded Runge-Kutta-Fehlberg 4(5) step. Returns the fifth order solution and
+// the scaled error estimate (<= 1.0 means the step satisfies `tolerance`).
+pub(crate) fn rkf45_step<const N: usize>(
+    f: &impl Fn(&[f64; N]) -> [f64; N],
+    y: &[f64; N],
+    h: f64,
+    tolerance: f64,
+) -> ([f64; N], f64) {
+    const A: [[f64; 5]; 5] = [
+        [1.0 / 4.0, 0.0, 0.0, 0.0, 0.0],
+        [3.0 / 32.0, 9.0 / 32.0, 0.0, 0.0, 0.0],
+        [1932.0 / 2197.0, -7200.0 / 2197.0, 7296.0 / 2197.0, 0.0, 0.0],
+        [439.0 / 216.0, -8.0, 3680.0 / 513.0, -845.0 / 4104.0, 0.0],
+        [-8.0 / 27.0, 2.0, -3544.0 / 2565.0, 1859.0 / 4104.0, -11.0 / 40.0],
+    ];
+    const B5: [f64; 6] = [
+        16.0 / 135.0,
+        0.0,
+        6656.0 / 12825.0,
+        28561.0 / 56430.0,
+        -9.0 / 50.0,
+        2.0 / 55.0,
+    ];
+    const B4: [f64; 6] = [
+        25.0 / 216.0,
+        0.0,
+        1408.0 / 2565.0,
+        2197.0 / 4104.0,
+        -1.0 / 5.0,
+        0.0,
+    ];
+
+    let mut k = [[0.0; N]; 6];
+    k[0] = f(y);
+    for stage in 1..6 {
+        let mut tmp = *y;
+        for (i, value) in tmp.iter_mut().enumerate() {
+            for (j, a) in A[stage - 1].iter().enumerate().take(stage) {
+                *value += h * a * k[j][i];
+            }
+        }
+        k[stage] = f(&tmp);
+    }
+
+    let mut next = *y;
+    let mut error: f64 = 0.0;
+    for i in 0..N {
+        let mut high = 0.0;
+        let mut low = 0.0;
+        for stage in 0..6 {
+            high += B5[stage] * k[stage][i];
+            low += B4[stage] * k[stage][i];
+        }
+        next[i] += h * high;
+        let scale = tolerance * (1.0 + y[i].abs().max(next[i].abs()));
+        error = error.max((h * (high - low)).abs() / scale);
+    }
+    (next, error)
+}
+
+// Standard step size update for a fourth order error estimate.
+pub(crate) fn next_step_size(h: f64, error: f64) -> f64 {
+    let factor = if error == 0.0 {
+        5.0
+    } else {
+        (0.9 * error.powf(-0.2)).clamp(0.2, 5.0)
+    };
+    h * factor
+}
+
+// Exact null geodesics in Schwarzschild spacetime.
+//
+// Photon orbits are planar, and with u = 1/r they obey u'' + u = 3 M u^2. Written in
+// Cartesian form this is x'' = -3 M h^2 x / r^5 with h = |x cross x'| conserved, the
+// same equation the shader's `accel` evaluates with a single Euler step. Here it is
+// integrated with adaptive RKF45 instead.
+pub struct SchwarzschildTracer {
+    pub settings: TraceSettings,
+}
+
+struct PathSample {
+    outcome: RayOutcome,
+    swept_angle: f64,
+    exit_position: DVec3,
+    exit_velocity: DVec3,
+}
+
+impl SchwarzschildTracer {
+    pub fn new(settings: TraceSettings) -> Self {
+        Self { settings }
+    }
+
+    fn integrate(&self, origin: DVec3, direction: DVec3) -> PathSample {
+        let direction = direction.normalize();
+        let h2 = origin.cross(direction).length_squared();
+        let rhs = |y: &[f64; 6]| -> [f64; 6] {
+            let pos = DVec3::new(y[0], y[1], y[2]);
+            let r2 = pos.length_squared();
+            let acc = pos * (-3.0 * MASS * h2 / (r2 * r2 * r2.sqrt()));
+            [y[3], y[4], y[5], acc.x, acc.y, acc.z]
+        };
+
+        let mut y = [
+            origin.x,
+            origin.y,
+            origin.z,
+            direction.x,
+            direction.y,
+            direction.z,
+        ];
+        let mut h = 0.01 * origin.length().max(SCHWARZSCHILD_RADIUS);
+        let mut swept_angle = 0.0;
+
+        for _ in 0..self.settings.max_steps {
+            let pos = DVec3::new(y[0], y[1], y[2]);
+            let vel = DVec3::new(y[3], y[4], y[5]);
+            let r = pos.length();
+
+            if r <= SCHWARZSCHILD_RADIUS {
+                return PathSample {
+                    outcome: RayOutcome::Horizon { position: pos },
+                    swept_angle,
+                    exit_position: pos,
+                    exit_velocity: vel,
+                };
+            }
+            if r >= self.settings.escape_radius && pos.dot(vel) > 0.0 {
+                return PathSample {
+                    outcome: RayOutcome::Escaped {
+                        direction: vel.normalize(),
+                    },
+                    swept_angle,
+                    exit_position: pos,
+                    exit_velocity: vel,
+                };
+            }
+
+            // Keep steps short relative to the radius so disk and horizon crossings
+            // are never skipped.
+            h = h.min(0.1 * r);
+            let (next, error) = rkf45_step(&rhs, &y, h, self.settings.tolerance);
+            if error > 1.0 {
+                h = next_step_size(h, error);
+                continue;
+            }
+
+            let next_pos = DVec3::new(next[0], next[1], next[2]);
+            let disk_hit = self.settings.disk.and_then(|disk| disk.crossing(pos, next_pos));
+            if let Some((position, radius)) = disk_hit {
+                return PathSample {
+                    outcome: RayOutcome::Disk { position, radius },
+                    swept_angle,
+                    exit_position: pos,
+                    exit_velocity: vel,
+                };
+            }
+
+            swept_angle += pos.cross(next_pos).length().atan2(pos.dot(next_pos));
+            y = next;
+            h = next_step_size(h, error);
+        }
+
+        let position = DVec3::new(y[0], y[1], y[2]);
+        let velocity = DVec3::new(y[3], y[4], y[5]);
+        PathSample {
+            outcome: RayOutcome::Unresolved {
+                position,
+                direction: velocity.normalize(),
+            },
+            swept_angle,
+            exit_position: position,
+            exit_velocity: velocity,
+        }
+    }
+
+    // Total bending of a ray that arrives from far away with impact parameter `b`,
+    // including any loops around the photon sphere. `None` if the ray is captured.
+    pub fn deflection_angle(&self, impact_parameter: f64) -> Option<f64> {
+        let distance = 0.5 * self.settings.escape_radius;
+        let origin = DVec3::new(impact_parameter, 0.0, -distance);
+        let sample = self.integrate(origin, DVec3::Z);
+        match sample.outcome {
+            RayOutcome::Escaped { .. } => {
+                // The ray starts and ends at finite distance; add the angle it still
+                // sweeps on both ends when continued in a straight line to infinity.
+                let head = origin.angle_between(-DVec3::Z);
+                let tail = sample.exit_position.angle_between(sample.exit_velocity);
+                Some(sample.swept_angle + head + tail - std::f64::consts::PI)
+            }
+            _ => None,
+        }
+    }
+
+    // Bisects the impact parameter separating captured from escaping rays.
+    pub fn capture_threshold(&self, mut captured: f64, mut escaping: f64, tolerance: f64) -> f64 {
+        let distance = 0.5 * self.settings.escape_radius;
+        while (escaping - captured).abs() > tolerance {
+            let mid = 0.5 * (captured + escaping);
+            let outcome = self.trace(DVec3::new(mid, 0.0, -distance), DVec3::Z);
+            if outcome.is_captured() {
+                captured = mid;
+            } else {
+                escaping = mid;
+            }
+        }
+        0.5 * (captured + escaping)
+    }
+}
+
+impl Default for SchwarzschildTracer {
+    fn default() -> Self {
+        Self::new(TraceSettings::default())
+    }
+}
+
+impl GeodesicTracer for SchwarzschildTracer {
+    fn trace(&self, origin: DVec3, direction: DVec3) -> RayOutcome {
+        self.integrate(origin, direction).outcome
+    }
+}
+
+// CPU port of the ray march in `traceColor`: fixed 300 iterations, single Euler
+// step of `accel` per iteration, plus the stylized spin terms (lateral push,
+// "D-shaped" hit radius and the skybox longitude twist).
+#[derive(Clone, Copy, Debug)]
+pub struct ShaderModel {
+    pub spin: f64,
+    pub gravitational_lensing: bool,
+    pub disk: Option<Disk>,
+}
+
+impl Default for ShaderModel {
+    fn default() -> Self {
+        Self {
+            spin: 0.0,
+            gravitational_lensing: true,
+            disk: None,
+        }
+    }
+}
+
+impl GeodesicTracer for ShaderModel {
+    fn trace(&self, origin: DVec3, direction: DVec3) -> RayOutcome {
+        let spin_axis = DVec3::Y;
+        let mut pos = origin;
+        let mut dir = direction.normalize();
+        let h2 = pos.cross(dir).length_squared();
+
+        for _ in 0..SHADER_MAX_STEPS {
+            let dist = pos.length();
+            let step_size = (dist * 0.05).max(0.02);
+
+            if self.gravitational_lensing {
+                let r5 = dist.powi(5);
+                let mut acc = pos * (-1.5 * h2 / r5);
+                if self.spin > 0.0 {
+                    let r3 = dist * dist * dist + SHADER_EPSILON;
+                    acc += dir.cross(spin_axis) * (self.spin / r3);
+                }
+                dir += acc * step_size;
+            }
+
+            let spin_dot = dir.normalize().dot(spin_axis);
+            let hit_radius = (1.0 - self.spin * 0.5 * spin_dot).max(0.5);
+            if dist < hit_radius {
+                return RayOutcome::Horizon { position: pos };
+            }
+
+            let next = pos + dir * step_size;
+            if let Some((position, radius)) = self.disk.and_then(|disk| disk.crossing(pos, next)) {
+                return RayOutcome::Disk { position, radius };
+            }
+            pos = next;
+        }
+
+        if self.spin > 0.0 {
+            let radius = pos.length().max(SHADER_EPSILON);
+            let phi_shift = (self.spin * 2.0) / radius;
+            dir = DQuat::from_axis_angle(spin_axis, -phi_shift) * dir;
+        }
+        RayOutcome::Escaped {
+            direction: dir.normalize(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DeflectionComparison {
+    pub reference: RayOutcome,
+    pub approximation: RayOutcome,
+    // Angle in radians between the two escape directions, when both rays escape.
+    pub angular_error: Option<f64>,
+}
+
+impl DeflectionComparison {
+    pub fn outcomes_agree(&self) -> bool {
+        std::mem::discriminant(&self.reference) == std::mem::discriminant(&self.approximation)
+    }
+}
+
+pub fn compare_rays(
+    reference: &impl GeodesicTracer,
+    approximation: &impl GeodesicTracer,
+    origin: DVec3,
+    direction: DVec3,
+) -> DeflectionComparison {
+    let reference = reference.trace(origin, direction);
+    let approximation = approximation.trace(origin, direction);
+    let angular_error = match (reference.escape_direction(), approximation.escape_direction()) {
+        (Some(a), Some(b)) => Some(a.dot(b).clamp(-1.0, 1.0).acos()),
+        _ => None,
+    };
+    DeflectionComparison {
+        reference,
+        approximation,
+        angular_error,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ComparisonSummary {
+    pub rays: usize,
+    pub mismatched_outcomes: usize,
+    pub mean_angular_error: f64,
+    pub max_angular_error: f64,
+}
+
+// Compares every `stride`-th pixel of a camera view.
+pub fn compare_view(
+    reference: &impl GeodesicTracer,
+    approximation: &impl GeodesicTracer,
+    camera: &RayCamera,
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> ComparisonSummary {
+    let stride = stride.max(1) as usize;
+    let mut summary = ComparisonSummary::default();
+    let mut escaped = 0;
+    for py in (0..height).step_by(stride) {
+        for px in (0..width).step_by(stride) {
+            let direction = camera.pixel_direction(px, py, width, height);
+            let comparison = compare_rays(reference, approximation, camera.position, direction);
+            summary.rays += 1;
+            if !comparison.outcomes_agree() {
+                summary.mismatched_outcomes += 1;
+            }
+            if let Some(error) = comparison.angular_error {
+                escaped += 1;
+                summary.mean_angular_error += error;
+                summary.max_angular_error = summary.max_angular_error.max(error);
+            }
+        }
+    }
+    if escaped > 0 {
+        summary.mean_angular_error /= escaped as f64;
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn critical_impact_parameter_is_3_sqrt_3_m() {
+        let expected = 3.0 * 3.0_f64.sqrt() * MASS;
+        assert!((CRITICAL_IMPACT_PARAMETER - expected).abs() < 1e-12);
+
+        let threshold = SchwarzschildTracer::default().capture_threshold(2.0, 3.5, 1e-7);
+        assert!(
+            (threshold - expected).abs() < 1e-5,
+            "capture threshold {} vs 3 sqrt(3) M = {}",
+            threshold,
+            expected
+        );
+    }
+
+    #[test]
+    fn weak_field_deflection_matches_4m_over_b() {
+        let b = 1000.0 * MASS;
+        let deflection = SchwarzschildTracer::default().deflection_angle(b).unwrap();
+        let expected = 4.0 * MASS / b;
+        // The next order term adds 15 pi M / (16 b) relative, about 0.3% here.
+        assert!(
+            (deflection / expected - 1.0).abs() < 0.01,
+            "deflection {} vs 4M/b = {}",
+            deflection,
+            expected
+        );
+    }
+}
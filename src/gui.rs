@@ -1,25 +1,249 @@
-use crate::app_state::AppState;
-use egui::{Context, Slider, Window};
+use crate::app_state::{AppState, UpscaleFilter};
+use crate::camera::{self, Bookmark, CameraMode, CameraPose};
+use crate::clock::Clock;
+use crate::presets::{self, Preset};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::recorder::{RecordFormat, RecordSettings};
+use crate::scene;
+use crate::shader_utils::Diagnostic;
+use crate::timeline::{Animatable, Interpolation, Timeline};
+use egui::{ComboBox, Context, DragValue, RichText, Slider, Window};
+use std::collections::BTreeMap;
+
+const PRESET_BLEND_SECONDS: f64 = 1.5;
+
+// Scene file operations are carried out by the event loop, which owns the clock.
+pub enum SceneAction {
+    Save(String),
+    Load(String),
+}
+
+// Recording needs the GL context, so like scene actions it is started and stopped
+// by the event loop.
+#[cfg(not(target_arch = "wasm32"))]
+pub enum RecordAction {
+    Start(RecordSettings),
+    Stop,
+}
+
+// Numeric settings and the camera pose glide from `from` to `to` on the live state;
+// toggles switch when the blend starts. A field that changes under the blend (a
+// slider, the camera controls, the timeline) drops out of it and keeps its new value.
+struct PresetBlend {
+    from: AppState,
+    to: AppState,
+    start: f64,
+    // The values the blend wrote last, to notice changes made since.
+    written: AppState,
+    // Fields no longer blended; "camera" stands for the pose.
+    released: Vec<&'static str>,
+}
 
 pub struct Gui {
-    // We can add local gui state here if needed
+    pub scene_path: String,
+    scene_status: String,
+    scene_action: Option<SceneAction>,
+    preset: Option<&'static Preset>,
+    preset_blend: Option<PresetBlend>,
+    key_interpolation: Interpolation,
+    key_field: &'static str,
+    bookmark_name: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    record: RecordUi,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct RecordUi {
+    format: RecordFormat,
+    path: String,
+    frame_limit: u64,
+    status: String,
+    action: Option<RecordAction>,
+    // Frames captured so far, while a recording is running.
+    active: Option<u64>,
 }
 
 impl Gui {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            scene_path: scene::DEFAULT_SCENE_PATH.to_string(),
+            scene_status: String::new(),
+            scene_action: None,
+            preset: None,
+            preset_blend: None,
+            key_interpolation: Interpolation::default(),
+            key_field: "spin",
+            bookmark_name: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            record: RecordUi {
+                format: RecordFormat::Png,
+                path: RecordFormat::Png.default_path().to_string(),
+                frame_limit: 0,
+                status: String::new(),
+                action: None,
+                active: None,
+            },
+        }
+    }
+
+    // Presets pick the look and the viewpoint; the camera mode, bookmarks and quality
+    // settings stay the user's.
+    fn start_preset(&mut self, preset: &'static Preset, state: &mut AppState, now: f64) {
+        let to = preset.state();
+        state.copy_toggles(&to);
+        self.preset = Some(preset);
+        self.preset_blend = Some(PresetBlend {
+            from: state.clone(),
+            to,
+            start: now,
+            written: state.clone(),
+            released: Vec::new(),
+        });
+    }
+
+    fn update_preset_blend(&mut self, state: &mut AppState, now: f64) {
+        let Some(blend) = &mut self.preset_blend else {
+            return;
+        };
+        let t = ((now - blend.start) / PRESET_BLEND_SECONDS).clamp(0.0, 1.0) as f32;
+        let t = t * t * (3.0 - 2.0 * t);
+
+        let fields = state
+            .numeric_fields_mut()
+            .into_iter()
+            .zip(blend.from.numeric_fields_mut())
+            .zip(blend.to.numeric_fields_mut())
+            .zip(blend.written.numeric_fields_mut());
+        for ((((name, value), (_, from)), (_, to)), (_, written)) in fields {
+            if blend.released.contains(&name) {
+                continue;
+            }
+            if *value != *written {
+                blend.released.push(name);
+                continue;
+            }
+            *value = *from + (*to - *from) * t;
+            *written = *value;
+        }
+        if !blend.released.contains(&"camera") {
+            // Auto-orbit turns the camera every frame; that keeps turning during the
+            // blend rather than counting as an edit.
+            let written = blend.written.camera.pose;
+            let live = state.camera.pose;
+            let only_turned = CameraPose {
+                yaw: live.yaw,
+                ..written
+            } == live;
+            if state.camera.auto_orbit != 0.0 && only_turned {
+                let turned = live.yaw - written.yaw;
+                blend.from.camera.pose.yaw += turned;
+                blend.to.camera.pose.yaw += turned;
+                blend.written.camera.pose.yaw = live.yaw;
+            }
+            if state.camera.pose != blend.written.camera.pose {
+                blend.released.push("camera");
+            } else {
+                let pose = CameraPose::lerp(blend.from.camera.pose, blend.to.camera.pose, t);
+                state.camera.pose = pose;
+                blend.written.camera.pose = pose;
+            }
+        }
+
+        if t >= 1.0 {
+            self.preset_blend = None;
+        }
+    }
+
+    pub fn take_scene_action(&mut self) -> Option<SceneAction> {
+        self.scene_action.take()
     }
 
-    pub fn ui(&mut self, ctx: &Context, state: &mut AppState, fps: f32) {
+    pub fn set_scene_status(&mut self, status: impl Into<String>) {
+        self.scene_status = status.into();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn take_record_action(&mut self) -> Option<RecordAction> {
+        self.record.action.take()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_record_state(&mut self, active: Option<u64>, status: impl Into<String>) {
+        self.record.active = active;
+        self.record.status = status.into();
+    }
+
+    pub fn ui(
+        &mut self,
+        ctx: &Context,
+        state: &mut AppState,
+        timeline: &mut Timeline,
+        clock: &mut Clock,
+        fps: f32,
+        // Progressive samples so far, and where refinement stops.
+        (samples, sample_target): (u32, u32),
+    ) {
+        let now = ctx.input(|i| i.time);
+        self.update_preset_blend(state, now);
+
         Window::new("Settings").show(ctx, |ui| {
-            ui.label(format!("FPS: {:.1}", fps));
+            ui.label(format!(
+                "FPS: {:.1}  Scale: {:.0}%",
+                fps,
+                state.quality.render_scale * 100.0
+            ));
+            if samples > 0 {
+                ui.label(format!("Samples: {} / {}", samples.min(sample_target), sample_target));
+            }
+            ui.separator();
+
+            ui.heading("Time");
+            clock_ui(ui, clock);
+
+            ui.separator();
+            ui.heading("Scene");
+            let mut selected = None;
+            ComboBox::from_label("Preset")
+                .selected_text(self.preset.map_or("Custom", |preset| preset.name))
+                .show_ui(ui, |ui| {
+                    for preset in presets::PRESETS {
+                        let current = self.preset.is_some_and(|p| std::ptr::eq(p, preset));
+                        if ui
+                            .selectable_label(current, preset.name)
+                            .on_hover_text(preset.description)
+                            .clicked()
+                        {
+                            selected = Some(preset);
+                        }
+                    }
+                });
+            if let Some(preset) = selected {
+                self.start_preset(preset, state, now);
+            }
+            ui.text_edit_singleline(&mut self.scene_path);
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    self.scene_action = Some(SceneAction::Save(self.scene_path.clone()));
+                }
+                if ui.button("Load").clicked() {
+                    self.scene_action = Some(SceneAction::Load(self.scene_path.clone()));
+                }
+            });
+            if !self.scene_status.is_empty() {
+                ui.label(&self.scene_status);
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                ui.separator();
+                ui.heading("Record");
+                self.record_ui(ui, clock);
+            }
+
             ui.separator();
 
             ui.heading("Camera");
-            ui.add(Slider::new(&mut state.camera_roll, -180.0..=180.0).text("Roll"));
-            ui.checkbox(&mut state.mouse_control, "Mouse Control");
-            ui.checkbox(&mut state.front_view, "Front View");
-            ui.checkbox(&mut state.top_view, "Top View");
+            self.camera_ui(ui, state);
 
             ui.separator();
             ui.heading("Rendering");
@@ -28,6 +252,43 @@ impl Gui {
             ui.checkbox(&mut state.tonemapping_enabled, "ACES Tonemapping");
             ui.add(Slider::new(&mut state.bloom_strength, 0.0..=1.0).text("Bloom Strength"));
             ui.add(Slider::new(&mut state.gamma, 0.1..=5.0).text("Gamma"));
+            let quality = &mut state.quality;
+            ui.add_enabled(
+                !quality.dynamic_resolution,
+                Slider::new(&mut quality.render_scale, 0.25..=1.0)
+                    .custom_formatter(|v, _| format!("{:.0}%", v * 100.0))
+                    .text("Render Scale"),
+            );
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut quality.dynamic_resolution, "Dynamic Resolution");
+                ui.add_enabled(
+                    quality.dynamic_resolution,
+                    DragValue::new(&mut quality.target_frame_ms)
+                        .range(4.0..=100.0)
+                        .speed(0.1)
+                        .prefix("Target: ")
+                        .suffix(" ms"),
+                );
+            });
+            ui.add_enabled_ui(quality.render_scale < 1.0 || quality.dynamic_resolution, |ui| {
+                ComboBox::from_label("Upscale")
+                    .selected_text(quality.upscale_filter.label())
+                    .show_ui(ui, |ui| {
+                        for f in UpscaleFilter::ALL {
+                            ui.selectable_value(&mut quality.upscale_filter, f, f.label());
+                        }
+                    });
+            });
+            ui.checkbox(&mut quality.taa, "Temporal Anti-Aliasing");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut quality.progressive, "Progressive Refinement");
+                ui.add_enabled(
+                    quality.progressive,
+                    DragValue::new(&mut quality.progressive_samples)
+                        .range(1..=4096)
+                        .suffix(" spp"),
+                );
+            });
 
             ui.separator();
             ui.heading("Black Hole");
@@ -53,5 +314,415 @@ impl Gui {
             ui.add(Slider::new(&mut state.adisk_noise_lod, 1.0..=10.0).text("Noise LOD"));
 
         });
+
+        self.timeline_ui(ctx, state, timeline, clock);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn record_ui(&mut self, ui: &mut egui::Ui, clock: &Clock) {
+        let record = &mut self.record;
+        ui.add_enabled_ui(record.active.is_none(), |ui| {
+            let mut format = record.format;
+            ComboBox::from_label("Format")
+                .selected_text(format.label())
+                .show_ui(ui, |ui| {
+                    for f in RecordFormat::ALL {
+                        ui.selectable_value(&mut format, f, f.label());
+                    }
+                });
+            if format != record.format {
+                if record.path == record.format.default_path() {
+                    record.path = format.default_path().to_string();
+                }
+                record.format = format;
+            }
+            ui.text_edit_singleline(&mut record.path);
+            ui.add(
+                DragValue::new(&mut record.frame_limit)
+                    .range(0..=1_000_000)
+                    .prefix("Frames: ")
+                    .custom_formatter(|n, _| {
+                        if n == 0.0 { "unlimited".into() } else { format!("{}", n) }
+                    }),
+            );
+        });
+        ui.horizontal(|ui| match record.active {
+            None => {
+                if ui.button("Start Recording").clicked() {
+                    record.action = Some(RecordAction::Start(RecordSettings {
+                        format: record.format,
+                        path: record.path.clone().into(),
+                        fps: clock.fps,
+                        frame_limit: record.frame_limit,
+                    }));
+                }
+            }
+            Some(frames) => {
+                if ui.button("Stop Recording").clicked() {
+                    record.action = Some(RecordAction::Stop);
+                }
+                ui.label(format!("{} frames", frames));
+            }
+        });
+        if !record.status.is_empty() {
+            ui.label(&record.status);
+        }
+    }
+
+    fn camera_ui(&mut self, ui: &mut egui::Ui, state: &mut AppState) {
+        let camera = &mut state.camera;
+        let mut mode = camera.mode;
+        ComboBox::from_label("Mode")
+            .selected_text(mode.label())
+            .show_ui(ui, |ui| {
+                for m in CameraMode::ALL {
+                    ui.selectable_value(&mut mode, m, m.label());
+                }
+            });
+        if mode != camera.mode {
+            camera.set_mode(mode);
+        }
+        ui.label(match camera.mode {
+            CameraMode::Orbit => "Drag to orbit the hole, scroll to zoom.",
+            CameraMode::FreeFly => {
+                "WASD to move, Q/E down/up, Shift faster, drag to look, scroll for FOV."
+            }
+            CameraMode::Inspect => "Left-drag to orbit, right-drag to pan, scroll to zoom.",
+        });
+
+        ui.add(Slider::new(&mut state.camera_roll, -180.0..=180.0).text("Roll"));
+        ui.add(Slider::new(&mut camera.pose.fov, 10.0..=120.0).text("FOV"));
+        match camera.mode {
+            CameraMode::Orbit => {
+                ui.add(
+                    Slider::new(&mut camera.pose.distance, 2.0..=100.0)
+                        .logarithmic(true)
+                        .text("Distance"),
+                );
+                ui.add(Slider::new(&mut camera.auto_orbit, -30.0..=30.0).text("Auto Orbit"));
+            }
+            CameraMode::FreeFly => {
+                ui.add(
+                    Slider::new(&mut camera.fly_speed, 0.5..=50.0)
+                        .logarithmic(true)
+                        .text("Fly Speed"),
+                );
+            }
+            CameraMode::Inspect => {
+                ui.add(
+                    Slider::new(&mut camera.pose.distance, 1.5..=100.0)
+                        .logarithmic(true)
+                        .text("Distance"),
+                );
+                if ui.button("Recenter on Hole").clicked() {
+                    camera.pose.target = [0.0; 3];
+                }
+            }
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            for (name, pose) in camera::builtin_bookmarks() {
+                if ui.button(name).clicked() {
+                    camera.pose = CameraPose {
+                        fov: camera.pose.fov,
+                        ..pose
+                    };
+                }
+            }
+        });
+        let mut remove = None;
+        for (i, bookmark) in camera.bookmarks.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button(&bookmark.name).clicked() {
+                    camera.pose = bookmark.pose;
+                }
+                if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            camera.bookmarks.remove(i);
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.bookmark_name).desired_width(120.0));
+            if ui.button("Add Bookmark").clicked() {
+                let name = match self.bookmark_name.trim() {
+                    "" => format!("View {}", camera.bookmarks.len() + 1),
+                    name => name.to_string(),
+                };
+                camera.bookmarks.push(Bookmark {
+                    name,
+                    pose: camera.pose,
+                });
+                self.bookmark_name.clear();
+            }
+        });
+    }
+
+    fn timeline_ui(
+        &mut self,
+        ctx: &Context,
+        state: &mut AppState,
+        timeline: &mut Timeline,
+        clock: &mut Clock,
+    ) {
+        Window::new("Timeline").default_open(false).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let label = if timeline.playing { "Pause" } else { "Play" };
+                if ui.button(label).clicked() {
+                    if !timeline.playing && timeline.playhead >= timeline.duration {
+                        timeline.seek(0.0);
+                    }
+                    timeline.playing = !timeline.playing;
+                    // The playhead follows the animation clock, so playing needs it running.
+                    if timeline.playing {
+                        clock.paused = false;
+                    }
+                }
+                if ui.button("Stop").clicked() {
+                    timeline.playing = false;
+                    timeline.seek(0.0);
+                }
+                ui.checkbox(&mut timeline.looping, "Loop");
+                ui.add(
+                    DragValue::new(&mut timeline.duration)
+                        .range(0.1..=600.0)
+                        .speed(0.1)
+                        .suffix(" s"),
+                );
+            });
+
+            let mut playhead = timeline.playhead;
+            let scrub = ui.add(
+                Slider::new(&mut playhead, 0.0..=timeline.duration)
+                    .text("Time")
+                    .suffix(" s"),
+            );
+            if scrub.changed() {
+                timeline.seek(playhead);
+            }
+
+            ui.separator();
+            ComboBox::from_label("Interpolation")
+                .selected_text(self.key_interpolation.label())
+                .show_ui(ui, |ui| {
+                    for mode in Interpolation::ALL {
+                        ui.selectable_value(&mut self.key_interpolation, mode, mode.label());
+                    }
+                });
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source("key_field")
+                    .selected_text(self.key_field)
+                    .show_ui(ui, |ui| {
+                        for (name, _) in state.numeric_fields_mut() {
+                            ui.selectable_value(&mut self.key_field, name, name);
+                        }
+                    });
+                if ui.button("Key").clicked() {
+                    timeline.key_field(state, self.key_field, self.key_interpolation);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Key Camera").clicked() {
+                    timeline.key_camera(state, self.key_interpolation);
+                }
+                if ui.button("Key All").clicked() {
+                    let names = state.numeric_fields_mut().map(|(name, _)| name);
+                    for name in names {
+                        timeline.key_field(state, name, self.key_interpolation);
+                    }
+                    timeline.key_camera(state, self.key_interpolation);
+                }
+                if ui.button("Clear").clicked() {
+                    timeline.clear();
+                }
+            });
+
+            ui.separator();
+            egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                if !timeline.camera.is_empty() {
+                    let title = format!("camera ({})", timeline.camera.keys.len());
+                    egui::CollapsingHeader::new(title).id_source("camera").show(ui, |ui| {
+                        let mut remove = None;
+                        for (i, key) in timeline.camera.keys.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.add(DragValue::new(&mut key.time).speed(0.01).suffix(" s"));
+                                ui.add(
+                                    DragValue::new(&mut key.value.yaw)
+                                        .speed(0.5)
+                                        .prefix("yaw "),
+                                );
+                                ui.add(
+                                    DragValue::new(&mut key.value.pitch)
+                                        .speed(0.5)
+                                        .prefix("pitch "),
+                                );
+                                ui.add(
+                                    DragValue::new(&mut key.value.distance)
+                                        .speed(0.1)
+                                        .prefix("dist "),
+                                );
+                                interpolation_combo(ui, ("camera", i), &mut key.interpolation);
+                                if ui.small_button("x").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = remove {
+                            timeline.camera.keys.remove(i);
+                        }
+                        timeline.camera.sort();
+                    });
+                }
+                for field_track in &mut timeline.tracks {
+                    let track = &mut field_track.track;
+                    let title = format!("{} ({})", field_track.field, track.keys.len());
+                    egui::CollapsingHeader::new(title).id_source(&field_track.field).show(ui, |ui| {
+                        let mut remove = None;
+                        for (i, key) in track.keys.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.add(DragValue::new(&mut key.time).speed(0.01).suffix(" s"));
+                                ui.add(DragValue::new(&mut key.value).speed(0.01));
+                                interpolation_combo(
+                                    ui,
+                                    (field_track.field.as_str(), i),
+                                    &mut key.interpolation,
+                                );
+                                if ui.small_button("x").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = remove {
+                            track.keys.remove(i);
+                        }
+                        track.sort();
+                    });
+                }
+                timeline.tracks.retain(|t| !t.track.is_empty());
+            });
+        });
+    }
+}
+
+fn clock_ui(ui: &mut egui::Ui, clock: &mut Clock) {
+    ui.horizontal(|ui| {
+        let label = if clock.paused { "Play" } else { "Pause" };
+        if ui.button(label).clicked() {
+            clock.paused = !clock.paused;
+        }
+        if ui.button("Reverse").clicked() {
+            clock.reverse();
+        }
+        if ui.button("<").on_hover_text("Step back one frame").clicked() {
+            clock.step_frames(-1);
+        }
+        if ui.button(">").on_hover_text("Step forward one frame").clicked() {
+            clock.step_frames(1);
+        }
+    });
+    ui.add(Slider::new(&mut clock.speed, -4.0..=4.0).text("Speed"));
+    let mut time = clock.time();
+    if ui
+        .add(DragValue::new(&mut time).speed(0.05).prefix("Time: ").suffix(" s"))
+        .changed()
+    {
+        clock.seek(time);
+    }
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut clock.fixed_step, "Fixed Step");
+        ui.add(
+            DragValue::new(&mut clock.fps)
+                .range(1.0..=240.0)
+                .speed(1.0)
+                .suffix(" fps"),
+        );
+    });
+}
+
+fn interpolation_combo(ui: &mut egui::Ui, id: impl std::hash::Hash, value: &mut Interpolation) {
+    ComboBox::from_id_source(id)
+        .selected_text(value.label())
+        .show_ui(ui, |ui| {
+            for mode in Interpolation::ALL {
+                ui.selectable_value(value, mode, mode.label());
+            }
+        });
+}
+
+// Compile errors of shaders that failed to build; their last working programs keep
+// rendering until the file is fixed.
+pub fn shader_errors_ui(ctx: &Context, errors: &BTreeMap<String, Vec<Diagnostic>>) {
+    if errors.is_empty() {
+        return;
+    }
+    Window::new("Shader Errors").show(ctx, |ui| {
+        ui.label(
+            "These shaders failed to build. Passes that built before keep their last working \
+             version; the others draw magenta until the shader is fixed.",
+        );
+        let error_color = ui.visuals().error_fg_color;
+        let highlight = ui.visuals().extreme_bg_color;
+        for (name, diagnostics) in errors {
+            ui.separator();
+            ui.strong(name);
+            for diagnostic in diagnostics {
+                ui.label(RichText::new(&diagnostic.message).monospace().color(error_color));
+                let failing = diagnostic.location.as_ref().map(|(_, line)| *line);
+                for (line, text) in &diagnostic.context {
+                    let text = RichText::new(format!("{:>5} | {}", line, text)).monospace();
+                    if Some(*line) == failing {
+                        ui.label(text.color(error_color).background_color(highlight));
+                    } else {
+                        ui.label(text.weak());
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blend_to_m87(state: &mut AppState, edit: impl Fn(&mut AppState)) {
+        let mut gui = Gui::new();
+        gui.start_preset(presets::find("M87*").unwrap(), state, 0.0);
+        gui.update_preset_blend(state, 0.5);
+        edit(state);
+        gui.update_preset_blend(state, 1.0);
+        gui.update_preset_blend(state, PRESET_BLEND_SECONDS);
+        assert!(gui.preset_blend.is_none());
+    }
+
+    #[test]
+    fn preset_blend_keeps_quality_and_camera_mode() {
+        let mut state = AppState::default();
+        state.quality.render_scale = 0.5;
+        state.camera.mode = CameraMode::FreeFly;
+        blend_to_m87(&mut state, |_| {});
+
+        let mut target = presets::find("M87*").unwrap().state();
+        target.quality = state.quality.clone();
+        target.camera.mode = CameraMode::FreeFly;
+        assert_eq!(state.quality.render_scale, 0.5);
+        assert!(state == target);
+    }
+
+    #[test]
+    fn editing_a_field_cancels_its_blend() {
+        let mut state = AppState::default();
+        let pose = state.camera.pose;
+        blend_to_m87(&mut state, |state| {
+            state.spin = 0.1;
+            state.camera.pose = pose;
+        });
+
+        let target = presets::find("M87*").unwrap().state();
+        assert_eq!(state.spin, 0.1);
+        assert_eq!(state.camera.pose, pose);
+        assert_eq!(state.adisk_height, target.adisk_height);
     }
 }
@@ -0,0 +1,196 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+// Offscreen OpenGL contexts for rendering without a visible window. On Linux this
+// prefers an EGL device with a surfaceless context (Mesa llvmpipe works, no display
+// server needed) and falls back to a hidden window everywhere else.
+
+use glutin::config::{Api, ConfigSurfaceTypes, ConfigTemplateBuilder};
+use glutin::context::{ContextApi, ContextAttributesBuilder, GlProfile, Version};
+use glutin::display::GetGlDisplay;
+use glutin::prelude::*;
+use glutin_winit::{DisplayBuilder, GlWindow};
+use raw_window_handle::HasRawWindowHandle;
+use std::ffi::CString;
+use winit::event_loop::EventLoop;
+use winit::window::WindowBuilder;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeadlessBackend {
+    Auto,
+    Egl,
+    HiddenWindow,
+}
+
+impl std::str::FromStr for HeadlessBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "egl" => Ok(Self::Egl),
+            "window" => Ok(Self::HiddenWindow),
+            _ => Err(anyhow::anyhow!(
+                "Unknown GL backend `{}` (expected auto, egl or window)",
+                s
+            )),
+        }
+    }
+}
+
+#[allow(dead_code)]
+struct HiddenWindow {
+    context: glutin::context::PossiblyCurrentContext,
+    surface: glutin::surface::Surface<glutin::surface::WindowSurface>,
+    window: winit::window::Window,
+    event_loop: EventLoop<()>,
+}
+
+// Owns whatever keeps the context alive; dropping it tears the context down.
+#[allow(dead_code)]
+enum ContextHolder {
+    #[cfg(target_os = "linux")]
+    Egl(glutin::api::egl::context::PossiblyCurrentContext),
+    Window(Box<HiddenWindow>),
+}
+
+pub struct HeadlessGl {
+    pub gl: glow::Context,
+    _holder: ContextHolder,
+}
+
+fn context_attributes(
+    raw_window_handle: Option<raw_window_handle::RawWindowHandle>,
+) -> glutin::context::ContextAttributes {
+    ContextAttributesBuilder::new()
+        .with_context_api(ContextApi::OpenGl(Some(Version::new(3, 3))))
+        .with_profile(GlProfile::Core)
+        .build(raw_window_handle)
+}
+
+impl HeadlessGl {
+    pub fn new(backend: HeadlessBackend) -> anyhow::Result<Self> {
+        match backend {
+            HeadlessBackend::Egl => unsafe { Self::new_egl() },
+            HeadlessBackend::HiddenWindow => unsafe { Self::new_hidden_window() },
+            HeadlessBackend::Auto => unsafe {
+                Self::new_egl().or_else(|egl_err| {
+                    Self::new_hidden_window().map_err(|window_err| {
+                        anyhow::anyhow!(
+                            "No offscreen GL context available. EGL: {}. Hidden window: {}",
+                            egl_err,
+                            window_err
+                        )
+                    })
+                })
+            },
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn new_egl() -> anyhow::Result<Self> {
+        use glutin::api::egl::device::Device;
+        use glutin::api::egl::display::Display;
+
+        let devices = Device::query_devices()
+            .map_err(|e| anyhow::anyhow!("EGL device query failed: {}", e))?;
+
+        let mut last_err = anyhow::anyhow!("No EGL devices found");
+        for device in devices {
+            let display = match Display::with_device(&device, None) {
+                Ok(display) => display,
+                Err(e) => {
+                    last_err = anyhow::anyhow!("EGL display creation failed: {}", e);
+                    continue;
+                }
+            };
+
+            let template = ConfigTemplateBuilder::new()
+                .with_surface_type(ConfigSurfaceTypes::empty())
+                .with_api(Api::OPENGL)
+                .with_depth_size(0)
+                .with_stencil_size(0)
+                .build();
+            let config = match display.find_configs(template) {
+                Ok(mut configs) => match configs.next() {
+                    Some(config) => config,
+                    None => {
+                        last_err = anyhow::anyhow!("EGL device has no OpenGL configs");
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    last_err = anyhow::anyhow!("EGL config query failed: {}", e);
+                    continue;
+                }
+            };
+
+            let context = match display
+                .create_context(&config, &context_attributes(None))
+                .and_then(|context| context.make_current_surfaceless())
+            {
+                Ok(context) => context,
+                Err(e) => {
+                    last_err = anyhow::anyhow!("EGL context creation failed: {}", e);
+                    continue;
+                }
+            };
+
+            let gl = glow::Context::from_loader_function(|s| {
+                display.get_proc_address(&CString::new(s).unwrap()) as *const _
+            });
+            return Ok(Self {
+                gl,
+                _holder: ContextHolder::Egl(context),
+            });
+        }
+
+        Err(last_err)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    unsafe fn new_egl() -> anyhow::Result<Self> {
+        Err(anyhow::anyhow!(
+            "Surfaceless EGL is only supported on Linux"
+        ))
+    }
+
+    unsafe fn new_hidden_window() -> anyhow::Result<Self> {
+        let event_loop = EventLoop::new()
+            .map_err(|e| anyhow::anyhow!("Event loop error: {}", e))?;
+        let window_builder = WindowBuilder::new()
+            .with_title("Blackhole Rust (offscreen)")
+            .with_visible(false);
+        let template = ConfigTemplateBuilder::new().with_alpha_size(8);
+        let display_builder = DisplayBuilder::new().with_window_builder(Some(window_builder));
+
+        let (window, gl_config) = display_builder
+            .build(&event_loop, template, |mut configs| configs.next().unwrap())
+            .map_err(|e| anyhow::anyhow!("Display builder error: {}", e))?;
+        let window = window.ok_or(anyhow::anyhow!("Failed to create window"))?;
+        let gl_display = gl_config.display();
+
+        let not_current = gl_display
+            .create_context(&gl_config, &context_attributes(Some(window.raw_window_handle())))
+            .map_err(|e| anyhow::anyhow!("Failed to create context: {}", e))?;
+        let attrs =
+            window.build_surface_attributes(glutin::surface::SurfaceAttributesBuilder::new());
+        let surface = gl_display
+            .create_window_surface(&gl_config, &attrs)
+            .map_err(|e| anyhow::anyhow!("Failed to create surface: {}", e))?;
+        let context = not_current
+            .make_current(&surface)
+            .map_err(|e| anyhow::anyhow!("Failed to make context current: {}", e))?;
+
+        let gl = glow::Context::from_loader_function(|s| {
+            gl_display.get_proc_address(&CString::new(s).unwrap()) as *const _
+        });
+        Ok(Self {
+            gl,
+            _holder: ContextHolder::Window(Box::new(HiddenWindow {
+                context,
+                surface,
+                window,
+                event_loop,
+            })),
+        })
+    }
+}
@@ -0,0 +1,415 @@
+// CPU null-geodesic integrator for a rotating (Kerr) black hole in Boyer-Lindquist
+// coordinates.
+//
+// Uses the same units as `geodesic.rs` (M = 0.5) and takes the dimensionless spin
+// a/M from `AppState::spin`. The spin axis is world +Y, and positive spin co-rotates
+// with the shader's accretion disk. Photons follow Hamilton's equations for
+// H = (Delta p_r^2 + p_theta^2 - P^2 / Delta + (L - a E sin^2)^2 / sin^2) / (2 Sigma),
+// so E = -p_t and L = p_phi are conserved exactly and the Carter constant is
+// monitored as a measure of integration error.
+//
+// Ray directions are measured by a zero angular momentum observer at the camera, i.e.
+// what a physical camera there would see. `SchwarzschildTracer` and the shader use
+// coordinate directions instead, so at spin 0 the two differ by the aberration
+// between those frames (of order M / r at the camera).
+
+use glam::DVec3;
+
+use crate::geodesic::{
+    next_step_size, rkf45_step, Disk, GeodesicTracer, RayOutcome, TraceSettings,
+    CRITICAL_IMPACT_PARAMETER, MASS,
+};
+
+// State vector layout for the integrator.
+const R: usize = 0;
+const THETA: usize = 1;
+const PHI: usize = 2;
+const T: usize = 3;
+const P_R: usize = 4;
+const P_THETA: usize = 5;
+
+// Rays are considered captured once they get this close to the outer horizon.
+const HORIZON_MARGIN: f64 = 1.0e-3;
+// Keeps the integrator away from the coordinate singularity on the spin axis.
+const MIN_SIN_THETA: f64 = 1.0e-9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConstantsOfMotion {
+    pub energy: f64,
+    pub angular_momentum: f64,
+    pub carter: f64,
+}
+
+impl ConstantsOfMotion {
+    // Largest relative change of any constant between `self` and `other`.
+    pub fn drift(&self, other: &ConstantsOfMotion) -> f64 {
+        let relative = |a: f64, b: f64| (a - b).abs() / a.abs().max(b.abs()).max(1.0);
+        relative(self.energy, other.energy)
+            .max(relative(self.angular_momentum, other.angular_momentum))
+            .max(relative(self.carter, other.carter))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct KerrTrace {
+    pub outcome: RayOutcome,
+    pub initial: ConstantsOfMotion,
+    pub last: ConstantsOfMotion,
+    // |H| at the end of the path; zero for an exact null geodesic.
+    pub hamiltonian_residual: f64,
+    // Boyer-Lindquist time elapsed along the path.
+    pub coordinate_time: f64,
+    pub steps: usize,
+}
+
+pub struct KerrTracer {
+    pub spin: f64,
+    pub settings: TraceSettings,
+}
+
+impl KerrTracer {
+    pub fn new(spin: f64, settings: TraceSettings) -> Self {
+        Self {
+            spin: spin.clamp(-0.9999, 0.9999),
+            settings,
+        }
+    }
+
+    pub fn from_app_spin(spin: f32) -> Self {
+        Self::new(spin as f64, TraceSettings::default())
+    }
+
+    pub fn a(&self) -> f64 {
+        self.spin * MASS
+    }
+
+    pub fn horizon_radius(&self) -> f64 {
+        let a = self.a();
+        MASS + (MASS * MASS - a * a).sqrt()
+    }
+
+    pub fn ergosphere_radius(&self, theta: f64) -> f64 {
+        let a = self.a();
+        MASS + (MASS * MASS - a * a * theta.cos().powi(2)).sqrt()
+    }
+
+    // Radius of the circular equatorial photon orbit; `prograde` orbits co-rotate
+    // with the hole.
+    pub fn photon_orbit_radius(&self, prograde: bool) -> f64 {
+        let chi = if prograde { -self.spin } else { self.spin };
+        2.0 * MASS * (1.0 + ((2.0 / 3.0) * chi.clamp(-1.0, 1.0).acos()).cos())
+    }
+
+    // Angular velocity of the locally non-rotating frame (frame dragging rate).
+    pub fn frame_dragging_rate(&self, r: f64, theta: f64) -> f64 {
+        let a = self.a();
+        let delta = r * r - 2.0 * MASS * r + a * a;
+        let big_a = (r * r + a * a).powi(2) - a * a * delta * theta.sin().powi(2);
+        2.0 * MASS * a * r / big_a
+    }
+
+    // Boyer-Lindquist (r, theta, phi) of a world position. Uses the Kerr-Schild
+    // oblate spheroidal relation so the mapping is exact at large radius.
+    pub fn to_boyer_lindquist(&self, position: DVec3) -> (f64, f64, f64) {
+        let a2 = self.a() * self.a();
+        let rho2 = position.length_squared();
+        let y2 = position.y * position.y;
+        let b = rho2 - a2;
+        let r = (0.5 * (b + (b * b + 4.0 * a2 * y2).sqrt())).sqrt();
+        let cos_theta = if r > 0.0 { (position.y / r).clamp(-1.0, 1.0) } else { 0.0 };
+        let phi = (-position.z).atan2(position.x);
+        (r, cos_theta.acos(), phi)
+    }
+
+    pub fn to_world(&self, r: f64, theta: f64, phi: f64) -> DVec3 {
+        let a = self.a();
+        let rho = (r * r + a * a).sqrt() * theta.sin();
+        DVec3::new(rho * phi.cos(), r * theta.cos(), -rho * phi.sin())
+    }
+
+    // Orthonormal (r, theta, phi) directions at a point, expressed in world space.
+    fn spherical_basis(theta: f64, phi: f64) -> (DVec3, DVec3, DVec3) {
+        let (st, ct) = theta.sin_cos();
+        let (sp, cp) = phi.sin_cos();
+        let e_r = DVec3::new(st * cp, ct, -st * sp);
+        let e_theta = DVec3::new(ct * cp, -st, -ct * sp);
+        let e_phi = DVec3::new(-sp, 0.0, -cp);
+        (e_r, e_theta, e_phi)
+    }
+
+    // Initial state for a photon leaving `origin` along the world `direction`, as seen
+    // by a zero angular momentum observer there. Returns (state, E, L).
+    fn initial_state(&self, origin: DVec3, direction: DVec3) -> ([f64; 6], f64, f64) {
+        let a = self.a();
+        let (r, theta, phi) = self.to_boyer_lindquist(origin);
+        let theta = theta.clamp(MIN_SIN_THETA, std::f64::consts::PI - MIN_SIN_THETA);
+        let (e_r, e_theta, e_phi) = Self::spherical_basis(theta, phi);
+        let n = direction.normalize();
+        let (n_r, n_theta, n_phi) = (n.dot(e_r), n.dot(e_theta), n.dot(e_phi));
+
+        let sin2 = theta.sin().powi(2);
+        let sigma = r * r + a * a * theta.cos().powi(2);
+        let delta = r * r - 2.0 * MASS * r + a * a;
+        let big_a = (r * r + a * a).powi(2) - a * a * delta * sin2;
+        let alpha = (sigma * delta / big_a).sqrt();
+        let omega = 2.0 * MASS * a * r / big_a;
+        let varpi = (big_a / sigma).sqrt() * theta.sin();
+
+        let angular_momentum = n_phi * varpi;
+        let energy = alpha + omega * angular_momentum;
+        let p_r = n_r * (sigma / delta).sqrt();
+        let p_theta = n_theta * sigma.sqrt();
+
+        ([r, theta, phi, 0.0, p_r, p_theta], energy, angular_momentum)
+    }
+
+    // World-space direction of travel for a state, in the local static frame.
+    fn world_direction(&self, y: &[f64; 6], angular_momentum: f64) -> DVec3 {
+        let a = self.a();
+        let (r, theta) = (y[R], y[THETA]);
+        let sin2 = theta.sin().powi(2).max(MIN_SIN_THETA);
+        let sigma = r * r + a * a * theta.cos().powi(2);
+        let delta = r * r - 2.0 * MASS * r + a * a;
+        let big_a = (r * r + a * a).powi(2) - a * a * delta * sin2;
+        let varpi = (big_a / sigma).sqrt() * sin2.sqrt();
+
+        let n_r = y[P_R] * (delta / sigma).sqrt();
+        let n_theta = y[P_THETA] / sigma.sqrt();
+        let n_phi = angular_momentum / varpi;
+        let (e_r, e_theta, e_phi) = Self::spherical_basis(theta, y[PHI]);
+        (e_r * n_r + e_theta * n_theta + e_phi * n_phi).normalize()
+    }
+
+    fn constants(&self, y: &[f64; 6], energy: f64, angular_momentum: f64) -> ConstantsOfMotion {
+        let a = self.a();
+        let (s, c) = y[THETA].sin_cos();
+        let sin2 = (s * s).max(MIN_SIN_THETA);
+        let carter = y[P_THETA] * y[P_THETA]
+            + c * c * (angular_momentum * angular_momentum / sin2 - a * a * energy * energy);
+        ConstantsOfMotion {
+            energy,
+            angular_momentum,
+            carter,
+        }
+    }
+
+    fn hamiltonian(&self, y: &[f64; 6], energy: f64, angular_momentum: f64) -> f64 {
+        let a = self.a();
+        let (r, theta) = (y[R], y[THETA]);
+        let (s, c) = theta.sin_cos();
+        let sin2 = (s * s).max(MIN_SIN_THETA);
+        let sigma = r * r + a * a * c * c;
+        let delta = r * r - 2.0 * MASS * r + a * a;
+        let p = energy * (r * r + a * a) - a * angular_momentum;
+        let l_term = angular_momentum - a * energy * sin2;
+        let n = delta * y[P_R] * y[P_R] + y[P_THETA] * y[P_THETA] - p * p / delta
+            + l_term * l_term / sin2;
+        n / (2.0 * sigma)
+    }
+
+    fn derivatives(&self, y: &[f64; 6], energy: f64, angular_momentum: f64) -> [f64; 6] {
+        let a = self.a();
+        let (e, l) = (energy, angular_momentum);
+        let (r, theta, p_r, p_theta) = (y[R], y[THETA], y[P_R], y[P_THETA]);
+        let (s, c) = theta.sin_cos();
+        let s = if s.abs() < MIN_SIN_THETA {
+            MIN_SIN_THETA.copysign(s)
+        } else {
+            s
+        };
+        let sin2 = s * s;
+
+        let r2a2 = r * r + a * a;
+        let sigma = r * r + a * a * c * c;
+        let delta = r2a2 - 2.0 * MASS * r;
+        let d_delta = 2.0 * r - 2.0 * MASS;
+        let p = e * r2a2 - a * l;
+        let d_p = 2.0 * r * e;
+        let l_term = l - a * e * sin2;
+
+        let n = delta * p_r * p_r + p_theta * p_theta - p * p / delta + l_term * l_term / sin2;
+
+        let dr = delta * p_r / sigma;
+        let dtheta = p_theta / sigma;
+        let dphi = (a * p / delta + l_term / sin2) / sigma;
+        let dt = (r2a2 * p / delta + a * l_term) / sigma;
+
+        let dn_dr = d_delta * p_r * p_r - (2.0 * p * d_p * delta - p * p * d_delta) / (delta * delta);
+        let dp_r = -(dn_dr / (2.0 * sigma) - n * r / (sigma * sigma));
+
+        let dn_dtheta = -2.0 * l * l * c / (sin2 * s) + 2.0 * a * a * e * e * s * c;
+        let dp_theta = -(dn_dtheta / (2.0 * sigma) + n * a * a * c * s / (sigma * sigma));
+
+        [dr, dtheta, dphi, dt, dp_r, dp_theta]
+    }
+
+    pub fn trace_with_diagnostics(&self, origin: DVec3, direction: DVec3) -> KerrTrace {
+        let (mut y, energy, angular_momentum) = self.initial_state(origin, direction);
+        let initial = self.constants(&y, energy, angular_momentum);
+        let rhs = |state: &[f64; 6]| self.derivatives(state, energy, angular_momentum);
+        let capture_radius = self.horizon_radius() * (1.0 + HORIZON_MARGIN);
+
+        let finish = |outcome: RayOutcome, y: &[f64; 6], steps: usize| KerrTrace {
+            outcome,
+            initial,
+            last: self.constants(y, energy, angular_momentum),
+            hamiltonian_residual: self.hamiltonian(y, energy, angular_momentum).abs(),
+            coordinate_time: y[T],
+            steps,
+        };
+
+        let mut h = 0.01 * y[R].max(MASS);
+        let mut steps = 0;
+        while steps < self.settings.max_steps {
+            let r = y[R];
+            if r <= capture_radius {
+                let position = self.to_world(r, y[THETA], y[PHI]);
+                return finish(RayOutcome::Horizon { position }, &y, steps);
+            }
+            if r >= self.settings.escape_radius && y[P_R] > 0.0 {
+                let direction = self.world_direction(&y, angular_momentum);
+                return finish(RayOutcome::Escaped { direction }, &y, steps);
+            }
+
+            // Affine steps shrink with the distance to the horizon, where p_r diverges.
+            h = h.min(0.05 * (r - self.horizon_radius()).max(1.0e-4) + 1.0e-6);
+            let (next, error) = rkf45_step(&rhs, &y, h, self.settings.tolerance);
+            steps += 1;
+            if error > 1.0 {
+                h = next_step_size(h, error);
+                continue;
+            }
+
+            if let Some(hit) = self
+                .settings
+                .disk
+                .and_then(|disk| self.disk_crossing(&disk, &y, &next))
+            {
+                return finish(hit, &y, steps);
+            }
+
+            y = next;
+            h = next_step_size(h, error);
+        }
+
+        let position = self.to_world(y[R], y[THETA], y[PHI]);
+        let direction = self.world_direction(&y, angular_momentum);
+        finish(RayOutcome::Unresolved { position, direction }, &y, steps)
+    }
+
+    // Equatorial plane crossing between two states, in Boyer-Lindquist radius.
+    fn disk_crossing(&self, disk: &Disk, a: &[f64; 6], b: &[f64; 6]) -> Option<RayOutcome> {
+        let ca = a[THETA].cos();
+        let cb = b[THETA].cos();
+        if ca == cb || ca * cb > 0.0 {
+            return None;
+        }
+        let t = ca / (ca - cb);
+        let radius = a[R] + (b[R] - a[R]) * t;
+        if radius < disk.inner_radius || radius > disk.outer_radius {
+            return None;
+        }
+        let phi = a[PHI] + (b[PHI] - a[PHI]) * t;
+        Some(RayOutcome::Disk {
+            position: self.to_world(radius, std::f64::consts::FRAC_PI_2, phi),
+            radius,
+        })
+    }
+}
+
+impl GeodesicTracer for KerrTracer {
+    fn trace(&self, origin: DVec3, direction: DVec3) -> RayOutcome {
+        self.trace_with_diagnostics(origin, direction).outcome
+    }
+}
+
+// Analytic edge of the shadow seen by a distant observer at `inclination` (radians
+// from the spin axis), as celestial coordinates (alpha, beta) in the same units as
+// the tracer. alpha runs perpendicular to the projected spin axis. Follows Bardeen
+// (1973).
+pub fn shadow_outline(spin: f64, inclination: f64, samples: usize) -> Vec<(f64, f64)> {
+    let samples = samples.max(2);
+    if spin == 0.0 {
+        // Bardeen's parametrization divides by a; without spin the shadow is the circle
+        // of radius b_c from any inclination.
+        let upper: Vec<(f64, f64)> = (0..samples)
+            .map(|i| {
+                let angle = std::f64::consts::PI * i as f64 / (samples - 1) as f64;
+                let (sin, cos) = angle.sin_cos();
+                (-CRITICAL_IMPACT_PARAMETER * cos, CRITICAL_IMPACT_PARAMETER * sin)
+            })
+            .collect();
+        let mut outline = upper.clone();
+        outline.extend(upper.iter().rev().map(|&(alpha, beta)| (alpha, -beta)));
+        return outline;
+    }
+    let spin = spin.clamp(1.0e-6, 0.9999);
+    let m = MASS;
+    let a = spin * m;
+    let prograde = 2.0 * m * (1.0 + ((2.0 / 3.0) * (-spin).acos()).cos());
+    let retrograde = 2.0 * m * (1.0 + ((2.0 / 3.0) * spin.acos()).cos());
+    let inclination = inclination.clamp(1.0e-3, std::f64::consts::PI - 1.0e-3);
+    let (sin_i, cos_i) = inclination.sin_cos();
+
+    let mut upper = Vec::with_capacity(samples);
+    for i in 0..samples {
+        let r = prograde + (retrograde - prograde) * i as f64 / (samples - 1) as f64;
+        let xi = (r * r * (3.0 * m - r) - a * a * (r + m)) / (a * (r - m));
+        let eta = r.powi(3) * (4.0 * a * a * m - r * (r - 3.0 * m).powi(2))
+            / (a * a * (r - m).powi(2));
+        let beta2 = eta + a * a * cos_i * cos_i - xi * xi * (cos_i / sin_i).powi(2);
+        if beta2 >= 0.0 {
+            upper.push((-xi / sin_i, beta2.sqrt()));
+        }
+    }
+
+    let mut outline = upper.clone();
+    outline.extend(upper.iter().rev().map(|&(alpha, beta)| (alpha, -beta)));
+    outline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_of_motion_hold_at_high_spin() {
+        let tracer = KerrTracer::new(0.9, TraceSettings::default());
+        // Strongly bent rays on the prograde and retrograde side that still escape.
+        for origin in [DVec3::new(-1.6, 0.3, -40.0), DVec3::new(3.6, 0.3, -40.0)] {
+            let trace = tracer.trace_with_diagnostics(origin, DVec3::Z);
+            assert!(
+                matches!(trace.outcome, RayOutcome::Escaped { .. }),
+                "{:?}",
+                trace.outcome
+            );
+            let drift = trace.initial.drift(&trace.last);
+            assert!(drift < 1e-7, "constants drifted by {}", drift);
+            assert!(trace.hamiltonian_residual < 1e-6, "H = {}", trace.hamiltonian_residual);
+        }
+    }
+
+    #[test]
+    fn shadow_is_schwarzschild_circle_at_zero_spin() {
+        let expected = 3.0 * 3.0_f64.sqrt() * MASS;
+        for inclination in [0.3, std::f64::consts::FRAC_PI_2] {
+            let outline = shadow_outline(0.0, inclination, 64);
+            assert_eq!(outline.len(), 128);
+            for (alpha, beta) in outline {
+                let radius = alpha.hypot(beta);
+                assert!((radius - expected).abs() < 1e-12, "shadow radius {}", radius);
+            }
+        }
+    }
+
+    #[test]
+    fn shadow_approaches_schwarzschild_at_small_spin() {
+        let expected = 3.0 * 3.0_f64.sqrt() * MASS;
+        let outline = shadow_outline(1.0e-4, std::f64::consts::FRAC_PI_2, 64);
+        assert!(!outline.is_empty());
+        for (alpha, beta) in outline {
+            let radius = alpha.hypot(beta);
+            assert!((radius - expected).abs() < 1e-3, "shadow radius {} vs {}", radius, expected);
+        }
+    }
+}
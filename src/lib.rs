@@ -32,19 +32,48 @@ use wasm_bindgen::JsCast;
 #[cfg(target_arch = "wasm32")]
 use winit::platform::web::{EventLoopExtWebSys, WindowExtWebSys};
 
+mod assets;
 mod render_pass;
+mod render_graph;
+mod uniform_blocks;
 mod render_utils;
 mod shader_utils;
+mod shader_include;
+// Only build.rs uses it; compiled here so its tests run.
+#[cfg(test)]
+#[allow(dead_code)]
+mod shader_syntax;
 mod texture;
 mod noise_gen;
-mod app_state;
+mod blue_noise;
+pub mod app_state;
 mod framebuffer;
 mod renderer;
 mod gui;
+mod dynamic_resolution;
+pub mod clock;
+pub mod camera;
+pub mod timeline;
+pub mod scene;
+pub mod presets;
+pub mod geodesic;
+pub mod kerr;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cpu_renderer;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offline;
+#[cfg(not(target_arch = "wasm32"))]
+mod recorder;
+#[cfg(not(target_arch = "wasm32"))]
+mod shader_watch;
 use renderer::Renderer;
 
 const SCR_WIDTH: u32 = 1200;
 const SCR_HEIGHT: u32 = 800;
+// Longest time step a single frame may take, in seconds.
+const MAX_FRAME_DT: f32 = 0.25;
 
 #[cfg(not(target_arch = "wasm32"))]
 type TimePoint = Instant;
@@ -82,7 +111,119 @@ fn resize_surface(
 ) {
     let width = NonZeroU32::new(size.width.max(1)).unwrap();
     let height = NonZeroU32::new(size.height.max(1)).unwrap();
-    let _ = gl_surface.resize(gl_context, width, height);
+    gl_surface.resize(gl_context, width, height);
+}
+
+// Carries out a Save/Load clicked in the GUI. Returns the scene time after a load so
+// the caller can move its clock there.
+fn handle_scene_action(
+    action: gui::SceneAction,
+    gui_state: &mut gui::Gui,
+    app_state: &mut app_state::AppState,
+    timeline: &mut timeline::Timeline,
+    time: f32,
+) -> Option<f32> {
+    match action {
+        gui::SceneAction::Save(path) => {
+            let mut saved = scene::Scene::new(app_state, time);
+            saved.timeline = timeline.clone();
+            match saved.save(&path) {
+                Ok(()) => gui_state.set_scene_status(format!("Saved {}", path)),
+                Err(e) => gui_state.set_scene_status(format!("{:#}", e)),
+            }
+            None
+        }
+        gui::SceneAction::Load(path) => match scene::Scene::load(&path) {
+            Ok(loaded) => {
+                *app_state = loaded.state;
+                *timeline = loaded.timeline;
+                timeline.seek(timeline.playhead);
+                gui_state.set_scene_status(format!("Loaded {}", path));
+                Some(loaded.time)
+            }
+            Err(e) => {
+                gui_state.set_scene_status(format!("{:#}", e));
+                None
+            }
+        },
+    }
+}
+
+// A running capture, plus the clock setting it overrode.
+#[cfg(not(target_arch = "wasm32"))]
+struct Recording {
+    recorder: recorder::Recorder,
+    fixed_step: bool,
+}
+
+// Recordings always run on the fixed timestep so every captured frame advances the
+// animation by exactly 1/fps, however long it took to render.
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn start_recording(
+    gl: &glow::Context,
+    renderer: &Renderer,
+    clock: &mut clock::Clock,
+    settings: recorder::RecordSettings,
+) -> anyhow::Result<Recording> {
+    let (width, height) = renderer.size();
+    let recorder = recorder::Recorder::start(gl, settings, width, height)?;
+    let fixed_step = clock.fixed_step;
+    clock.fixed_step = true;
+    clock.paused = false;
+    Ok(Recording {
+        recorder,
+        fixed_step,
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn stop_recording(
+    gl: &glow::Context,
+    recording: Recording,
+    clock: &mut clock::Clock,
+    gui_state: &mut gui::Gui,
+    error: Option<anyhow::Error>,
+) {
+    clock.fixed_step = recording.fixed_step;
+    let path = recording.recorder.settings().path.clone();
+    let status = match (recording.recorder.finish(gl), error) {
+        (_, Some(e)) | (Err(e), None) => format!("Recording stopped: {:#}", e),
+        (Ok(frames), None) => format!("Wrote {} frames to {}", frames, path.display()),
+    };
+    gui_state.set_record_state(None, status);
+}
+
+// Reads the native command line, `[--assets <dir>] [scene.toml]`: applies the asset
+// directory and returns the scene path.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_args() -> anyhow::Result<Option<String>> {
+    let mut scene = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--assets" {
+            let dir = args.next().ok_or(anyhow::anyhow!("Missing value for --assets"))?;
+            assets::set_dir(dir.into())?;
+        } else {
+            scene = Some(arg);
+        }
+    }
+    Ok(scene)
+}
+
+// The scene named on the command line, or `scene.toml` in the working directory
+// when it exists.
+#[cfg(not(target_arch = "wasm32"))]
+fn startup_scene(arg: Option<String>) -> anyhow::Result<Option<(String, scene::Scene)>> {
+    if let Some(path) = arg {
+        let loaded = scene::Scene::load(&path)?;
+        return Ok(Some((path, loaded)));
+    }
+    if std::path::Path::new(scene::DEFAULT_SCENE_PATH).exists() {
+        let path = scene::DEFAULT_SCENE_PATH.to_string();
+        let loaded = scene::Scene::load(&path)?;
+        return Ok(Some((path, loaded)));
+    }
+    Ok(None)
 }
 
 pub fn run_app() -> anyhow::Result<()> {
@@ -105,6 +246,7 @@ pub fn start() -> Result<(), JsValue> {
 
 #[cfg(not(target_arch = "wasm32"))]
 fn run_app_native() -> anyhow::Result<()> {
+    let scene_arg = parse_args()?;
     let event_loop = EventLoop::new().unwrap();
 
     // Get primary detector for fullscreen
@@ -165,6 +307,7 @@ fn run_app_native() -> anyhow::Result<()> {
     };
 
     // Wrap gl in Arc to share with egui
+    #[allow(clippy::arc_with_non_send_sync)]
     let gl = Arc::new(gl);
 
     let mut egui_glow = egui_glow::EguiGlow::new(&event_loop, gl.clone(), None, None);
@@ -172,14 +315,33 @@ fn run_app_native() -> anyhow::Result<()> {
     let mut window_size = window.inner_size();
 
     let mut renderer = unsafe { Renderer::new(&gl, window_size.width, window_size.height)? };
+    // Embedded shaders cannot change, so there is nothing to watch.
+    let mut shader_watcher =
+        assets::dir()?.map(|dir| shader_watch::ShaderWatcher::new(dir.join("shader")));
 
-    let start_time = time_now();
+    let mut clock = clock::Clock::new(0.0);
+    let mut timeline = timeline::Timeline::default();
     let mut app_state = app_state::AppState::default();
+    let mut gui_state = gui::Gui::new();
 
     resize_surface(&gl_surface, &gl_context, window_size);
 
-    app_state.mouse_x = window_size.width as f32 * 0.5;
-    app_state.mouse_y = window_size.height as f32 * 0.5;
+    let mut camera_controller = camera::CameraController::default();
+    let mut gpu_timer = unsafe { dynamic_resolution::GpuTimer::new(&gl) };
+    let mut dynamic_resolution =
+        dynamic_resolution::DynamicResolution::new(!gpu_timer.is_available());
+    // Set while the loop sleeps; the first frame after that measures the wait.
+    let mut waiting = false;
+    let mut recording: Option<Recording> = None;
+
+    if let Some((path, loaded)) = startup_scene(scene_arg)? {
+        app_state = loaded.state;
+        clock.seek(loaded.time);
+        timeline = loaded.timeline;
+        timeline.seek(timeline.playhead);
+        gui_state.set_scene_status(format!("Loaded {}", path));
+        gui_state.scene_path = path;
+    }
 
     unsafe {
         gl.clear_color(0.0, 0.0, 0.0, 1.0);
@@ -187,29 +349,41 @@ fn run_app_native() -> anyhow::Result<()> {
 
     let mut frame_count = 0;
     let mut last_fps_update = time_now();
+    let mut last_frame = time_now();
     let mut fps_display = 60.0;
-    let mut gui_state = gui::Gui::new();
 
     event_loop
         .run(move |event, window_target| {
-            window_target.set_control_flow(ControlFlow::Poll);
-
             match event {
                 Event::WindowEvent { event, .. } => {
                     // Let egui handle the event first
                     let response = egui_glow.on_window_event(&window, &event);
+                    // Any input may change the image, even while the loop is waiting.
+                    if !matches!(event, WindowEvent::RedrawRequested) {
+                        window.request_redraw();
+                    }
+                    let over_ui = egui_glow.egui_ctx.is_pointer_over_area();
                     if response.consumed {
                         return;
                     }
+                    camera_controller.handle_event(&mut app_state.camera, &event, over_ui);
 
                     match event {
-                        WindowEvent::CloseRequested => window_target.exit(),
+                        WindowEvent::CloseRequested => {
+                            if let Some(active) = recording.take() {
+                                unsafe {
+                                    stop_recording(&gl, active, &mut clock, &mut gui_state, None);
+                                }
+                            }
+                            window_target.exit();
+                        }
                         WindowEvent::Resized(size) => {
                             window_size = size;
                             resize_surface(&gl_surface, &gl_context, size);
                             unsafe {
                                 renderer.resize(&gl, size.width, size.height).unwrap();
                             }
+                            dynamic_resolution.reset();
                         }
                         WindowEvent::ScaleFactorChanged { .. } => {
                             window_size = window.inner_size();
@@ -217,13 +391,7 @@ fn run_app_native() -> anyhow::Result<()> {
                             unsafe {
                                 renderer.resize(&gl, window_size.width, window_size.height).unwrap();
                             }
-                        }
-                        WindowEvent::CursorMoved { position, .. } => {
-                            // Only update mouse pos if we aren't hovering UI
-                            if !egui_glow.egui_ctx.is_pointer_over_area() {
-                                app_state.mouse_x = position.x as f32;
-                                app_state.mouse_y = position.y as f32;
-                            }
+                            dynamic_resolution.reset();
                         }
                         WindowEvent::RedrawRequested => {
                             if window_size.width == 0 || window_size.height == 0 {
@@ -239,25 +407,148 @@ fn run_app_native() -> anyhow::Result<()> {
                                 frame_count = 0;
                             }
 
-                            let time = seconds_since(now, start_time);
+                            // Clamped so the first frame after an idle wait doesn't jump the camera.
+                            let frame_dt = seconds_since(now, last_frame).min(MAX_FRAME_DT);
+                            last_frame = now;
+                            if std::mem::take(&mut waiting) {
+                                dynamic_resolution.reset();
+                            }
+                            if let Some(active) = &recording {
+                                clock.fixed_step = true;
+                                clock.fps = active.recorder.settings().fps;
+                            }
+                            let step = clock.tick(frame_dt);
+                            let time = clock.time();
+
+                            camera_controller.update(&mut app_state.camera, frame_dt, step);
+                            timeline.advance(step);
+                            if timeline.should_apply() {
+                                timeline.apply(&mut app_state, timeline.playhead);
+                            }
 
                             unsafe {
+                                gpu_timer.begin(&gl);
                                 renderer.render(&gl, &app_state, time);
                             }
 
+                            // Capture before the GUI is drawn so it stays out of the frames.
+                            if let Some(mut active) = recording.take() {
+                                let captured = unsafe { active.recorder.capture(&gl, &renderer) };
+                                match captured {
+                                    Ok(()) if !active.recorder.is_complete() => {
+                                        let frames = active.recorder.frames_captured();
+                                        gui_state.set_record_state(Some(frames), "Recording");
+                                        recording = Some(active);
+                                    }
+                                    result => unsafe {
+                                        stop_recording(
+                                            &gl,
+                                            active,
+                                            &mut clock,
+                                            &mut gui_state,
+                                            result.err(),
+                                        );
+                                    },
+                                }
+                            }
+
                             // Render GUI
+                            let samples = (
+                                renderer.accumulated_samples(),
+                                renderer.sample_target(&app_state.quality),
+                            );
                             egui_glow.run(&window, |ctx| {
-                                gui_state.ui(ctx, &mut app_state, fps_display);
+                                gui_state.ui(
+                                    ctx,
+                                    &mut app_state,
+                                    &mut timeline,
+                                    &mut clock,
+                                    fps_display,
+                                    samples,
+                                );
+                                gui::shader_errors_ui(ctx, renderer.shader_errors());
                             });
                             egui_glow.paint(&window);
 
+                            // Without timer queries fall back to the wall-clock frame time.
+                            let gpu_ms = unsafe { gpu_timer.end(&gl) };
+                            let frame_ms = if gpu_timer.is_available() {
+                                gpu_ms
+                            } else {
+                                Some(frame_dt * 1000.0)
+                            };
+                            // Accumulating frames march longer rays on purpose; don't count them.
+                            let frame_ms = frame_ms.filter(|_| renderer.accumulated_samples() == 0);
+                            if let Some(frame_ms) = frame_ms {
+                                dynamic_resolution.update(&mut app_state.quality, frame_ms);
+                            }
+
+                            let scene_time = gui_state.take_scene_action().and_then(|action| {
+                                handle_scene_action(
+                                    action,
+                                    &mut gui_state,
+                                    &mut app_state,
+                                    &mut timeline,
+                                    time,
+                                )
+                            });
+                            if let Some(scene_time) = scene_time {
+                                clock.seek(scene_time);
+                            }
+
+                            match gui_state.take_record_action() {
+                                Some(gui::RecordAction::Start(settings)) if recording.is_none() => {
+                                    let started = unsafe {
+                                        start_recording(&gl, &renderer, &mut clock, settings)
+                                    };
+                                    match started {
+                                        Ok(active) => {
+                                            gui_state.set_record_state(Some(0), "Recording");
+                                            recording = Some(active);
+                                        }
+                                        Err(e) => {
+                                            gui_state.set_record_state(None, format!("{:#}", e));
+                                        }
+                                    }
+                                }
+                                Some(gui::RecordAction::Stop) => {
+                                    if let Some(active) = recording.take() {
+                                        let (gui, clock) = (&mut gui_state, &mut clock);
+                                        unsafe { stop_recording(&gl, active, clock, gui, None) };
+                                    }
+                                }
+                                _ => {}
+                            }
+
                             gl_surface.swap_buffers(&gl_context).unwrap();
                         }
                         _ => (),
                     }
                 }
                 Event::AboutToWait => {
-                    window.request_redraw();
+                    if let Some(watcher) = &mut shader_watcher {
+                        let changed = watcher.poll();
+                        if !changed.is_empty() {
+                            unsafe { renderer.reload_shaders(&gl, &changed) };
+                        }
+                    }
+
+                    // Sleep until the next input once the image has stopped changing,
+                    // waking up only to look for shader edits.
+                    let idle = recording.is_none()
+                        && renderer.is_settled()
+                        && !egui_glow.egui_ctx.has_requested_repaint();
+                    waiting = idle;
+                    if idle {
+                        let wake = match &shader_watcher {
+                            Some(watcher) => ControlFlow::WaitUntil(watcher.next_poll()),
+                            None => ControlFlow::Wait,
+                        };
+                        window_target.set_control_flow(wake);
+                    } else {
+                        window_target.set_control_flow(ControlFlow::Poll);
+                        window.request_redraw();
+                    }
                 }
                 _ => (),
             }
@@ -307,17 +598,23 @@ fn run_app_web() -> anyhow::Result<()> {
     let gl = glow::Context::from_webgl2_context(webgl2_context);
 
     // Wrap gl in Arc to share with egui
+    #[allow(clippy::arc_with_non_send_sync)]
     let gl = Arc::new(gl);
 
     let mut egui_glow = egui_glow::EguiGlow::new(&event_loop, gl.clone(), None, None);
 
     let mut renderer = unsafe { Renderer::new(&gl, window_size.width, window_size.height)? };
 
-    let start_time = time_now();
+    let mut clock = clock::Clock::new(0.0);
+    let mut timeline = timeline::Timeline::default();
     let mut app_state = app_state::AppState::default();
 
-    app_state.mouse_x = window_size.width as f32 * 0.5;
-    app_state.mouse_y = window_size.height as f32 * 0.5;
+    let mut camera_controller = camera::CameraController::default();
+    let mut gpu_timer = unsafe { dynamic_resolution::GpuTimer::new(&gl) };
+    let mut dynamic_resolution =
+        dynamic_resolution::DynamicResolution::new(!gpu_timer.is_available());
+    // Set while the loop sleeps; the first frame after that measures the wait.
+    let mut waiting = false;
 
     unsafe {
         gl.clear_color(0.0, 0.0, 0.0, 1.0);
@@ -325,19 +622,24 @@ fn run_app_web() -> anyhow::Result<()> {
 
     let mut frame_count = 0;
     let mut last_fps_update = time_now();
+    let mut last_frame = time_now();
     let mut fps_display = 60.0;
     let mut gui_state = gui::Gui::new();
 
     event_loop.spawn(move |event, window_target| {
-        window_target.set_control_flow(ControlFlow::Poll);
-
         match event {
             Event::WindowEvent { event, .. } => {
                 // Let egui handle the event first
                 let response = egui_glow.on_window_event(&window, &event);
+                // Any input may change the image, even while the loop is waiting.
+                if !matches!(event, WindowEvent::RedrawRequested) {
+                    window.request_redraw();
+                }
+                let over_ui = egui_glow.egui_ctx.is_pointer_over_area();
                 if response.consumed {
                     return;
                 }
+                camera_controller.handle_event(&mut app_state.camera, &event, over_ui);
 
                 match event {
                     WindowEvent::CloseRequested => window_target.exit(),
@@ -348,6 +650,7 @@ fn run_app_web() -> anyhow::Result<()> {
                         unsafe {
                             renderer.resize(&gl, size.width, size.height).unwrap();
                         }
+                        dynamic_resolution.reset();
                     }
                     WindowEvent::ScaleFactorChanged { .. } => {
                         window_size = window.inner_size();
@@ -356,13 +659,7 @@ fn run_app_web() -> anyhow::Result<()> {
                         unsafe {
                             renderer.resize(&gl, window_size.width, window_size.height).unwrap();
                         }
-                    }
-                    WindowEvent::CursorMoved { position, .. } => {
-                        // Only update mouse pos if we aren't hovering UI
-                        if !egui_glow.egui_ctx.is_pointer_over_area() {
-                            app_state.mouse_x = position.x as f32;
-                            app_state.mouse_y = position.y as f32;
-                        }
+                        dynamic_resolution.reset();
                     }
                     WindowEvent::RedrawRequested => {
                         if window_size.width == 0 || window_size.height == 0 {
@@ -378,17 +675,69 @@ fn run_app_web() -> anyhow::Result<()> {
                             frame_count = 0;
                         }
 
-                        let time = seconds_since(now, start_time);
+                        // Clamped so the first frame after an idle wait doesn't jump the camera.
+                        let frame_dt = seconds_since(now, last_frame).min(MAX_FRAME_DT);
+                        last_frame = now;
+                        if std::mem::take(&mut waiting) {
+                            dynamic_resolution.reset();
+                        }
+                        let step = clock.tick(frame_dt);
+                        let time = clock.time();
+
+                        camera_controller.update(&mut app_state.camera, frame_dt, step);
+                        timeline.advance(step);
+                        if timeline.should_apply() {
+                            timeline.apply(&mut app_state, timeline.playhead);
+                        }
 
                         unsafe {
+                            gpu_timer.begin(&gl);
                             renderer.render(&gl, &app_state, time);
                         }
 
                         // Render GUI
+                        let samples = (
+                            renderer.accumulated_samples(),
+                            renderer.sample_target(&app_state.quality),
+                        );
                         egui_glow.run(&window, |ctx| {
-                            gui_state.ui(ctx, &mut app_state, fps_display);
+                            gui_state.ui(
+                                ctx,
+                                &mut app_state,
+                                &mut timeline,
+                                &mut clock,
+                                fps_display,
+                                samples,
+                            );
+                            gui::shader_errors_ui(ctx, renderer.shader_errors());
                         });
                         egui_glow.paint(&window);
+
+                        // Without timer queries fall back to the wall-clock frame time.
+                        let gpu_ms = unsafe { gpu_timer.end(&gl) };
+                        let frame_ms = if gpu_timer.is_available() {
+                            gpu_ms
+                        } else {
+                            Some(frame_dt * 1000.0)
+                        };
+                        // Accumulating frames march longer rays on purpose; don't count them.
+                        let frame_ms = frame_ms.filter(|_| renderer.accumulated_samples() == 0);
+                        if let Some(frame_ms) = frame_ms {
+                            dynamic_resolution.update(&mut app_state.quality, frame_ms);
+                        }
+
+                        let scene_time = gui_state.take_scene_action().and_then(|action| {
+                            handle_scene_action(
+                                action,
+                                &mut gui_state,
+                                &mut app_state,
+                                &mut timeline,
+                                time,
+                            )
+                        });
+                        if let Some(scene_time) = scene_time {
+                            clock.seek(scene_time);
+                        }
                         unsafe {
                             gl.flush();
                         }
@@ -397,7 +746,15 @@ fn run_app_web() -> anyhow::Result<()> {
                 }
             }
             Event::AboutToWait => {
-                window.request_redraw();
+                // Sleep until the next input once the image has stopped changing.
+                let idle = renderer.is_settled() && !egui_glow.egui_ctx.has_requested_repaint();
+                waiting = idle;
+                if idle {
+                    window_target.set_control_flow(ControlFlow::Wait);
+                } else {
+                    window_target.set_control_flow(ControlFlow::Poll);
+                    window.request_redraw();
+                }
             }
             _ => (),
         }
@@ -197,6 +197,7 @@ fn permute(x: Vec4) -> Vec4 {
     mod289_vec4(((x * 34.0) + Vec4::splat(1.0)) * x)
 }
 
+#[allow(clippy::excessive_precision)]
 fn taylor_inv_sqrt(r: Vec4) -> Vec4 {
     Vec4::splat(1.79284291400159) - r * 0.85373472095314
 }
@@ -0,0 +1,229 @@
+// One-shot still renderer behind the `blackhole_render` binary. Renders a single
+// frame through `Renderer` into its offscreen targets (or through `CpuRenderer`)
+// and writes `tex_tonemapped` to a PNG or EXR file.
+
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+use crate::app_state::AppState;
+use crate::headless::{HeadlessBackend, HeadlessGl};
+use crate::presets;
+use crate::renderer::Renderer;
+use crate::scene::Scene;
+
+const USAGE: &str = "\
+Usage: blackhole_render [options] --output <file.png|file.exr>
+
+Options:
+  --scene <file>       Scene file (TOML) to render; defaults are used otherwise
+  --preset <name>      Built-in look instead of a scene (Gargantua, M87*, ...)
+  --size <W>x<H>       Output resolution (default 1920x1080)
+  --time <seconds>     Animation and timeline time (default: the scene's time and playhead)
+  --output <file>      Output image; the format follows the extension (png, exr)
+  --gl <backend>       auto | egl | window (default auto)
+  --cpu                Use the CPU renderer instead of OpenGL
+  --assets <dir>       Read shaders and textures from <dir> (holding assets/ and shader/)
+  -h, --help           Show this message";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Png,
+    Exr,
+}
+
+impl OutputFormat {
+    fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("png") => Ok(Self::Png),
+            Some("exr") => Ok(Self::Exr),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported output format for {} (use .png or .exr)",
+                path.display()
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RenderOptions {
+    pub scene: Option<PathBuf>,
+    pub preset: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub time: Option<f32>,
+    pub output: PathBuf,
+    pub backend: HeadlessBackend,
+    pub cpu: bool,
+    pub assets: Option<PathBuf>,
+}
+
+fn parse_size(value: &str) -> anyhow::Result<(u32, u32)> {
+    let (w, h) = value
+        .split_once(['x', 'X'])
+        .with_context(|| format!("Invalid size `{}` (expected WxH)", value))?;
+    let w: u32 = w.trim().parse().with_context(|| format!("Invalid width `{}`", w))?;
+    let h: u32 = h.trim().parse().with_context(|| format!("Invalid height `{}`", h))?;
+    if w == 0 || h == 0 {
+        anyhow::bail!("Resolution must be non-zero, got {}x{}", w, h);
+    }
+    Ok((w, h))
+}
+
+impl RenderOptions {
+    // `Ok(None)` means help was requested.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Option<Self>> {
+        let mut scene = None;
+        let mut preset = None;
+        let mut size = (1920, 1080);
+        let mut time = None;
+        let mut output = None;
+        let mut backend = HeadlessBackend::Auto;
+        let mut cpu = false;
+        let mut assets = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .with_context(|| format!("Missing value for {}", name))
+            };
+            match arg.as_str() {
+                "--scene" => scene = Some(PathBuf::from(value("--scene")?)),
+                "--preset" => preset = Some(value("--preset")?),
+                "--size" => size = parse_size(&value("--size")?)?,
+                "--time" => {
+                    let v = value("--time")?;
+                    time = Some(v.parse().with_context(|| format!("Invalid time `{}`", v))?);
+                }
+                "--output" | "-o" => output = Some(PathBuf::from(value("--output")?)),
+                "--gl" => backend = value("--gl")?.parse()?,
+                "--cpu" => cpu = true,
+                "--assets" => assets = Some(PathBuf::from(value("--assets")?)),
+                "-h" | "--help" => return Ok(None),
+                _ => anyhow::bail!("Unknown argument `{}`\n\n{}", arg, USAGE),
+            }
+        }
+
+        let output = output.with_context(|| format!("Missing --output\n\n{}", USAGE))?;
+        if scene.is_some() && preset.is_some() {
+            anyhow::bail!("--scene and --preset cannot be combined");
+        }
+        Ok(Some(Self {
+            scene,
+            preset,
+            width: size.0,
+            height: size.1,
+            time,
+            output,
+            backend,
+            cpu,
+            assets,
+        }))
+    }
+}
+
+fn load_scene(options: &RenderOptions) -> anyhow::Result<Scene> {
+    if let Some(name) = &options.preset {
+        let preset = presets::find(name).with_context(|| {
+            let names: Vec<_> = presets::PRESETS.iter().map(|p| p.name).collect();
+            format!("Unknown preset `{}` (available: {})", name, names.join(", "))
+        })?;
+        return Ok(Scene::new(&preset.state(), 0.0));
+    }
+    match &options.scene {
+        Some(path) => Scene::load(&path.to_string_lossy()),
+        None => Ok(Scene::new(&AppState::default(), 0.0)),
+    }
+}
+
+// Flips bottom-up GL rows into top-down image rows.
+fn flip_rows<T: Copy>(pixels: &[T], width: usize, height: usize) -> Vec<T> {
+    let row = width * 4;
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for y in (0..height).rev() {
+        flipped.extend_from_slice(&pixels[y * row..(y + 1) * row]);
+    }
+    flipped
+}
+
+fn render_gpu(options: &RenderOptions, state: &AppState, time: f32) -> anyhow::Result<image::Rgba32FImage> {
+    let headless = HeadlessGl::new(options.backend)?;
+    let gl = &headless.gl;
+    let (w, h) = (options.width, options.height);
+    // Stills are rendered at full resolution and converged with progressive
+    // accumulation rather than TAA, which has no history to resolve against here.
+    let mut state = state.clone();
+    state.quality.taa = false;
+    state.quality.render_scale = 1.0;
+    state.quality.dynamic_resolution = false;
+    let pixels = unsafe {
+        let mut renderer = Renderer::new(gl, w, h)?;
+        // The first frame starts the idle view; later ones add samples until the
+        // average reaches `progressive_samples` (or right away without progressive).
+        renderer.render_offscreen(gl, &state, time);
+        while !renderer.is_settled() {
+            renderer.render_offscreen(gl, &state, time);
+        }
+        // The interactive app keeps running on the magenta fallback; a render to disk
+        // would only produce a broken image.
+        if let Some((name, diagnostics)) = renderer.shader_errors().iter().next() {
+            let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+            anyhow::bail!("{} failed to build:\n{}", name, messages.join("\n"));
+        }
+        renderer.read_tonemapped_f32(gl)
+    };
+    let mut pixels = flip_rows(&pixels, w as usize, h as usize);
+    for alpha in pixels.iter_mut().skip(3).step_by(4) {
+        *alpha = 1.0;
+    }
+    image::Rgba32FImage::from_raw(w, h, pixels).context("Readback size mismatch")
+}
+
+fn render_cpu(options: &RenderOptions, state: &AppState, time: f32) -> anyhow::Result<image::Rgba32FImage> {
+    let renderer = crate::cpu_renderer::CpuRenderer::new()?;
+    let img = renderer.render(state, time, options.width, options.height);
+    Ok(image::DynamicImage::ImageRgba8(img).into_rgba32f())
+}
+
+pub fn render_still(options: &RenderOptions) -> anyhow::Result<()> {
+    let format = OutputFormat::from_path(&options.output)?;
+    let scene = load_scene(options)?;
+    // Like the app after loading the scene: the clock at the saved time and the
+    // timeline at the saved playhead. `--time` sets both.
+    let time = options.time.unwrap_or(scene.time);
+    let playhead = options.time.unwrap_or(scene.timeline.playhead);
+    let mut state = scene.state;
+    scene.timeline.apply(&mut state, playhead);
+
+    let img = if options.cpu {
+        render_cpu(options, &state, time)?
+    } else {
+        render_gpu(options, &state, time)?
+    };
+
+    let img = image::DynamicImage::ImageRgba32F(img);
+    match format {
+        OutputFormat::Png => img.into_rgba8().save(&options.output),
+        OutputFormat::Exr => img.save(&options.output),
+    }
+    .with_context(|| format!("Failed to write {}", options.output.display()))
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> anyhow::Result<()> {
+    match RenderOptions::from_args(args)? {
+        Some(options) => {
+            if let Some(dir) = &options.assets {
+                crate::assets::set_dir(dir.clone())?;
+            }
+            render_still(&options)
+        }
+        None => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// Built-in looks. Each preset is a complete `AppState`; anything a preset does not
+// mention keeps its `AppState::default()` value.
+
+use glam::Vec3;
+
+use crate::app_state::AppState;
+use crate::camera::{Camera, CameraPose};
+
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    state: fn() -> AppState,
+}
+
+impl Preset {
+    pub fn state(&self) -> AppState {
+        (self.state)()
+    }
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "Default",
+        description: "The startup look.",
+        state: AppState::default,
+    },
+    Preset {
+        name: "Gargantua",
+        description: "Interstellar-style thin, bright disk seen almost edge-on.",
+        state: gargantua,
+    },
+    Preset {
+        name: "M87*",
+        description: "Thick, hot disk around a fast spinner, seen 17 degrees off its axis.",
+        state: m87,
+    },
+    Preset {
+        name: "Sgr A*",
+        description: "Dim, puffy and fast-flickering flow around a moderate spinner, seen 40 degrees off its axis.",
+        state: sgr_a,
+    },
+    Preset {
+        name: "Diagnostic",
+        description: "Flat green density disk and no post effects; for checking the ray march and disk shape.",
+        state: diagnostic,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+fn camera_at(position: Vec3) -> Camera {
+    Camera {
+        pose: CameraPose::looking_at(position, Vec3::ZERO),
+        ..Camera::default()
+    }
+}
+
+fn gargantua() -> AppState {
+    AppState {
+        camera: camera_at(Vec3::new(10.0, 1.0, 10.0)),
+        camera_roll: -6.0,
+        spin: 0.6,
+        adisk_height: 0.2,
+        adisk_density_v: 3.0,
+        adisk_density_h: 5.0,
+        adisk_lit: 0.35,
+        adisk_noise_scale: 1.2,
+        adisk_speed: 0.3,
+        bloom_strength: 0.12,
+        flare_strength: 0.25,
+        chroma_aberration: 0.002,
+        grain_strength: 0.015,
+        saturation: 0.9,
+        gamma: 2.2,
+        ..AppState::default()
+    }
+}
+
+fn m87() -> AppState {
+    AppState {
+        camera: camera_at(Vec3::new(0.0, 17.2, 5.3)),
+        camera_roll: 0.0,
+        spin: 0.9,
+        adisk_height: 0.6,
+        adisk_density_v: 1.5,
+        adisk_density_h: 3.0,
+        adisk_lit: 0.12,
+        adisk_noise_scale: 0.6,
+        adisk_speed: 0.8,
+        bloom_strength: 0.12,
+        flare_strength: 0.0,
+        chroma_aberration: 0.0,
+        grain_strength: 0.02,
+        saturation: 1.6,
+        gamma: 1.8,
+        ..AppState::default()
+    }
+}
+
+fn sgr_a() -> AppState {
+    AppState {
+        camera: camera_at(Vec3::new(10.3, 12.3, 0.0)),
+        camera_roll: 20.0,
+        spin: 0.5,
+        adisk_height: 0.8,
+        adisk_density_v: 1.0,
+        adisk_density_h: 2.0,
+        adisk_lit: 0.025,
+        adisk_noise_scale: 1.6,
+        adisk_noise_lod: 6.0,
+        adisk_speed: 1.8,
+        bloom_strength: 0.05,
+        flare_strength: 0.05,
+        chroma_aberration: 0.004,
+        grain_strength: 0.02,
+        saturation: 1.2,
+        gamma: 2.0,
+        ..AppState::default()
+    }
+}
+
+fn diagnostic() -> AppState {
+    AppState {
+        camera: camera_at(Vec3::new(10.0, 1.0, 10.0)),
+        camera_roll: 0.0,
+        adisk_particle: false,
+        bloom_strength: 0.0,
+        flare_strength: 0.0,
+        chroma_aberration: 0.0,
+        grain_strength: 0.0,
+        saturation: 1.0,
+        ..AppState::default()
+    }
+}
@@ -0,0 +1,353 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+// Frame capture from the live app. Each frame `tex_tonemapped` (the image before
+// the egui overlay) is copied into one of a small ring of pixel buffer objects;
+// frames are fenced and only read back once the GPU has finished with them, so
+// the render loop does not stall on readback. Finished frames go to a writer
+// thread that encodes numbered PNGs or appends to a raw Y4M stream.
+
+use anyhow::Context;
+use glow::HasContext;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use crate::renderer::Renderer;
+
+// Frames in flight between the GPU copy and the CPU read.
+const PIXEL_BUFFERS: usize = 3;
+// Frames queued for the writer thread before the render loop waits on it.
+const WRITER_QUEUE: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    Png,
+    Y4m,
+}
+
+impl RecordFormat {
+    pub const ALL: [RecordFormat; 2] = [Self::Png, Self::Y4m];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Png => "PNG Sequence",
+            Self::Y4m => "Y4M Video",
+        }
+    }
+
+    pub fn default_path(self) -> &'static str {
+        match self {
+            Self::Png => "capture",
+            Self::Y4m => "capture.y4m",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordSettings {
+    pub format: RecordFormat,
+    // Directory for PNG sequences, file for Y4M.
+    pub path: PathBuf,
+    pub fps: f32,
+    // Stop after this many frames; 0 records until stopped.
+    pub frame_limit: u64,
+}
+
+struct Frame {
+    index: u64,
+    pixels: Vec<u8>,
+}
+
+struct PendingFrame {
+    index: u64,
+    buffer: glow::Buffer,
+    fence: glow::Fence,
+}
+
+pub struct Recorder {
+    settings: RecordSettings,
+    width: u32,
+    height: u32,
+    free_buffers: Vec<glow::Buffer>,
+    pending: VecDeque<PendingFrame>,
+    captured: u64,
+    sender: Option<mpsc::SyncSender<Frame>>,
+    writer: Option<JoinHandle<anyhow::Result<u64>>>,
+}
+
+impl Recorder {
+    pub unsafe fn start(
+        gl: &glow::Context,
+        settings: RecordSettings,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<Self> {
+        let mut sink = FrameSink::create(&settings, width, height)?;
+        let (sender, receiver) = mpsc::sync_channel::<Frame>(WRITER_QUEUE);
+        let writer = std::thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || {
+                let mut written = 0;
+                for frame in receiver {
+                    sink.write(&frame)?;
+                    written += 1;
+                }
+                sink.finish()?;
+                Ok(written)
+            })
+            .context("Failed to start recorder thread")?;
+
+        let size = width as i32 * height as i32 * 4;
+        let mut free_buffers = Vec::with_capacity(PIXEL_BUFFERS);
+        for _ in 0..PIXEL_BUFFERS {
+            let buffer = gl
+                .create_buffer()
+                .map_err(|e| anyhow::anyhow!("Failed to create pixel buffer: {}", e))?;
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+            gl.buffer_data_size(glow::PIXEL_PACK_BUFFER, size, glow::STREAM_READ);
+            free_buffers.push(buffer);
+        }
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+
+        Ok(Self {
+            settings,
+            width,
+            height,
+            free_buffers,
+            pending: VecDeque::new(),
+            captured: 0,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    pub fn settings(&self) -> &RecordSettings {
+        &self.settings
+    }
+
+    pub fn frames_captured(&self) -> u64 {
+        self.captured
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.settings.frame_limit > 0 && self.captured >= self.settings.frame_limit
+    }
+
+    // Queues the current `tex_tonemapped` for writing. Frames that finished on the
+    // GPU since the last call are handed to the writer thread.
+    pub unsafe fn capture(
+        &mut self,
+        gl: &glow::Context,
+        renderer: &Renderer,
+    ) -> anyhow::Result<()> {
+        if renderer.size() != (self.width, self.height) {
+            anyhow::bail!(
+                "Window resized from {}x{} while recording",
+                self.width,
+                self.height
+            );
+        }
+
+        self.collect(gl, false)?;
+        if self.free_buffers.is_empty() {
+            self.collect_one(gl, true)?;
+        }
+        let buffer = self.free_buffers.pop().context("No free pixel buffer")?;
+        renderer.read_tonemapped_to_buffer(gl, buffer);
+        let fence = gl
+            .fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0)
+            .map_err(|e| anyhow::anyhow!("Failed to create fence: {}", e))?;
+        self.pending.push_back(PendingFrame {
+            index: self.captured,
+            buffer,
+            fence,
+        });
+        self.captured += 1;
+        Ok(())
+    }
+
+    // Waits for every queued frame, flushes the writer and returns the number of
+    // frames written.
+    pub unsafe fn finish(mut self, gl: &glow::Context) -> anyhow::Result<u64> {
+        let collected = self.collect(gl, true);
+        self.release(gl);
+        self.sender.take();
+        let written = match self.writer.take().map(|w| w.join()) {
+            Some(Ok(result)) => result?,
+            Some(Err(_)) => anyhow::bail!("Recorder thread panicked"),
+            None => 0,
+        };
+        collected?;
+        Ok(written)
+    }
+
+    unsafe fn collect(&mut self, gl: &glow::Context, wait: bool) -> anyhow::Result<()> {
+        while !self.pending.is_empty() {
+            if !self.collect_one(gl, wait)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Reads back the oldest pending frame if its copy has finished (or after waiting
+    // for it when `wait` is set). Returns whether a frame was collected.
+    unsafe fn collect_one(&mut self, gl: &glow::Context, wait: bool) -> anyhow::Result<bool> {
+        let Some(frame) = self.pending.front() else {
+            return Ok(false);
+        };
+        let timeout = if wait { 1_000_000_000 } else { 0 };
+        let status = gl.client_wait_sync(frame.fence, glow::SYNC_FLUSH_COMMANDS_BIT, timeout);
+        if status != glow::ALREADY_SIGNALED && status != glow::CONDITION_SATISFIED {
+            if wait {
+                anyhow::bail!("Timed out waiting for frame readback");
+            }
+            return Ok(false);
+        }
+
+        let frame = self.pending.pop_front().expect("pending frame");
+        gl.delete_sync(frame.fence);
+        let mut pixels = vec![0u8; self.width as usize * self.height as usize * 4];
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(frame.buffer));
+        gl.get_buffer_sub_data(glow::PIXEL_PACK_BUFFER, 0, &mut pixels);
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        self.free_buffers.push(frame.buffer);
+
+        let sender = self.sender.as_ref().context("Recorder already finished")?;
+        sender
+            .send(Frame {
+                index: frame.index,
+                pixels,
+            })
+            .map_err(|_| anyhow::anyhow!("Recorder thread stopped"))?;
+        Ok(true)
+    }
+
+    unsafe fn release(&mut self, gl: &glow::Context) {
+        for frame in self.pending.drain(..) {
+            gl.delete_sync(frame.fence);
+            gl.delete_buffer(frame.buffer);
+        }
+        for buffer in self.free_buffers.drain(..) {
+            gl.delete_buffer(buffer);
+        }
+    }
+}
+
+enum FrameSink {
+    Png {
+        dir: PathBuf,
+        width: u32,
+        height: u32,
+    },
+    Y4m {
+        out: BufWriter<File>,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl FrameSink {
+    fn create(settings: &RecordSettings, width: u32, height: u32) -> anyhow::Result<Self> {
+        match settings.format {
+            RecordFormat::Png => {
+                std::fs::create_dir_all(&settings.path).with_context(|| {
+                    format!("Failed to create {}", settings.path.display())
+                })?;
+                Ok(Self::Png {
+                    dir: settings.path.clone(),
+                    width,
+                    height,
+                })
+            }
+            RecordFormat::Y4m => {
+                if let Some(parent) = settings.path.parent().filter(|p| !p.as_os_str().is_empty())
+                {
+                    std::fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create {}", parent.display()))?;
+                }
+                let file = File::create(&settings.path)
+                    .with_context(|| format!("Failed to create {}", settings.path.display()))?;
+                let mut out = BufWriter::new(file);
+                let (num, den) = frame_rate_ratio(settings.fps);
+                writeln!(
+                    out,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444 XCOLORRANGE=LIMITED",
+                    width, height, num, den
+                )?;
+                Ok(Self::Y4m { out, width, height })
+            }
+        }
+    }
+
+    fn write(&mut self, frame: &Frame) -> anyhow::Result<()> {
+        match self {
+            Self::Png { dir, width, height } => {
+                let rgb = flip_to_rgb(&frame.pixels, *width as usize, *height as usize);
+                let path = frame_path(dir, frame.index);
+                image::RgbImage::from_raw(*width, *height, rgb)
+                    .context("Frame size mismatch")?
+                    .save(&path)
+                    .with_context(|| format!("Failed to write {}", path.display()))
+            }
+            Self::Y4m { out, width, height } => {
+                let rgb = flip_to_rgb(&frame.pixels, *width as usize, *height as usize);
+                out.write_all(b"FRAME\n")?;
+                out.write_all(&rgb_to_yuv444(&rgb))?;
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        if let Self::Y4m { mut out, .. } = self {
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn frame_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("frame_{:05}.png", index))
+}
+
+fn frame_rate_ratio(fps: f32) -> (u32, u32) {
+    if fps.fract() == 0.0 {
+        (fps as u32, 1)
+    } else {
+        ((fps * 1000.0).round() as u32, 1000)
+    }
+}
+
+// GL rows are bottom-up RGBA; images are top-down RGB.
+fn flip_to_rgb(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for row in pixels.chunks_exact(width * 4).rev().take(height) {
+        for px in row.chunks_exact(4) {
+            rgb.extend_from_slice(&px[..3]);
+        }
+    }
+    rgb
+}
+
+// Planar Y, Cb, Cr with BT.709 coefficients in limited (studio) range.
+fn rgb_to_yuv444(rgb: &[u8]) -> Vec<u8> {
+    let n = rgb.len() / 3;
+    let mut yuv = vec![0u8; n * 3];
+    let (y_plane, chroma) = yuv.split_at_mut(n);
+    let (u_plane, v_plane) = chroma.split_at_mut(n);
+    for (i, px) in rgb.chunks_exact(3).enumerate() {
+        let r = px[0] as f32 / 255.0;
+        let g = px[1] as f32 / 255.0;
+        let b = px[2] as f32 / 255.0;
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let cb = (b - y) / 1.8556;
+        let cr = (r - y) / 1.5748;
+        y_plane[i] = (16.0 + 219.0 * y).round() as u8;
+        u_plane[i] = (128.0 + 224.0 * cb).round() as u8;
+        v_plane[i] = (128.0 + 224.0 * cr).round() as u8;
+    }
+    yuv
+}
@@ -0,0 +1,379 @@
+// Declarative description of the post-processing chain.
+//
+// A `GraphDesc` lists passes in execution order: the fragment shader, which sampler
+// each input is bound to, and the target drawn into. Targets are only declared with
+// a size relative to the window; `RenderGraph::rebuild` works out how long each one
+// is needed and lets targets whose lifetimes don't overlap share a texture.
+// Textures owned outside the graph (the resolved ray march, lookup textures) come
+// in as named imports when the graph is executed. Pass settings are not part of the
+// graph; they come from the shared `PostParams` uniform block.
+
+use glow::HasContext;
+use std::collections::HashMap;
+
+use crate::render_pass::{RenderPass, UniformValue};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetSize {
+    Window,
+    // Window dimensions shifted right by `n`, as used by the bloom mip chain.
+    Mip(u32),
+}
+
+impl TargetSize {
+    fn resolve(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            TargetSize::Window => (width, height),
+            TargetSize::Mip(n) => (width >> n, height >> n),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TargetId(usize);
+
+#[derive(Clone, Copy, Debug)]
+pub enum Input {
+    Target(TargetId),
+    Import(&'static str),
+}
+
+struct PassDesc {
+    shader: &'static str,
+    inputs: Vec<(&'static str, Input)>,
+    output: TargetId,
+}
+
+#[derive(Default)]
+pub struct GraphDesc {
+    targets: Vec<TargetSize>,
+    passes: Vec<PassDesc>,
+    outputs: Vec<TargetId>,
+}
+
+impl GraphDesc {
+    pub fn target(&mut self, size: TargetSize) -> TargetId {
+        self.targets.push(size);
+        TargetId(self.targets.len() - 1)
+    }
+
+    pub fn pass(
+        &mut self,
+        shader: &'static str,
+        inputs: &[(&'static str, Input)],
+        output: TargetId,
+    ) {
+        self.passes.push(PassDesc {
+            shader,
+            inputs: inputs.to_vec(),
+            output,
+        });
+    }
+
+    // Marks a target that is read after the graph has run, so its texture is never
+    // handed to another target.
+    pub fn output(&mut self, target: TargetId) {
+        self.outputs.push(target);
+    }
+
+    pub fn shaders(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|pass| pass.shader)
+    }
+}
+
+struct Surface {
+    texture: glow::Texture,
+    framebuffer: glow::Framebuffer,
+    size: (u32, u32),
+}
+
+pub struct RenderGraph {
+    passes: Vec<PassDesc>,
+    // Surface index and pixel size of every declared target.
+    targets: Vec<(usize, (u32, u32))>,
+    surfaces: Vec<Surface>,
+}
+
+impl RenderGraph {
+    pub fn empty() -> Self {
+        Self {
+            passes: Vec::new(),
+            targets: Vec::new(),
+            surfaces: Vec::new(),
+        }
+    }
+
+    // Replaces the current graph with `desc` at the given window size. Surfaces of
+    // the old graph are reused where the sizes match; the rest are freed.
+    pub unsafe fn rebuild(
+        &mut self,
+        gl: &glow::Context,
+        desc: GraphDesc,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        let allocation = allocate(&desc, width, height)?;
+
+        let mut pool = std::mem::take(&mut self.surfaces);
+        let mut surfaces = Vec::with_capacity(allocation.surfaces.len());
+        for &size in &allocation.surfaces {
+            let surface = match pool.iter().position(|surface| surface.size == size) {
+                Some(index) => pool.swap_remove(index),
+                None => {
+                    let texture = crate::texture::create_color_texture(gl, size.0, size.1)?;
+                    let framebuffer = crate::framebuffer::create_framebuffer(gl, texture)?;
+                    Surface {
+                        texture,
+                        framebuffer,
+                        size,
+                    }
+                }
+            };
+            surfaces.push(surface);
+        }
+        delete_surfaces(gl, &mut pool);
+
+        self.passes = desc.passes;
+        self.targets = allocation.targets;
+        self.surfaces = surfaces;
+        Ok(())
+    }
+
+    pub unsafe fn destroy(&mut self, gl: &glow::Context) {
+        delete_surfaces(gl, &mut self.surfaces);
+        self.passes.clear();
+        self.targets.clear();
+    }
+
+    pub fn texture(&self, target: TargetId) -> glow::Texture {
+        self.surfaces[self.targets[target.0].0].texture
+    }
+
+    pub fn framebuffer(&self, target: TargetId) -> glow::Framebuffer {
+        self.surfaces[self.targets[target.0].0].framebuffer
+    }
+
+    // Runs every pass in order. `programs` holds a compiled pass per shader name.
+    pub unsafe fn execute(
+        &self,
+        gl: &glow::Context,
+        programs: &mut HashMap<&'static str, RenderPass>,
+        imports: &[(&str, glow::Texture)],
+    ) {
+        for pass in &self.passes {
+            let uniforms: Vec<(&str, UniformValue)> = pass
+                .inputs
+                .iter()
+                .map(|&(sampler, input)| {
+                    let texture = match input {
+                        Input::Target(target) => self.texture(target),
+                        Input::Import(name) => imports
+                            .iter()
+                            .find(|(import, _)| *import == name)
+                            .map(|&(_, texture)| texture)
+                            .unwrap_or_else(|| panic!("missing render graph import `{}`", name)),
+                    };
+                    (sampler, UniformValue::Texture2D(texture))
+                })
+                .collect();
+            let (surface, (width, height)) = self.targets[pass.output.0];
+            let program = programs
+                .get_mut(pass.shader)
+                .unwrap_or_else(|| panic!("render graph shader `{}` not loaded", pass.shader));
+            program.render(
+                gl,
+                Some(self.surfaces[surface].framebuffer),
+                width as i32,
+                height as i32,
+                &uniforms,
+            );
+        }
+    }
+}
+
+// Where every target of a graph lives: its surface index and pixel size, and the
+// size of each surface to create.
+struct Allocation {
+    targets: Vec<(usize, (u32, u32))>,
+    surfaces: Vec<(u32, u32)>,
+}
+
+fn allocate(desc: &GraphDesc, width: u32, height: u32) -> anyhow::Result<Allocation> {
+    // Index of the last pass that needs each target. Outputs live to the end.
+    let mut written = vec![false; desc.targets.len()];
+    let mut last_use: Vec<usize> = vec![0; desc.targets.len()];
+    for (index, pass) in desc.passes.iter().enumerate() {
+        for (sampler, input) in &pass.inputs {
+            if let Input::Target(TargetId(target)) = *input {
+                if !written[target] {
+                    anyhow::bail!(
+                        "`{}` reads target {} ({}) before any pass writes it",
+                        pass.shader,
+                        target,
+                        sampler
+                    );
+                }
+                if target == pass.output.0 {
+                    anyhow::bail!("`{}` reads the target it writes", pass.shader);
+                }
+                last_use[target] = index;
+            }
+        }
+        let TargetId(output) = pass.output;
+        written[output] = true;
+        last_use[output] = last_use[output].max(index);
+    }
+    for TargetId(target) in &desc.outputs {
+        last_use[*target] = usize::MAX;
+    }
+
+    // Walk the passes handing out surfaces: a target takes any surface of its size
+    // whose previous holder was last read by an earlier pass.
+    let mut slots: Vec<((u32, u32), usize)> = Vec::new();
+    let mut targets = vec![None; desc.targets.len()];
+    for (index, pass) in desc.passes.iter().enumerate() {
+        let TargetId(target) = pass.output;
+        if targets[target].is_some() {
+            continue;
+        }
+        let size = desc.targets[target].resolve(width, height);
+        if size.0 == 0 || size.1 == 0 {
+            anyhow::bail!("`{}` draws into an empty {:?} target", pass.shader, size);
+        }
+        let free = slots
+            .iter()
+            .position(|&(slot_size, busy_until)| slot_size == size && busy_until < index);
+        let slot = match free {
+            Some(slot) => slot,
+            None => {
+                slots.push((size, 0));
+                slots.len() - 1
+            }
+        };
+        slots[slot].1 = last_use[target];
+        targets[target] = Some((slot, size));
+    }
+    let targets = targets
+        .into_iter()
+        .enumerate()
+        .map(|(target, assigned)| {
+            assigned.ok_or_else(|| anyhow::anyhow!("target {} is never written", target))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Allocation {
+        targets,
+        surfaces: slots.into_iter().map(|(size, _)| size).collect(),
+    })
+}
+
+unsafe fn delete_surfaces(gl: &glow::Context, surfaces: &mut Vec<Surface>) {
+    for surface in surfaces.drain(..) {
+        gl.delete_framebuffer(surface.framebuffer);
+        gl.delete_texture(surface.texture);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Same shape as the renderer's post chain: a bright pass, a mip chain down and
+    // back up, a composite and a tonemapped output.
+    fn bloom_chain(levels: u32) -> GraphDesc {
+        let mut graph = GraphDesc::default();
+        let scene = Input::Import("scene");
+        let bright = graph.target(TargetSize::Window);
+        graph.pass("bright", &[("texture0", scene)], bright);
+        let mut down = vec![bright];
+        for level in 1..=levels {
+            let dst = graph.target(TargetSize::Mip(level));
+            let src = *down.last().unwrap();
+            graph.pass("down", &[("texture0", Input::Target(src))], dst);
+            down.push(dst);
+        }
+        let mut bloom = *down.last().unwrap();
+        for level in (0..levels).rev() {
+            let dst = graph.target(TargetSize::Mip(level));
+            let inputs = [
+                ("texture0", Input::Target(bloom)),
+                ("texture1", Input::Target(down[level as usize])),
+            ];
+            graph.pass("up", &inputs, dst);
+            bloom = dst;
+        }
+        let composite = graph.target(TargetSize::Window);
+        let inputs = [("texture0", scene), ("texture1", Input::Target(bloom))];
+        graph.pass("composite", &inputs, composite);
+        let tonemapped = graph.target(TargetSize::Window);
+        graph.pass("tonemap", &[("texture0", Input::Target(composite))], tonemapped);
+        graph.output(tonemapped);
+        graph
+    }
+
+    // Passes from the first write of each target to its last read (or the end, for
+    // outputs).
+    fn lifetimes(desc: &GraphDesc) -> Vec<(usize, usize)> {
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; desc.targets.len()];
+        for (index, pass) in desc.passes.iter().enumerate() {
+            for (_, input) in &pass.inputs {
+                if let Input::Target(TargetId(target)) = *input {
+                    lifetimes[target].as_mut().unwrap().1 = index;
+                }
+            }
+            let live = lifetimes[pass.output.0].get_or_insert((index, index));
+            live.1 = live.1.max(index);
+        }
+        for TargetId(target) in &desc.outputs {
+            lifetimes[*target].as_mut().unwrap().1 = usize::MAX;
+        }
+        lifetimes.into_iter().map(Option::unwrap).collect()
+    }
+
+    #[test]
+    fn shared_surfaces_are_never_read_while_reused() {
+        let desc = bloom_chain(5);
+        let allocation = allocate(&desc, 1920, 1080).unwrap();
+        assert!(allocation.surfaces.len() < desc.targets.len(), "nothing was aliased");
+
+        let lifetimes = lifetimes(&desc);
+        for a in 0..desc.targets.len() {
+            for b in a + 1..desc.targets.len() {
+                if allocation.targets[a].0 != allocation.targets[b].0 {
+                    continue;
+                }
+                let ((start_a, end_a), (start_b, end_b)) = (lifetimes[a], lifetimes[b]);
+                assert!(
+                    end_a < start_b || end_b < start_a,
+                    "targets {} {:?} and {} {:?} share a surface",
+                    a,
+                    lifetimes[a],
+                    b,
+                    lifetimes[b]
+                );
+            }
+        }
+        for (target, &(surface, size)) in allocation.targets.iter().enumerate() {
+            assert_eq!(allocation.surfaces[surface], size, "target {}", target);
+        }
+    }
+
+    #[test]
+    fn read_before_write_is_rejected() {
+        let mut desc = GraphDesc::default();
+        let early = desc.target(TargetSize::Window);
+        let late = desc.target(TargetSize::Window);
+        desc.pass("first", &[("texture0", Input::Target(late))], early);
+        desc.pass("second", &[("texture0", Input::Import("scene"))], late);
+        let error = allocate(&desc, 64, 64).err().unwrap().to_string();
+        assert!(error.contains("before any pass writes it"), "{}", error);
+    }
+
+    #[test]
+    fn reading_own_target_is_rejected() {
+        let mut desc = GraphDesc::default();
+        let target = desc.target(TargetSize::Window);
+        desc.pass("first", &[("texture0", Input::Import("scene"))], target);
+        desc.pass("feedback", &[("texture0", Input::Target(target))], target);
+        assert!(allocate(&desc, 64, 64).is_err());
+    }
+}
@@ -1,38 +1,345 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 use glow::HasContext;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::shader_utils::Defines;
+
+// A value for one uniform. Samplers take the texture; units are assigned in the
+// order they appear in the list passed to `render`.
+#[derive(Clone, Copy, Debug)]
+pub enum UniformValue {
+    Float(f32),
+    Int(i32),
+    Bool(bool),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Mat3([f32; 9]),
+    Mat4([f32; 16]),
+    Texture2D(glow::Texture),
+    Texture3D(glow::Texture),
+    Cubemap(glow::Texture),
+}
+
+impl UniformValue {
+    // The GL type an active uniform needs to accept this value.
+    fn gl_type(&self) -> u32 {
+        match self {
+            UniformValue::Float(_) => glow::FLOAT,
+            UniformValue::Int(_) => glow::INT,
+            UniformValue::Bool(_) => glow::BOOL,
+            UniformValue::Vec2(_) => glow::FLOAT_VEC2,
+            UniformValue::Vec3(_) => glow::FLOAT_VEC3,
+            UniformValue::Vec4(_) => glow::FLOAT_VEC4,
+            UniformValue::Mat3(_) => glow::FLOAT_MAT3,
+            UniformValue::Mat4(_) => glow::FLOAT_MAT4,
+            UniformValue::Texture2D(_) => glow::SAMPLER_2D,
+            UniformValue::Texture3D(_) => glow::SAMPLER_3D,
+            UniformValue::Cubemap(_) => glow::SAMPLER_CUBE,
+        }
+    }
+}
+
+impl From<f32> for UniformValue {
+    fn from(value: f32) -> Self {
+        UniformValue::Float(value)
+    }
+}
+
+impl From<i32> for UniformValue {
+    fn from(value: i32) -> Self {
+        UniformValue::Int(value)
+    }
+}
+
+impl From<bool> for UniformValue {
+    fn from(value: bool) -> Self {
+        UniformValue::Bool(value)
+    }
+}
+
+impl From<glam::Vec2> for UniformValue {
+    fn from(value: glam::Vec2) -> Self {
+        UniformValue::Vec2(value.to_array())
+    }
+}
+
+impl From<glam::Vec3> for UniformValue {
+    fn from(value: glam::Vec3) -> Self {
+        UniformValue::Vec3(value.to_array())
+    }
+}
+
+impl From<glam::Vec4> for UniformValue {
+    fn from(value: glam::Vec4) -> Self {
+        UniformValue::Vec4(value.to_array())
+    }
+}
+
+impl From<glam::Mat3> for UniformValue {
+    fn from(value: glam::Mat3) -> Self {
+        UniformValue::Mat3(value.to_cols_array())
+    }
+}
+
+impl From<glam::Mat4> for UniformValue {
+    fn from(value: glam::Mat4) -> Self {
+        UniformValue::Mat4(value.to_cols_array())
+    }
+}
+
+// Values of the uniforms declared with an initializer, by shader file. GLSL ES has no
+// initializers, so they are set from here on every target.
+include!(concat!(env!("OUT_DIR"), "/uniform_defaults.rs"));
+
+fn glsl_type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        glow::FLOAT => "float",
+        glow::INT => "int",
+        glow::BOOL => "bool",
+        glow::FLOAT_VEC2 => "vec2",
+        glow::FLOAT_VEC3 => "vec3",
+        glow::FLOAT_VEC4 => "vec4",
+        glow::FLOAT_MAT3 => "mat3",
+        glow::FLOAT_MAT4 => "mat4",
+        glow::SAMPLER_2D => "sampler2D",
+        glow::SAMPLER_3D => "sampler3D",
+        glow::SAMPLER_CUBE => "samplerCube",
+        _ => "another type",
+    }
+}
+
+pub(crate) fn warn(message: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("warning: {}", message);
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::warn_1(&message.into());
+}
+
+struct ActiveUniform {
+    location: glow::UniformLocation,
+    gl_type: u32,
+}
+
+// Sets a value that is not a texture on the program in use.
+unsafe fn set_value(gl: &glow::Context, location: &glow::UniformLocation, value: UniformValue) {
+    let location = Some(location);
+    match value {
+        UniformValue::Float(v) => gl.uniform_1_f32(location, v),
+        UniformValue::Int(v) => gl.uniform_1_i32(location, v),
+        UniformValue::Bool(v) => gl.uniform_1_i32(location, v as i32),
+        UniformValue::Vec2(v) => gl.uniform_2_f32_slice(location, &v),
+        UniformValue::Vec3(v) => gl.uniform_3_f32_slice(location, &v),
+        UniformValue::Vec4(v) => gl.uniform_4_f32_slice(location, &v),
+        UniformValue::Mat3(v) => gl.uniform_matrix_3_f32_slice(location, false, &v),
+        UniformValue::Mat4(v) => gl.uniform_matrix_4_f32_slice(location, false, &v),
+        UniformValue::Texture2D(_) | UniformValue::Texture3D(_) | UniformValue::Cubemap(_) => {}
+    }
+}
+
+// Compiles and links a program, attaches its uniform blocks and lists its uniforms.
+unsafe fn build_program(
+    gl: &glow::Context,
+    vertex_name: &str,
+    fragment_name: &str,
+    defines: &[(&'static str, i32)],
+) -> anyhow::Result<(glow::Program, HashMap<String, ActiveUniform>)> {
+    let program =
+        crate::shader_utils::create_shader_program(gl, vertex_name, fragment_name, defines)?;
+    if let Err(e) = crate::uniform_blocks::bind_blocks(gl, program) {
+        gl.delete_program(program);
+        anyhow::bail!("{}: {}", fragment_name, e);
+    }
+
+    let mut uniforms = HashMap::new();
+    for index in 0..gl.get_active_uniforms(program) {
+        let Some(active) = gl.get_active_uniform(program, index) else {
+            continue;
+        };
+        // Arrays are reported as `name[0]`.
+        let name = active.name.trim_end_matches("[0]").to_string();
+        if let Some(location) = gl.get_uniform_location(program, &name) {
+            let gl_type = active.utype;
+            uniforms.insert(name, ActiveUniform { location, gl_type });
+        }
+    }
+
+    gl.use_program(Some(program));
+    for name in [vertex_name, fragment_name] {
+        let defaults = UNIFORM_DEFAULTS.iter().filter(|(shader, _)| *shader == name);
+        for &(uniform, value) in defaults.flat_map(|(_, values)| values.iter()) {
+            match uniforms.get(uniform) {
+                Some(active) if active.gl_type == value.gl_type() => {
+                    set_value(gl, &active.location, value)
+                }
+                _ => {}
+            }
+        }
+    }
+    gl.use_program(None);
+    Ok((program, uniforms))
+}
+
+// Where a pass was built from, so it can be rebuilt when one of its files changes.
+#[cfg(not(target_arch = "wasm32"))]
+struct PassSource {
+    vertex_name: String,
+    fragment_name: String,
+    defines: Defines,
+    // Every file the program was built from, relative to the shader directory.
+    files: Vec<String>,
+}
 
 pub struct RenderPass {
+    name: String,
     program: glow::Program,
     vao: glow::VertexArray,
-    uniform_locations: HashMap<String, Option<glow::UniformLocation>>,
+    // Uniforms the linker kept; anything else is misspelled or optimized out.
+    uniforms: HashMap<String, ActiveUniform>,
+    // Uniforms that may legitimately be compiled out, e.g. by a shader variant.
+    optional: HashSet<String>,
+    warned: HashSet<String>,
+    // Drawing the magenta stand-in because the pass's own shader failed to build.
+    fallback: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    source: PassSource,
 }
 
 impl RenderPass {
     pub unsafe fn new(
         gl: &glow::Context,
-        vertex_path: &str,
-        fragment_path: &str,
+        vertex_name: &str,
+        fragment_name: &str,
         vao: glow::VertexArray,
+        defines: &[(&'static str, i32)],
     ) -> anyhow::Result<Self> {
-        let program = crate::shader_utils::create_shader_program(gl, vertex_path, fragment_path)?;
-        Ok(Self {
+        let (program, uniforms) = build_program(gl, vertex_name, fragment_name, defines)?;
+        Ok(Self::with_program(vertex_name, fragment_name, vao, defines, program, uniforms))
+    }
+
+    // A pass that draws flat magenta in place of `fragment_name`, for when that shader
+    // does not build. It keeps the paths, so a later `reload` can replace it.
+    pub unsafe fn fallback(
+        gl: &glow::Context,
+        vertex_name: &str,
+        fragment_name: &str,
+        vao: glow::VertexArray,
+        defines: &[(&'static str, i32)],
+    ) -> anyhow::Result<Self> {
+        let program = crate::shader_utils::create_fallback_program(gl)?;
+        let mut pass =
+            Self::with_program(vertex_name, fragment_name, vao, defines, program, HashMap::new());
+        pass.fallback = true;
+        Ok(pass)
+    }
+
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+    fn with_program(
+        vertex_name: &str,
+        fragment_name: &str,
+        vao: glow::VertexArray,
+        defines: &[(&'static str, i32)],
+        program: glow::Program,
+        uniforms: HashMap<String, ActiveUniform>,
+    ) -> Self {
+        Self {
+            name: fragment_name.to_string(),
             program,
             vao,
-            uniform_locations: HashMap::new(),
-        })
+            uniforms,
+            optional: HashSet::new(),
+            warned: HashSet::new(),
+            fallback: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            source: PassSource {
+                vertex_name: vertex_name.to_string(),
+                fragment_name: fragment_name.to_string(),
+                defines: defines.to_vec(),
+                files: crate::shader_utils::shader_files(&[vertex_name, fragment_name]),
+            },
+        }
+    }
+
+    // File name of the fragment shader.
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
-    fn uniform_location(
+    // Whether the program was built from `file` (relative to the shader directory).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn depends_on(&self, file: &str) -> bool {
+        self.source.files.iter().any(|source| source == file)
+    }
+
+    // Rebuilds the program from its files. On failure the current program (or the
+    // fallback) is kept.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn reload(&mut self, gl: &glow::Context) -> anyhow::Result<()> {
+        let source = &mut self.source;
+        let paths = [source.vertex_name.as_str(), source.fragment_name.as_str()];
+        source.files = crate::shader_utils::shader_files(&paths);
+        let (program, uniforms) =
+            build_program(gl, &source.vertex_name, &source.fragment_name, &source.defines)?;
+        gl.delete_program(self.program);
+        self.program = program;
+        self.uniforms = uniforms;
+        self.warned.clear();
+        self.fallback = false;
+        Ok(())
+    }
+
+    // Values for these names are dropped silently when the program does not use them.
+    pub fn allow_inactive(&mut self, names: &[&str]) {
+        self.optional.extend(names.iter().map(|name| name.to_string()));
+    }
+
+    // Reports each problem once per pass instead of every frame.
+    fn warn_once(&mut self, uniform: &str, message: impl FnOnce() -> String) {
+        if self.warned.insert(uniform.to_string()) {
+            warn(&format!("{}: {}", self.name, message()));
+        }
+    }
+
+    unsafe fn apply(
         &mut self,
         gl: &glow::Context,
         name: &str,
-    ) -> Option<&glow::UniformLocation> {
-        if !self.uniform_locations.contains_key(name) {
-            let loc = unsafe { gl.get_uniform_location(self.program, name) };
-            self.uniform_locations.insert(name.to_string(), loc);
+        value: UniformValue,
+        unit: &mut u32,
+    ) {
+        let Some(uniform) = self.uniforms.get(name) else {
+            if self.optional.contains(name) {
+                return;
+            }
+            self.warn_once(name, || format!("`{}` is not an active uniform", name));
+            return;
+        };
+        if uniform.gl_type != value.gl_type() {
+            let declared = glsl_type_name(uniform.gl_type);
+            self.warn_once(name, || {
+                format!(
+                    "`{}` is declared as {} but was given a {}",
+                    name,
+                    declared,
+                    glsl_type_name(value.gl_type())
+                )
+            });
+            return;
+        }
+
+        let mut bind = |target: u32, texture: glow::Texture| {
+            gl.active_texture(glow::TEXTURE0 + *unit);
+            gl.bind_texture(target, Some(texture));
+            gl.uniform_1_i32(Some(&uniform.location), *unit as i32);
+            *unit += 1;
+        };
+        match value {
+            UniformValue::Texture2D(texture) => bind(glow::TEXTURE_2D, texture),
+            UniformValue::Texture3D(texture) => bind(glow::TEXTURE_3D, texture),
+            UniformValue::Cubemap(texture) => bind(glow::TEXTURE_CUBE_MAP, texture),
+            _ => set_value(gl, &uniform.location, value),
         }
-        self.uniform_locations.get(name).and_then(|loc| loc.as_ref())
     }
 
     pub unsafe fn render(
@@ -41,10 +348,7 @@ impl RenderPass {
         target_framebuffer: Option<glow::Framebuffer>,
         width: i32,
         height: i32,
-        float_uniforms: &[(&str, f32)],
-        texture_uniforms: &[(&str, glow::Texture)],
-        texture_3d_uniforms: &[(&str, glow::Texture)],
-        cubemap_uniforms: &[(&str, glow::Texture)],
+        uniforms: &[(&str, UniformValue)],
     ) {
         gl.bind_framebuffer(glow::FRAMEBUFFER, target_framebuffer);
         gl.viewport(0, 0, width, height);
@@ -53,41 +357,16 @@ impl RenderPass {
 
         gl.use_program(Some(self.program));
 
-        if let Some(loc) = self.uniform_location(gl, "resolution") {
-            gl.uniform_2_f32(Some(loc), width as f32, height as f32);
-        }
-
-        for (name, val) in float_uniforms {
-            if let Some(loc) = self.uniform_location(gl, name) {
-                gl.uniform_1_f32(Some(loc), *val);
-            }
-        }
-
-        let mut unit: u32 = 0;
-        for (name, tex) in texture_uniforms {
-            if let Some(loc) = self.uniform_location(gl, name) {
-                gl.active_texture(glow::TEXTURE0 + unit);
-                gl.bind_texture(glow::TEXTURE_2D, Some(*tex));
-                gl.uniform_1_i32(Some(loc), unit as i32);
-                unit += 1;
-            }
+        // Set for every pass; only passes that declare it need it.
+        if let Some(resolution) = self.uniforms.get("resolution") {
+            gl.uniform_2_f32(Some(&resolution.location), width as f32, height as f32);
         }
 
-        for (name, tex) in texture_3d_uniforms {
-            if let Some(loc) = self.uniform_location(gl, name) {
-                gl.active_texture(glow::TEXTURE0 + unit);
-                gl.bind_texture(glow::TEXTURE_3D, Some(*tex));
-                gl.uniform_1_i32(Some(loc), unit as i32);
-                unit += 1;
-            }
-        }
-        
-        for (name, tex) in cubemap_uniforms {
-            if let Some(loc) = self.uniform_location(gl, name) {
-                gl.active_texture(glow::TEXTURE0 + unit);
-                gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(*tex));
-                gl.uniform_1_i32(Some(loc), unit as i32);
-                unit += 1;
+        // The fallback declares none of the pass's uniforms.
+        if !self.fallback {
+            let mut unit: u32 = 0;
+            for &(name, value) in uniforms {
+                self.apply(gl, name, value, &mut unit);
             }
         }
 
@@ -1,19 +1,49 @@
 use glow::HasContext;
+use crate::app_state::{AppState, QualitySettings};
+use glam::{Mat3, Vec3};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::render_graph::{GraphDesc, Input, RenderGraph, TargetId, TargetSize};
+use crate::render_pass::{warn, RenderPass, UniformValue};
+use crate::shader_utils::{Defines, Diagnostic};
+use crate::uniform_blocks::{std140_mat3, BlackHoleParams, PostParams, UniformBuffer};
+
+pub(crate) const MAX_BLOOM_ITER: usize = 8;
+// Weight of the newest frame in the TAA history.
+const TAA_BLEND: f32 = 0.1;
+// Length of the subpixel jitter sequence.
+const TAA_JITTER_PHASES: u32 = 16;
+// Ray-march settings for idle-time accumulation: half-length steps, twice as many,
+// and a finer floor near the horizon.
+const PROGRESSIVE_STEP_SCALE: f32 = 0.5;
+const PROGRESSIVE_MIN_STEP: f32 = 0.01;
+const PROGRESSIVE_MAX_STEPS: f32 = 600.0;
+// Dither added to the ray-march output: one step on the 8-bit targets of the wasm
+// build, nothing on native half-float targets.
+#[cfg(target_arch = "wasm32")]
+const TARGET_DITHER: f32 = 1.0 / 255.0;
 #[cfg(not(target_arch = "wasm32"))]
-use anyhow::Context;
-#[cfg(not(target_arch = "wasm32"))]
-use std::path::PathBuf;
-use crate::app_state::AppState;
-use crate::render_pass::RenderPass;
-
-const MAX_BLOOM_ITER: usize = 8;
-
-fn flag(value: bool) -> f32 {
-    if value {
-        1.0
-    } else {
-        0.0
+const TARGET_DITHER: f32 = 0.0;
+// Triangular dither before the 8-bit output: one quantization step.
+const OUTPUT_DITHER: f32 = 1.0 / 255.0;
+
+// Radical inverse of `index` in `base`: the Halton low-discrepancy sequence in [0, 1).
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
     }
+    result
+}
+
+// Camera the previous frame was rendered from, for TAA reprojection.
+#[derive(Clone, Copy)]
+struct FrameCamera {
+    position: Vec3,
+    view: Mat3,
+    fov_scale: f32,
 }
 
 fn delete_optional_texture(gl: &glow::Context, texture: &mut Option<glow::Texture>) {
@@ -51,160 +81,255 @@ fn delete_framebuffers(gl: &glow::Context, framebuffers: &mut Vec<glow::Framebuf
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn find_asset_root() -> anyhow::Result<PathBuf> {
-    let exe_path = std::env::current_exe().context("Failed to resolve executable path")?;
-    let mut dir = exe_path
-        .parent()
-        .context("Executable has no parent directory")?
-        .to_path_buf();
-
-    for _ in 0..5 {
-        if dir.join("assets").is_dir() && dir.join("shader").is_dir() {
-            return Ok(dir);
-        }
-        match dir.parent() {
-            Some(parent) => dir = parent.to_path_buf(),
-            None => break,
+// Vertex shader shared by every full-screen pass.
+const QUAD_VERTEX: &str = "simple.vert";
+
+// Compiles full-screen passes from `shader/` against the shared quad.
+struct ShaderLoader {
+    quad_vao: glow::VertexArray,
+}
+
+impl ShaderLoader {
+    unsafe fn load_variant(
+        &self,
+        gl: &glow::Context,
+        f_name: &str,
+        defines: &[(&'static str, i32)],
+    ) -> anyhow::Result<RenderPass> {
+        RenderPass::new(gl, QUAD_VERTEX, f_name, self.quad_vao, defines)
+    }
+
+    // Like `load_variant`, but a shader that does not build is recorded in `errors`
+    // and replaced by the magenta fallback instead of failing the caller.
+    unsafe fn load_or_fallback(
+        &self,
+        gl: &glow::Context,
+        f_name: &str,
+        defines: &[(&'static str, i32)],
+        errors: &mut BTreeMap<String, Vec<Diagnostic>>,
+    ) -> anyhow::Result<RenderPass> {
+        match RenderPass::new(gl, QUAD_VERTEX, f_name, self.quad_vao, defines) {
+            Ok(pass) => Ok(pass),
+            Err(e) => {
+                warn(&format!("{}: {:#}", f_name, e));
+                errors.insert(f_name.to_string(), crate::shader_utils::diagnostics(&e));
+                RenderPass::fallback(gl, QUAD_VERTEX, f_name, self.quad_vao, defines)
+            }
         }
     }
+}
 
-    Err(anyhow::anyhow!(
-        "Unable to locate `assets/` and `shader/`. Place them next to the binary or run from the project root."
-    ))
+// Settings that are compiled into blackhole_main.frag rather than passed as uniforms,
+// so the march loop neither branches on them nor has a dynamic bound.
+fn blackhole_defines(state: &AppState) -> Defines {
+    vec![
+        ("GRAVITATIONAL_LENSING", state.gravitational_lensing as i32),
+        ("RENDER_BLACK_HOLE", state.render_black_hole as i32),
+        ("ADISK_ENABLED", state.adisk_enabled as i32),
+        ("ADISK_PARTICLE", state.adisk_particle as i32),
+        ("ADISK_NOISE_LOD", state.adisk_noise_lod.max(0.0) as i32),
+    ]
+}
+
+// Samplers only the disk reads; variants without it drop them.
+const DISK_SAMPLERS: &[&str] = &["colorMap", "noiseTex"];
+
+// The post-processing chain from the resolved ray march (imported as `scene`) to the
+// tonemapped image, which is returned alongside. `upscale` adds the pass that brings
+// a reduced render scale back to window size.
+fn post_chain(width: u32, height: u32, upscale: bool) -> (GraphDesc, TargetId) {
+    let mut graph = GraphDesc::default();
+    let mut scene = Input::Import("scene");
+    if upscale {
+        let upscaled = graph.target(TargetSize::Window);
+        graph.pass("upscale.frag", &[("texture0", scene)], upscaled);
+        scene = Input::Target(upscaled);
+    }
+
+    let brightness = graph.target(TargetSize::Window);
+    graph.pass("bloom_brightness_pass.frag", &[("texture0", scene)], brightness);
+
+    let lens_flare = graph.target(TargetSize::Window);
+    let flare_inputs = [("texture0", Input::Target(brightness))];
+    graph.pass("lens_flare.frag", &flare_inputs, lens_flare);
+
+    let levels = (1..=MAX_BLOOM_ITER as u32)
+        .take_while(|&level| width >> level > 0 && height >> level > 0)
+        .count();
+    let mut downsampled = Vec::with_capacity(levels);
+    for i in 0..levels {
+        let src = if i == 0 { brightness } else { downsampled[i - 1] };
+        let dst = graph.target(TargetSize::Mip(i as u32 + 1));
+        let inputs = [("texture0", Input::Target(src))];
+        graph.pass("bloom_downsample.frag", &inputs, dst);
+        downsampled.push(dst);
+    }
+    let mut bloom = None;
+    for i in (0..levels).rev() {
+        let tex0 = bloom.unwrap_or(downsampled[i]);
+        let tex1 = if i == 0 { brightness } else { downsampled[i - 1] };
+        let dst = graph.target(TargetSize::Mip(i as u32));
+        let inputs = [("texture0", Input::Target(tex0)), ("texture1", Input::Target(tex1))];
+        graph.pass("bloom_upsample.frag", &inputs, dst);
+        bloom = Some(dst);
+    }
+
+    let bloom_final = graph.target(TargetSize::Window);
+    let composite_inputs = [
+        ("texture0", scene),
+        ("texture1", bloom.map_or(scene, Input::Target)),
+        ("texture2", Input::Target(lens_flare)),
+    ];
+    graph.pass("bloom_composite.frag", &composite_inputs, bloom_final);
+
+    let tonemapped = graph.target(TargetSize::Window);
+    let tone_inputs = [
+        ("texture0", Input::Target(bloom_final)),
+        ("blueNoise", Input::Import("blueNoise")),
+    ];
+    graph.pass("tonemapping.frag", &tone_inputs, tonemapped);
+    graph.output(tonemapped);
+
+    (graph, tonemapped)
 }
 
 pub struct Renderer {
-    pass_blackhole: RenderPass,
-    pass_brightness: RenderPass,
-    pass_lens_flare: RenderPass,
-    pass_downsample: RenderPass,
-    pass_upsample: RenderPass,
-    pass_composite: RenderPass,
-    pass_tonemapping: RenderPass,
+    shaders: ShaderLoader,
+    // One program per combination of `blackhole_defines`, compiled on first use.
+    blackhole_variants: HashMap<Defines, RenderPass>,
+    // Variants that failed to compile, not retried until the shader changes, and the
+    // last variant that did, which renders in their place.
+    failed_variants: HashSet<Defines>,
+    last_variant: Defines,
+    // Compile and link errors by shader file name. A pass that fails to rebuild
+    // keeps rendering with its previous program, or the magenta fallback if it never
+    // built.
+    shader_errors: BTreeMap<String, Vec<Diagnostic>>,
+    pass_taa: RenderPass,
+    pass_accumulate: RenderPass,
     pass_passthrough: RenderPass,
 
     tex_blackhole: Option<glow::Texture>,
     fbo_blackhole: Option<glow::Framebuffer>,
-    
-    tex_brightness: Option<glow::Texture>,
-    fbo_brightness: Option<glow::Framebuffer>,
-
-    tex_lens_flare: Option<glow::Texture>,
-    fbo_lens_flare: Option<glow::Framebuffer>,
-
-    tex_downsampled: Vec<glow::Texture>,
-    fbo_downsampled: Vec<glow::Framebuffer>,
-
-    tex_upsampled: Vec<glow::Texture>,
-    fbo_upsampled: Vec<glow::Framebuffer>,
 
-    tex_bloom_final: Option<glow::Texture>,
-    fbo_bloom_final: Option<glow::Framebuffer>,
-
-    tex_tonemapped: Option<glow::Texture>,
-    fbo_tonemapped: Option<glow::Framebuffer>,
+    // TAA ping-pong at the black hole pass resolution: one holds last frame's
+    // output, the other receives this frame's.
+    tex_history: Vec<glow::Texture>,
+    fbo_history: Vec<glow::Framebuffer>,
+    history_index: usize,
+    history_camera: Option<FrameCamera>,
+    frame_index: u32,
+    // Inputs of the last rendered frame. Rendering the same inputs again means the
+    // view is idle, and the history buffers accumulate instead of running TAA.
+    last_frame: Option<(AppState, f32)>,
+    accumulated: u32,
+    // Most samples the history format can average; see `accumulation_sample_limit`.
+    accumulation_limit: u32,
+    settled: bool,
+
+    // Everything after the ray march, at window resolution. Built by `post_chain`
+    // and rebuilt when the window size or the need for an upscale pass changes.
+    post_graph: RenderGraph,
+    post_programs: HashMap<&'static str, RenderPass>,
+    post_output: Option<TargetId>,
+    post_upscale: bool,
+
+    // Shared parameter blocks, uploaded once per frame.
+    blackhole_params: UniformBuffer<BlackHoleParams>,
+    post_params: UniformBuffer<PostParams>,
 
     galaxy_cubemap: glow::Texture,
     color_map: glow::Texture,
     noise_tex: glow::Texture,
+    blue_noise_tex: glow::Texture,
 
     width: u32,
     height: u32,
+    render_scale: f32,
+    blackhole_width: u32,
+    blackhole_height: u32,
 }
 
 impl Renderer {
     pub unsafe fn new(gl: &glow::Context, width: u32, height: u32) -> anyhow::Result<Self> {
-        let quad_vao = crate::render_utils::create_quad_vao(gl)?;
-
+        // Without an asset directory every pass would fall back, so stop here instead.
         #[cfg(not(target_arch = "wasm32"))]
-        let (shader_dir, asset_dir) = {
-            let base_dir = find_asset_root()?;
-            (base_dir.join("shader"), base_dir.join("assets"))
-        };
+        crate::assets::dir()?;
 
-        #[cfg(not(target_arch = "wasm32"))]
-        let load_pass = |v_name, f_name| -> anyhow::Result<RenderPass> {
-            let v_path = shader_dir.join(v_name);
-            let f_path = shader_dir.join(f_name);
-            let v_path = v_path
-                .to_str()
-                .with_context(|| format!("Non-UTF8 shader path: {}", v_path.display()))?;
-            let f_path = f_path
-                .to_str()
-                .with_context(|| format!("Non-UTF8 shader path: {}", f_path.display()))?;
-            RenderPass::new(gl, v_path, f_path, quad_vao)
-        };
-
-        #[cfg(target_arch = "wasm32")]
-        let load_pass = |v_name, f_name| -> anyhow::Result<RenderPass> {
-            RenderPass::new(gl, v_name, f_name, quad_vao)
-        };
-
-        let pass_blackhole = load_pass("simple.vert", "blackhole_main.frag")?;
-        let pass_brightness = load_pass("simple.vert", "bloom_brightness_pass.frag")?;
-        let pass_lens_flare = load_pass("simple.vert", "lens_flare.frag")?;
-        let pass_downsample = load_pass("simple.vert", "bloom_downsample.frag")?;
-        let pass_upsample = load_pass("simple.vert", "bloom_upsample.frag")?;
-        let pass_composite = load_pass("simple.vert", "bloom_composite.frag")?;
-        let pass_tonemapping = load_pass("simple.vert", "tonemapping.frag")?;
-        let pass_passthrough = load_pass("simple.vert", "passthrough.frag")?;
+        let quad_vao = crate::render_utils::create_quad_vao(gl)?;
 
-        #[cfg(not(target_arch = "wasm32"))]
-        let (color_map, galaxy_cubemap) = {
-            let color_map_path = asset_dir.join("color_map.png");
-            let galaxy_path = asset_dir.join("skybox_nebula_dark");
-            let color_map_path = color_map_path
-                .to_str()
-                .with_context(|| format!("Non-UTF8 texture path: {}", color_map_path.display()))?;
-            let galaxy_path = galaxy_path
-                .to_str()
-                .with_context(|| format!("Non-UTF8 cubemap path: {}", galaxy_path.display()))?;
-            let color_map = crate::texture::load_texture_2d(gl, color_map_path)?;
-            let galaxy_cubemap = crate::texture::load_cubemap(gl, galaxy_path)?;
-            (color_map, galaxy_cubemap)
-        };
+        let shaders = ShaderLoader { quad_vao };
+
+        // Every pass is compiled up front so shader errors show at startup. A pass
+        // that does not build draws magenta and is listed in `shader_errors`.
+        let mut shader_errors = BTreeMap::new();
+        let defines = blackhole_defines(&AppState::default());
+        let mut pass_blackhole =
+            shaders.load_or_fallback(gl, "blackhole_main.frag", &defines, &mut shader_errors)?;
+        pass_blackhole.allow_inactive(DISK_SAMPLERS);
+        let blackhole_variants = HashMap::from([(defines.clone(), pass_blackhole)]);
+        let pass_taa = shaders.load_or_fallback(gl, "taa_resolve.frag", &[], &mut shader_errors)?;
+        let pass_accumulate =
+            shaders.load_or_fallback(gl, "accumulate.frag", &[], &mut shader_errors)?;
+        let pass_passthrough =
+            shaders.load_or_fallback(gl, "passthrough.frag", &[], &mut shader_errors)?;
+
+        // Including the passes the post chain only uses once the render scale drops.
+        let mut post_programs = HashMap::new();
+        let (all_post_passes, _) = post_chain(2, 2, true);
+        for shader in all_post_passes.shaders() {
+            if !post_programs.contains_key(shader) {
+                let pass = shaders.load_or_fallback(gl, shader, &[], &mut shader_errors)?;
+                post_programs.insert(shader, pass);
+            }
+        }
 
-        #[cfg(target_arch = "wasm32")]
-        let (color_map, galaxy_cubemap) = {
-            let color_map = crate::texture::load_texture_2d(gl, "color_map.png")?;
-            let galaxy_cubemap = crate::texture::load_cubemap(gl, "skybox_nebula_dark")?;
-            (color_map, galaxy_cubemap)
-        };
+        let color_map = crate::texture::load_texture_2d(gl, "assets/color_map.png")?;
+        let galaxy_cubemap = crate::texture::load_cubemap(gl, "assets/skybox_nebula_dark")?;
 
         let noise_tex = crate::texture::create_noise_texture_3d(gl)?;
+        let blue_noise_tex = crate::texture::create_blue_noise_texture(gl)?;
+        let blackhole_params = UniformBuffer::new(gl)?;
+        let post_params = UniformBuffer::new(gl)?;
 
         let mut renderer = Self {
-            pass_blackhole,
-            pass_brightness,
-            pass_lens_flare,
-            pass_downsample,
-            pass_upsample,
-            pass_composite,
-            pass_tonemapping,
+            shaders,
+            blackhole_variants,
+            failed_variants: HashSet::new(),
+            last_variant: defines,
+            shader_errors,
+            pass_taa,
+            pass_accumulate,
             pass_passthrough,
 
             tex_blackhole: None,
             fbo_blackhole: None,
-            tex_brightness: None,
-            fbo_brightness: None,
-            tex_lens_flare: None,
-            fbo_lens_flare: None,
-            tex_downsampled: vec![],
-            fbo_downsampled: vec![],
-            tex_upsampled: vec![],
-            fbo_upsampled: vec![],
-            tex_bloom_final: None,
-            fbo_bloom_final: None,
-            tex_tonemapped: None,
-            fbo_tonemapped: None,
+            tex_history: vec![],
+            fbo_history: vec![],
+            history_index: 0,
+            history_camera: None,
+            frame_index: 0,
+            last_frame: None,
+            accumulated: 0,
+            accumulation_limit: crate::texture::accumulation_sample_limit(gl),
+            settled: false,
+            post_graph: RenderGraph::empty(),
+            post_programs,
+            post_output: None,
+            post_upscale: false,
+            blackhole_params,
+            post_params,
 
             galaxy_cubemap,
             color_map,
             noise_tex,
+            blue_noise_tex,
 
             width: 0,
             height: 0,
+            render_scale: 1.0,
+            blackhole_width: 0,
+            blackhole_height: 0,
         };
 
         renderer.resize(gl, width, height)?;
@@ -214,19 +339,11 @@ impl Renderer {
     unsafe fn destroy_targets(&mut self, gl: &glow::Context) {
         delete_optional_texture(gl, &mut self.tex_blackhole);
         delete_optional_framebuffer(gl, &mut self.fbo_blackhole);
-        delete_optional_texture(gl, &mut self.tex_brightness);
-        delete_optional_framebuffer(gl, &mut self.fbo_brightness);
-        delete_optional_texture(gl, &mut self.tex_lens_flare);
-        delete_optional_framebuffer(gl, &mut self.fbo_lens_flare);
-        delete_optional_texture(gl, &mut self.tex_bloom_final);
-        delete_optional_framebuffer(gl, &mut self.fbo_bloom_final);
-        delete_optional_texture(gl, &mut self.tex_tonemapped);
-        delete_optional_framebuffer(gl, &mut self.fbo_tonemapped);
-
-        delete_textures(gl, &mut self.tex_downsampled);
-        delete_framebuffers(gl, &mut self.fbo_downsampled);
-        delete_textures(gl, &mut self.tex_upsampled);
-        delete_framebuffers(gl, &mut self.fbo_upsampled);
+        delete_textures(gl, &mut self.tex_history);
+        delete_framebuffers(gl, &mut self.fbo_history);
+        self.history_camera = None;
+        self.post_graph.destroy(gl);
+        self.post_output = None;
     }
 
     pub unsafe fn resize(&mut self, gl: &glow::Context, width: u32, height: u32) -> anyhow::Result<()> {
@@ -240,238 +357,412 @@ impl Renderer {
             return Ok(());
         }
 
+        self.blackhole_width = 0;
+        self.blackhole_height = 0;
+        self.set_render_scale(gl, self.render_scale)
+    }
+
+    // Resizes the black hole target to `scale` (clamped to 25-100%) of the window.
+    // Only reallocates when the pixel size actually changes.
+    pub unsafe fn set_render_scale(&mut self, gl: &glow::Context, scale: f32) -> anyhow::Result<()> {
+        self.render_scale = scale.clamp(0.25, 1.0);
+        if self.width == 0 || self.height == 0 {
+            return Ok(());
+        }
+        let scaled = |size: u32| ((size as f32 * self.render_scale).round() as u32).clamp(1, size);
+        let (width, height) = (scaled(self.width), scaled(self.height));
+        if (width, height) == (self.blackhole_width, self.blackhole_height) {
+            return Ok(());
+        }
+
+        delete_optional_texture(gl, &mut self.tex_blackhole);
+        delete_optional_framebuffer(gl, &mut self.fbo_blackhole);
         let tex_blackhole = crate::texture::create_color_texture(gl, width, height)?;
         let fbo_blackhole = crate::framebuffer::create_framebuffer(gl, tex_blackhole)?;
         self.tex_blackhole = Some(tex_blackhole);
         self.fbo_blackhole = Some(fbo_blackhole);
 
-        let tex_brightness = crate::texture::create_color_texture(gl, width, height)?;
-        let fbo_brightness = crate::framebuffer::create_framebuffer(gl, tex_brightness)?;
-        self.tex_brightness = Some(tex_brightness);
-        self.fbo_brightness = Some(fbo_brightness);
-
-        let tex_lens_flare = crate::texture::create_color_texture(gl, width, height)?;
-        let fbo_lens_flare = crate::framebuffer::create_framebuffer(gl, tex_lens_flare)?;
-        self.tex_lens_flare = Some(tex_lens_flare);
-        self.fbo_lens_flare = Some(fbo_lens_flare);
-
-        for i in 0..MAX_BLOOM_ITER {
-            let mip_w = width >> (i + 1);
-            let mip_h = height >> (i + 1);
-            if mip_w == 0 || mip_h == 0 { break; }
-
-            let tex_down = crate::texture::create_color_texture(gl, mip_w, mip_h)?;
-            let fbo_down = crate::framebuffer::create_framebuffer(gl, tex_down)?;
-            self.tex_downsampled.push(tex_down);
-            self.fbo_downsampled.push(fbo_down);
-
-            let tex_up_w = width >> i;
-            let tex_up_h = height >> i;
-            let tex_up = crate::texture::create_color_texture(gl, tex_up_w, tex_up_h)?;
-            let fbo_up = crate::framebuffer::create_framebuffer(gl, tex_up)?;
-            self.tex_upsampled.push(tex_up);
-            self.fbo_upsampled.push(fbo_up);
+        delete_textures(gl, &mut self.tex_history);
+        delete_framebuffers(gl, &mut self.fbo_history);
+        self.history_camera = None;
+        self.last_frame = None;
+        self.accumulated = 0;
+        for _ in 0..2 {
+            let tex = crate::texture::create_accumulation_texture(gl, width, height)?;
+            let fbo = crate::framebuffer::create_framebuffer(gl, tex)?;
+            self.tex_history.push(tex);
+            self.fbo_history.push(fbo);
         }
+        self.blackhole_width = width;
+        self.blackhole_height = height;
+        self.rebuild_post_graph(gl)
+    }
 
-        let tex_bloom_final = crate::texture::create_color_texture(gl, width, height)?;
-        let fbo_bloom_final = crate::framebuffer::create_framebuffer(gl, tex_bloom_final)?;
-        self.tex_bloom_final = Some(tex_bloom_final);
-        self.fbo_bloom_final = Some(fbo_bloom_final);
-
-        let tex_tonemapped = crate::texture::create_color_texture(gl, width, height)?;
-        let fbo_tonemapped = crate::framebuffer::create_framebuffer(gl, tex_tonemapped)?;
-        self.tex_tonemapped = Some(tex_tonemapped);
-        self.fbo_tonemapped = Some(fbo_tonemapped);
-
+    // Rebuilds the post chain for the current window size, with an upscale pass when
+    // the ray march runs below window resolution.
+    unsafe fn rebuild_post_graph(&mut self, gl: &glow::Context) -> anyhow::Result<()> {
+        let upscale = (self.blackhole_width, self.blackhole_height) != (self.width, self.height);
+        if self.post_output.is_some() && upscale == self.post_upscale {
+            return Ok(());
+        }
+        let (desc, output) = post_chain(self.width, self.height, upscale);
+        for shader in desc.shaders() {
+            if !self.post_programs.contains_key(shader) {
+                let errors = &mut self.shader_errors;
+                let pass = self.shaders.load_or_fallback(gl, shader, &[], errors)?;
+                self.post_programs.insert(shader, pass);
+            }
+        }
+        self.post_graph.rebuild(gl, desc, self.width, self.height)?;
+        self.post_output = Some(output);
+        self.post_upscale = upscale;
         Ok(())
     }
 
-    pub unsafe fn render(&mut self, gl: &glow::Context, state: &AppState, time: f32) {
-        let tex_blackhole = self.tex_blackhole.clone().expect("missing tex_blackhole");
-        let fbo_blackhole = self.fbo_blackhole.clone().expect("missing fbo_blackhole");
-        let tex_brightness = self.tex_brightness.clone().expect("missing tex_brightness");
-        let fbo_brightness = self.fbo_brightness.clone().expect("missing fbo_brightness");
-        let tex_lens_flare = self.tex_lens_flare.clone().expect("missing tex_lens_flare");
-        let fbo_lens_flare = self.fbo_lens_flare.clone().expect("missing fbo_lens_flare");
-        let tex_bloom_final = self.tex_bloom_final.clone().expect("missing tex_bloom_final");
-        let fbo_bloom_final = self.fbo_bloom_final.clone().expect("missing fbo_bloom_final");
-        let tex_tonemapped = self.tex_tonemapped.clone().expect("missing tex_tonemapped");
-        let fbo_tonemapped = self.fbo_tonemapped.clone().expect("missing fbo_tonemapped");
-
-        // Reset state that might be messed up by egui
-        gl.disable(glow::SCISSOR_TEST);
-        gl.disable(glow::BLEND);
-        gl.disable(glow::CULL_FACE);
+    fn tonemapped(&self) -> TargetId {
+        self.post_output.expect("missing post-processing output")
+    }
 
-        let blackhole_uniforms = [
-            ("time", time),
-            ("mouseX", state.mouse_x),
-            ("mouseY", state.mouse_y),
-            ("cameraRoll", state.camera_roll),
-            ("gravatationalLensing", flag(state.gravitational_lensing)),
-            ("renderBlackHole", flag(state.render_black_hole)),
-            ("mouseControl", flag(state.mouse_control)),
-            ("fovScale", 1.0),
-            ("frontView", flag(state.front_view)),
-            ("topView", flag(state.top_view)),
-            ("adiskEnabled", flag(state.adisk_enabled)),
-            ("adiskParticle", flag(state.adisk_particle)),
-            ("adiskDensityV", state.adisk_density_v),
-            ("adiskDensityH", state.adisk_density_h),
-            ("adiskHeight", state.adisk_height),
-            ("adiskLit", state.adisk_lit),
-            ("adiskNoiseLOD", state.adisk_noise_lod),
-            ("adiskNoiseScale", state.adisk_noise_scale),
-            ("adiskSpeed", state.adisk_speed),
-            ("spin", state.spin),
+    // Blends this frame's jittered ray march into the history and returns the
+    // resolved texture, which is also next frame's history.
+    unsafe fn resolve_taa(
+        &mut self,
+        gl: &glow::Context,
+        camera: FrameCamera,
+        tex_current: glow::Texture,
+    ) -> glow::Texture {
+        let read = self.history_index;
+        let write = read ^ 1;
+        let previous = self.history_camera.unwrap_or(camera);
+
+        let taa_uniforms: &[(&str, UniformValue)] = &[
+            ("texture0", UniformValue::Texture2D(tex_current)),
+            ("texture1", UniformValue::Texture2D(self.tex_history[read])),
+            ("cameraPos", camera.position.into()),
+            ("view", camera.view.into()),
+            ("fovScale", camera.fov_scale.into()),
+            ("prevCameraPos", previous.position.into()),
+            ("prevView", previous.view.into()),
+            ("prevFovScale", previous.fov_scale.into()),
+            ("historyValid", self.history_camera.is_some().into()),
+            ("blendFactor", TAA_BLEND.into()),
         ];
-        let blackhole_textures = [("colorMap", self.color_map)];
-        let blackhole_textures_3d = [("noiseTex", self.noise_tex)];
-        let blackhole_cubemaps = [("galaxy", self.galaxy_cubemap)];
-
-        self.pass_blackhole.render(
+        self.pass_taa.render(
             gl,
-            Some(fbo_blackhole),
-            self.width as i32,
-            self.height as i32,
-            &blackhole_uniforms,
-            &blackhole_textures,
-            &blackhole_textures_3d,
-            &blackhole_cubemaps,
+            Some(self.fbo_history[write]),
+            self.blackhole_width as i32,
+            self.blackhole_height as i32,
+            taa_uniforms,
         );
 
-        let brightness_textures = [("texture0", tex_blackhole)];
-        self.pass_brightness.render(
+        self.history_index = write;
+        self.history_camera = Some(camera);
+        self.tex_history[write]
+    }
+
+    // Adds this frame's sample to the running average in the history buffers. The
+    // result also seeds TAA once the view starts moving again.
+    unsafe fn accumulate(
+        &mut self,
+        gl: &glow::Context,
+        camera: FrameCamera,
+        tex_current: glow::Texture,
+    ) -> glow::Texture {
+        let read = self.history_index;
+        let write = read ^ 1;
+        self.accumulated += 1;
+
+        let uniforms: &[(&str, UniformValue)] = &[
+            ("texture0", UniformValue::Texture2D(tex_current)),
+            ("texture1", UniformValue::Texture2D(self.tex_history[read])),
+            ("sampleWeight", (1.0 / self.accumulated as f32).into()),
+        ];
+        self.pass_accumulate.render(
             gl,
-            Some(fbo_brightness),
-            self.width as i32,
-            self.height as i32,
-            &[],
-            &brightness_textures,
-            &[],
-            &[],
+            Some(self.fbo_history[write]),
+            self.blackhole_width as i32,
+            self.blackhole_height as i32,
+            uniforms,
         );
 
-        let flare_textures = [("texture0", tex_brightness)];
-        self.pass_lens_flare.render(
+        self.history_index = write;
+        self.history_camera = Some(camera);
+        self.tex_history[write]
+    }
+
+    // Samples averaged into the current idle view; 0 while the view is changing.
+    pub fn accumulated_samples(&self) -> u32 {
+        self.accumulated
+    }
+
+    // Samples progressive refinement stops at: `progressive_samples`, unless the
+    // history format runs out of precision first (RGBA8 or half-float on the web).
+    pub fn sample_target(&self, quality: &QualitySettings) -> u32 {
+        quality.progressive_samples.min(self.accumulation_limit)
+    }
+
+    // True when rendering the same inputs again would not change the image: the view
+    // is idle and accumulation has reached its target (or nothing accumulates).
+    pub fn is_settled(&self) -> bool {
+        self.settled
+    }
+
+    pub fn shader_errors(&self) -> &BTreeMap<String, Vec<Diagnostic>> {
+        &self.shader_errors
+    }
+
+    // Returns the variant to draw `state` with, compiling it on first use.
+    unsafe fn blackhole_variant(&mut self, gl: &glow::Context, state: &AppState) -> Defines {
+        let defines = blackhole_defines(state);
+        if !self.blackhole_variants.contains_key(&defines)
+            && !self.failed_variants.contains(&defines)
+        {
+            match self.shaders.load_variant(gl, "blackhole_main.frag", &defines) {
+                Ok(mut pass) => {
+                    pass.allow_inactive(DISK_SAMPLERS);
+                    self.blackhole_variants.insert(defines.clone(), pass);
+                }
+                Err(e) => {
+                    let diagnostics = crate::shader_utils::diagnostics(&e);
+                    self.shader_errors.insert("blackhole_main.frag".to_string(), diagnostics);
+                    self.failed_variants.insert(defines.clone());
+                }
+            }
+        }
+        if self.blackhole_variants.contains_key(&defines) {
+            self.last_variant = defines.clone();
+        }
+        self.last_variant.clone()
+    }
+
+    // Rebuilds every pass that uses one of the `changed` shader files, and retries the
+    // ones that failed before. Failures are kept in `shader_errors`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn reload_shaders(&mut self, gl: &glow::Context, changed: &[String]) {
+        self.failed_variants.clear();
+        let passes = self
+            .blackhole_variants
+            .values_mut()
+            .chain([&mut self.pass_taa, &mut self.pass_accumulate, &mut self.pass_passthrough])
+            .chain(self.post_programs.values_mut());
+
+        // Shader name and the errors of its first failing program, if any.
+        let mut results: BTreeMap<String, Option<Vec<Diagnostic>>> = BTreeMap::new();
+        for pass in passes {
+            let affected = changed.iter().any(|file| pass.depends_on(file))
+                || self.shader_errors.contains_key(pass.name());
+            if !affected {
+                continue;
+            }
+            let result = pass.reload(gl).err().map(|e| crate::shader_utils::diagnostics(&e));
+            let entry = results.entry(pass.name().to_string()).or_default();
+            if entry.is_none() {
+                *entry = result;
+            }
+        }
+        if results.is_empty() {
+            return;
+        }
+        for (name, error) in results {
+            match error {
+                Some(error) => self.shader_errors.insert(name, error),
+                None => self.shader_errors.remove(&name),
+            };
+        }
+
+        // Draw and accumulate again with the new programs.
+        self.last_frame = None;
+        self.settled = false;
+    }
+
+    pub unsafe fn render(&mut self, gl: &glow::Context, state: &AppState, time: f32) {
+        self.render_offscreen(gl, state, time);
+
+        let tex_tonemapped = self.post_graph.texture(self.tonemapped());
+        self.pass_passthrough.render(
             gl,
-            Some(fbo_lens_flare),
+            None,
             self.width as i32,
             self.height as i32,
-            &[],
-            &flare_textures,
-            &[],
-            &[],
+            &[("texture0", UniformValue::Texture2D(tex_tonemapped))],
         );
+    }
 
-        let chain_len = self.tex_downsampled.len();
-        for i in 0..chain_len {
-            let src_tex = if i == 0 {
-                tex_brightness
-            } else {
-                self.tex_downsampled[i - 1]
-            };
-            let dst_fbo = self.fbo_downsampled[i];
-            let w = self.width >> (i + 1);
-            let h = self.height >> (i + 1);
+    // Runs the whole chain up to `tex_tonemapped` without touching the default
+    // framebuffer, so it also works on surfaceless contexts.
+    pub unsafe fn render_offscreen(&mut self, gl: &glow::Context, state: &AppState, time: f32) {
+        self.set_render_scale(gl, state.quality.render_scale)
+            .expect("failed to resize black hole target");
 
-            let downsample_textures = [("texture0", src_tex)];
-            self.pass_downsample.render(
-                gl,
-                Some(dst_fbo),
-                w as i32,
-                h as i32,
-                &[],
-                &downsample_textures,
-                &[],
-                &[],
-            );
-        }
+        let tex_blackhole = self.tex_blackhole.expect("missing tex_blackhole");
+        let fbo_blackhole = self.fbo_blackhole.expect("missing fbo_blackhole");
 
-        for i in (0..chain_len).rev() {
-            let tex0 = if i == chain_len - 1 {
-                self.tex_downsampled[i]
-            } else {
-                self.tex_upsampled[i + 1]
-            };
-            let tex1 = if i == 0 {
-                tex_brightness
-            } else {
-                self.tex_downsampled[i - 1]
-            };
-            let dst_fbo = self.fbo_upsampled[i];
-            let w = self.width >> i;
-            let h = self.height >> i;
+        // Reset state that might be messed up by egui
+        gl.disable(glow::SCISSOR_TEST);
+        gl.disable(glow::BLEND);
+        gl.disable(glow::CULL_FACE);
 
-            let upsample_textures = [("texture0", tex0), ("texture1", tex1)];
-            self.pass_upsample.render(
-                gl,
-                Some(dst_fbo),
-                w as i32,
-                h as i32,
-                &[],
-                &upsample_textures,
-                &[],
-                &[],
-            );
+        let pose = &state.camera.pose;
+        let camera = FrameCamera {
+            position: pose.position(),
+            view: pose.view_matrix(state.camera_roll),
+            fov_scale: pose.fov_scale(),
+        };
+
+        let idle = self
+            .last_frame
+            .as_ref()
+            .is_some_and(|(last, last_time)| last == state && *last_time == time);
+        if !idle {
+            self.last_frame = Some((state.clone(), time));
         }
+        let progressive = idle && state.quality.progressive;
+        if !progressive {
+            self.accumulated = 0;
+        }
+        let taa = state.quality.taa;
+        self.settled = idle
+            && if progressive {
+                self.accumulated >= self.sample_target(&state.quality)
+            } else {
+                !taa
+            };
 
-        let bloom_tex = if self.tex_upsampled.is_empty() {
-            tex_blackhole
+        self.frame_index = self.frame_index.wrapping_add(1);
+        // Progressive samples walk one long Halton sequence; TAA cycles a short one.
+        let (jitter_phase, noise_index) = if progressive {
+            (Some(self.accumulated + 1), self.accumulated)
+        } else if taa {
+            (Some(self.frame_index % TAA_JITTER_PHASES + 1), self.frame_index % 1024)
         } else {
-            self.tex_upsampled[0]
+            (None, 0)
         };
-        let composite_textures = [
-            ("texture0", tex_blackhole),
-            ("texture1", bloom_tex),
-            ("texture2", tex_lens_flare),
-        ];
-        let composite_uniforms = [
-            ("tone", 1.0),
-            ("bloomStrength", state.bloom_strength),
-            ("flareStrength", state.flare_strength),
-        ];
-        self.pass_composite.render(
+        let jitter = jitter_phase
+            .map_or([0.0, 0.0], |phase| [halton(phase, 2) - 0.5, halton(phase, 3) - 0.5]);
+        let (step_scale, min_step, max_steps) = if progressive {
+            (PROGRESSIVE_STEP_SCALE, PROGRESSIVE_MIN_STEP, PROGRESSIVE_MAX_STEPS)
+        } else {
+            (1.0, 0.02, 300.0)
+        };
+
+        self.blackhole_params.upload(
             gl,
-            Some(fbo_bloom_final),
-            self.width as i32,
-            self.height as i32,
-            &composite_uniforms,
-            &composite_textures,
-            &[],
-            &[],
+            &BlackHoleParams {
+                camera_pos: camera.position.to_array(),
+                fov_scale: camera.fov_scale,
+                view: std140_mat3(camera.view),
+                jitter,
+                time,
+                spin: state.spin,
+                adisk_density_v: state.adisk_density_v,
+                adisk_density_h: state.adisk_density_h,
+                adisk_height: state.adisk_height,
+                adisk_lit: state.adisk_lit,
+                adisk_noise_scale: state.adisk_noise_scale,
+                adisk_speed: state.adisk_speed,
+                step_jitter: jitter_phase.is_some() as u32,
+                frame_index: noise_index as f32,
+                dither_strength: TARGET_DITHER,
+                step_scale,
+                min_step,
+                max_steps,
+            },
         );
-
-        let tone_textures = [("texture0", tex_bloom_final)];
-        let tone_uniforms = [
-            ("tonemappingEnabled", flag(state.tonemapping_enabled)),
-            ("gamma", state.gamma),
-            ("time", time),
-            ("chromaStrength", state.chroma_aberration),
-            ("grainStrength", state.grain_strength),
-            ("saturation", state.saturation),
+        let blackhole_uniforms: &[(&str, UniformValue)] = &[
+            ("galaxy", UniformValue::Cubemap(self.galaxy_cubemap)),
+            ("colorMap", UniformValue::Texture2D(self.color_map)),
+            ("noiseTex", UniformValue::Texture3D(self.noise_tex)),
+            ("blueNoise", UniformValue::Texture2D(self.blue_noise_tex)),
         ];
-        self.pass_tonemapping.render(
+
+        let tex_blackhole = if progressive && self.settled {
+            // Nothing left to add; the average is final.
+            self.tex_history[self.history_index]
+        } else {
+            let defines = self.blackhole_variant(gl, state);
+            let pass_blackhole = self.blackhole_variants.get_mut(&defines).unwrap();
+            pass_blackhole.render(
+                gl,
+                Some(fbo_blackhole),
+                self.blackhole_width as i32,
+                self.blackhole_height as i32,
+                blackhole_uniforms,
+            );
+            if progressive {
+                self.accumulate(gl, camera, tex_blackhole)
+            } else if taa {
+                self.resolve_taa(gl, camera, tex_blackhole)
+            } else {
+                self.history_camera = None;
+                tex_blackhole
+            }
+        };
+
+        self.post_params.upload(
             gl,
-            Some(fbo_tonemapped),
+            &PostParams {
+                tonemapping_enabled: state.tonemapping_enabled as u32,
+                gamma: state.gamma,
+                time,
+                chroma_strength: state.chroma_aberration,
+                grain_strength: state.grain_strength,
+                saturation: state.saturation,
+                output_dither: OUTPUT_DITHER,
+                tone: 1.0,
+                bloom_strength: state.bloom_strength,
+                flare_strength: state.flare_strength,
+                filter_mode: state.quality.upscale_filter.shader_mode(),
+                ..Default::default()
+            },
+        );
+        let imports = [("scene", tex_blackhole), ("blueNoise", self.blue_noise_tex)];
+        self.post_graph.execute(gl, &mut self.post_programs, &imports);
+    }
+
+    // Reads the tonemapped image back as RGBA32F, bottom row first. Needs the float
+    // render targets of the native build.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn read_tonemapped_f32(&self, gl: &glow::Context) -> Vec<f32> {
+        let fbo_tonemapped = self.post_graph.framebuffer(self.tonemapped());
+        let mut pixels = vec![0f32; self.width as usize * self.height as usize * 4];
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo_tonemapped));
+        gl.read_pixels(
+            0,
+            0,
             self.width as i32,
             self.height as i32,
-            &tone_uniforms,
-            &tone_textures,
-            &[],
-            &[],
+            glow::RGBA,
+            glow::FLOAT,
+            glow::PixelPackData::Slice(bytemuck::cast_slice_mut(&mut pixels)),
         );
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        pixels
+    }
 
-        let pass_textures = [("texture0", tex_tonemapped)];
-        self.pass_passthrough.render(
-            gl,
-            None,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // Starts an asynchronous RGBA8 copy of the tonemapped image (bottom row first) into
+    // `buffer`, which must hold `width * height * 4` bytes. Returns immediately; fence
+    // the copy before reading the buffer back.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn read_tonemapped_to_buffer(&self, gl: &glow::Context, buffer: glow::Buffer) {
+        let fbo_tonemapped = self.post_graph.framebuffer(self.tonemapped());
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo_tonemapped));
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+        gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+        gl.read_pixels(
+            0,
+            0,
             self.width as i32,
             self.height as i32,
-            &[],
-            &pass_textures,
-            &[],
-            &[],
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            glow::PixelPackData::BufferOffset(0),
         );
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
     }
 }
@@ -0,0 +1,230 @@
+// Versioned scene files. A scene is the full `AppState` (every toggle and slider,
+// the camera and its bookmarks), the animation time and the keyframe timeline
+// (when it has keys), stored as TOML:
+//
+//   version = 2
+//   time = 12.5
+//
+//   [state]
+//   spin = 0.2
+//   ...
+//
+// Missing fields fall back to `AppState::default()`, so older files keep loading
+// as new settings are added. Older versions are migrated on load; files without a
+// `version` key are bare version 1 `AppState` tables (the format
+// `blackhole_render --scene` accepted before versioning).
+// Native builds read and write files; wasm keeps scenes in localStorage.
+
+use anyhow::Context;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::camera::{Camera, CameraPose};
+use crate::timeline::Timeline;
+
+pub const SCENE_VERSION: u32 = 2;
+pub const DEFAULT_SCENE_PATH: &str = "scene.toml";
+
+#[cfg(target_arch = "wasm32")]
+const STORAGE_PREFIX: &str = "blackhole.scene.";
+
+#[derive(Serialize, Deserialize)]
+pub struct Scene {
+    pub version: u32,
+    #[serde(default)]
+    pub time: f32,
+    #[serde(default)]
+    pub state: AppState,
+    #[serde(default, skip_serializing_if = "Timeline::is_empty")]
+    pub timeline: Timeline,
+}
+
+impl Scene {
+    pub fn new(state: &AppState, time: f32) -> Self {
+        Self {
+            version: SCENE_VERSION,
+            time,
+            state: state.clone(),
+            timeline: Timeline::default(),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let mut table: toml::Table = toml::from_str(text)?;
+        let version = match table.get("version") {
+            None => {
+                let state = std::mem::take(&mut table);
+                table.insert("version".into(), 1.into());
+                table.insert("state".into(), toml::Value::Table(state));
+                1
+            }
+            Some(value) => value
+                .as_integer()
+                .context("Scene `version` must be an integer")?,
+        };
+        if !(1..=SCENE_VERSION as i64).contains(&version) {
+            anyhow::bail!(
+                "Unsupported scene version {} (this build reads up to {})",
+                version,
+                SCENE_VERSION
+            );
+        }
+        if version < 2 {
+            migrate_v1(&mut table)?;
+        }
+        let mut scene = Self::deserialize(toml::Value::Table(table))?;
+        scene.version = SCENE_VERSION;
+        Ok(scene)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let mut value = toml::Value::try_from(self)?;
+        shorten_floats(&mut value);
+        Ok(toml::to_string_pretty(&value)?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scene {}", path))?;
+        Self::from_toml(&text).with_context(|| format!("Failed to parse scene {}", path))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_toml()?)
+            .with_context(|| format!("Failed to write scene {}", path))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text = local_storage()?
+            .get_item(&format!("{}{}", STORAGE_PREFIX, path))
+            .map_err(|_| anyhow::anyhow!("Failed to read scene {}", path))?
+            .with_context(|| format!("No saved scene named {}", path))?;
+        Self::from_toml(&text).with_context(|| format!("Failed to parse scene {}", path))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        local_storage()?
+            .set_item(&format!("{}{}", STORAGE_PREFIX, path), &self.to_toml()?)
+            .map_err(|_| anyhow::anyhow!("Failed to write scene {}", path))
+    }
+}
+
+// Version 1 placed the camera with `front_view`/`top_view`/`mouse_control` and the
+// mouse position, and timeline camera keys were normalized mouse coordinates. Both
+// become `CameraPose`s looking at the hole from the same spot. Mouse positions are
+// read against the default 1200x800 window.
+fn migrate_v1(table: &mut toml::Table) -> anyhow::Result<()> {
+    let orbit_position = |x: f32, y: f32| {
+        Vec3::new(-(x * 10.0).cos() * 15.0, y * 30.0, (x * 10.0).sin() * 15.0)
+    };
+    let float = |value: Option<&toml::Value>| {
+        value.and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+    };
+
+    let time = float(table.get("time")).unwrap_or(0.0) as f32;
+    if let Some(toml::Value::Table(state)) = table.get_mut("state") {
+        let mut flag = |name: &str| state.remove(name).and_then(|v| v.as_bool());
+        let (mouse_control, front_view, top_view) =
+            (flag("mouse_control"), flag("front_view"), flag("top_view"));
+        let mouse_x = float(state.remove("mouse_x").as_ref()).unwrap_or(600.0) as f32;
+        let mouse_y = float(state.remove("mouse_y").as_ref()).unwrap_or(400.0) as f32;
+
+        let position = if mouse_control.unwrap_or(false) {
+            let x = (mouse_x / 1200.0).clamp(0.0, 1.0) - 0.5;
+            let y = (mouse_y / 800.0).clamp(0.0, 1.0) - 0.5;
+            orbit_position(x, y)
+        } else if front_view.unwrap_or(true) {
+            Vec3::new(10.0, 1.0, 10.0)
+        } else if top_view.unwrap_or(false) {
+            Vec3::new(15.0, 15.0, 0.0)
+        } else {
+            let t = time * 0.1;
+            Vec3::new(-t.cos() * 15.0, t.sin() * 15.0, t.sin() * 15.0)
+        };
+        let camera = Camera {
+            pose: CameraPose::looking_at(position, Vec3::ZERO),
+            ..Camera::default()
+        };
+        state.insert("camera".into(), toml::Value::try_from(camera)?);
+    }
+
+    let keys = table
+        .get_mut("timeline")
+        .and_then(|timeline| timeline.get_mut("camera"))
+        .and_then(|camera| camera.get_mut("keys"))
+        .and_then(|keys| keys.as_array_mut());
+    for key in keys.into_iter().flatten() {
+        let orbit = key
+            .get("value")
+            .and_then(|v| v.as_array())
+            .map(|v| [float(v.first()), float(v.get(1))]);
+        if let (Some([Some(x), Some(y)]), Some(key)) = (orbit, key.as_table_mut()) {
+            let pose = CameraPose::looking_at(orbit_position(x as f32, y as f32), Vec3::ZERO);
+            key.insert("value".into(), toml::Value::try_from(pose)?);
+        }
+    }
+    Ok(())
+}
+
+// Settings are f32; write them as `0.55` rather than the widened `0.550000011920929`.
+fn shorten_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(f) => *f = (*f as f32).to_string().parse().unwrap_or(*f),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, v)| shorten_floats(v)),
+        toml::Value::Array(array) => array.iter_mut().for_each(shorten_floats),
+        _ => {}
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> anyhow::Result<web_sys::Storage> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or_else(|| anyhow::anyhow!("localStorage unavailable"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated_pose(v1: &str) -> CameraPose {
+        Scene::from_toml(v1).unwrap().state.camera.pose
+    }
+
+    #[test]
+    fn v1_front_view_migrates_to_front_pose() {
+        let pose = migrated_pose("front_view = true\ntop_view = false\nmouse_control = false\n");
+        assert_eq!(pose, CameraPose::looking_at(Vec3::new(10.0, 1.0, 10.0), Vec3::ZERO));
+    }
+
+    #[test]
+    fn v1_top_view_migrates_to_top_pose() {
+        let pose = migrated_pose("front_view = false\ntop_view = true\nmouse_control = false\n");
+        assert_eq!(pose, CameraPose::looking_at(Vec3::new(15.0, 15.0, 0.0), Vec3::ZERO));
+    }
+
+    #[test]
+    fn v1_mouse_control_migrates_to_orbit_pose() {
+        let pose = migrated_pose(
+            "front_view = true\nmouse_control = true\nmouse_x = 900.0\nmouse_y = 200\n",
+        );
+        // (900, 200) in the 1200x800 window is (0.25, -0.25) from its center.
+        let position = Vec3::new(-(2.5_f32).cos() * 15.0, -7.5, (2.5_f32).sin() * 15.0);
+        assert_eq!(pose, CameraPose::looking_at(position, Vec3::ZERO));
+    }
+
+    #[test]
+    fn playhead_round_trips() {
+        let mut scene = Scene::new(&AppState::default(), 4.0);
+        scene.timeline.track_mut("spin").insert(0.0, 0.2, Default::default());
+        scene.timeline.seek(2.5);
+        let loaded = Scene::from_toml(&scene.to_toml().unwrap()).unwrap();
+        assert_eq!(loaded.time, 4.0);
+        assert_eq!(loaded.timeline.playhead, 2.5);
+    }
+}
@@ -0,0 +1,66 @@
+// `#include` expansion for GLSL sources, shared by the shader loader
+// (`shader_utils.rs`) and the build-time checks in `build.rs`, which pulls this file
+// in with `#[path]`. Both therefore resolve includes the same way.
+//
+// `#include "common/color.glsl"` paths are relative to the shader directory. Each
+// file is included at most once per shader, so snippets need no include guards.
+
+use anyhow::Context;
+use std::borrow::Cow;
+
+pub struct Expanded {
+    // Every file that was read, starting with the shader itself.
+    pub files: Vec<String>,
+    // The combined source, one entry per line, with the index into `files` and the
+    // 1-based line number it came from.
+    pub lines: Vec<(String, usize, u32)>,
+}
+
+// Expands `name` and everything it includes. `read` returns a file's text by its
+// name relative to the shader directory.
+pub fn expand(
+    name: &str,
+    read: &dyn Fn(&str) -> anyhow::Result<Cow<'static, str>>,
+) -> anyhow::Result<Expanded> {
+    let mut expanded = Expanded {
+        files: Vec::new(),
+        lines: Vec::new(),
+    };
+    include(&mut expanded, name, read, &mut Vec::new())?;
+    Ok(expanded)
+}
+
+fn include(
+    expanded: &mut Expanded,
+    name: &str,
+    read: &dyn Fn(&str) -> anyhow::Result<Cow<'static, str>>,
+    stack: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    if stack.iter().any(|open| open == name) {
+        anyhow::bail!("{} includes itself ({} -> {})", name, stack.join(" -> "), name);
+    }
+    if expanded.files.iter().any(|file| file == name) {
+        return Ok(());
+    }
+    let text = read(name)?;
+    let file = expanded.files.len();
+    expanded.files.push(name.to_string());
+
+    stack.push(name.to_string());
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index as u32 + 1;
+        if let Some(rest) = line.trim_start().strip_prefix("#include") {
+            let target = rest
+                .trim()
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .with_context(|| format!("{}:{}: expected #include \"file\"", name, line_number))?;
+            include(expanded, target, read, stack)
+                .with_context(|| format!("included from {}:{}", name, line_number))?;
+        } else {
+            expanded.lines.push((line.to_string(), file, line_number));
+        }
+    }
+    stack.pop();
+    Ok(())
+}
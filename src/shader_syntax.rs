@@ -0,0 +1,366 @@
+// Token-level GLSL scanning for `build.rs`, which pulls this file in with `#[path]`:
+// finds global `uniform`/`in`/`out` declarations and rewrites sources without moving
+// any line.
+//
+// The GLSL ES 3.00 files the wasm build embeds are made here, from the shader files
+// themselves, rather than taken from naga's GLSL ES output. naga's output is a
+// different program as far as the renderer is concerned: includes are inlined, the
+// uniform blocks the renderer binds by name are flattened, samplers are renamed after
+// their bindings and line numbers no longer match, so uniform lookups, hot-reload
+// diagnostics and `file:line` errors would all break. naga parses and validates every
+// program in `build.rs`; the ES files only drop what ES 3.00 lacks (uniform
+// initializers) and change the `#version` line.
+
+use std::ops::Range;
+
+// The ES 3.00 version of one file. Line numbers are kept, so compile errors on the web
+// point at the same lines as on desktop.
+pub fn to_es(text: &str) -> String {
+    let code = strip_comments(text);
+    let initializers = declarations(&code)
+        .into_iter()
+        .filter(|decl| decl.storage == "uniform" && decl.body.is_none())
+        .flat_map(|decl| decl.declarators)
+        .filter_map(|declarator| declarator.initializer);
+    let mut edits: Vec<(Range<usize>, String)> =
+        initializers.map(|range| (range, String::new())).collect();
+    if let Some(directive) = version_directive(text) {
+        edits.push((directive, "#version 300 es".to_string()));
+    }
+    apply_edits(text, edits)
+}
+
+// The `#version` line, without its newline. Only a directive counts, not the word in
+// a comment.
+pub fn version_directive(text: &str) -> Option<Range<usize>> {
+    let code = strip_comments(text);
+    let mut start = 0;
+    for line in code.split_inclusive('\n') {
+        let directive = line.trim_start().strip_prefix('#').map(str::trim_start);
+        if directive.is_some_and(|rest| rest.starts_with("version")) {
+            return Some(start..start + line.trim_end().len());
+        }
+        start += line.len();
+    }
+    None
+}
+
+// Replaces each range with its text plus the newlines it covered. Ranges must not
+// overlap.
+pub fn apply_edits(text: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, replacement) in edits {
+        out.push_str(&text[last..range.start]);
+        out.push_str(&replacement);
+        out.extend(text[range.clone()].chars().filter(|&c| c == '\n'));
+        last = range.end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+// The source with comments blanked out, so byte offsets and lines stay the same.
+pub fn strip_comments(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut i = 0;
+    while i < bytes.len() {
+        let end = if bytes[i..].starts_with(b"//") {
+            bytes[i..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(bytes.len(), |n| i + n)
+        } else if bytes[i..].starts_with(b"/*") {
+            let rest = &bytes[i + 2..];
+            rest.windows(2)
+                .position(|w| w == b"*/")
+                .map_or(bytes.len(), |n| i + n + 4)
+        } else {
+            i += 1;
+            continue;
+        };
+        for b in &mut out[i..end] {
+            if *b != b'\n' {
+                *b = b' ';
+            }
+        }
+        i = end;
+    }
+    // Only whole comments were replaced, so the rest is still valid UTF-8.
+    String::from_utf8(out).expect("comment stripping split a character")
+}
+
+// Byte ranges of the tokens outside preprocessor lines: identifiers and numbers, or
+// single punctuation characters.
+pub fn tokenize(code: &str) -> Vec<Range<usize>> {
+    let bytes = code.as_bytes();
+    let word = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'.';
+    let mut tokens = Vec::new();
+    let mut line_start = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == b'\n' {
+            line_start = true;
+            i += 1;
+        } else if b.is_ascii_whitespace() {
+            i += 1;
+        } else if b == b'#' && line_start {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else {
+            let start = i;
+            i += 1;
+            if word(b) {
+                while i < bytes.len() && word(bytes[i]) {
+                    i += 1;
+                }
+            }
+            tokens.push(start..i);
+            line_start = false;
+        }
+    }
+    tokens
+}
+
+// A global `uniform`, `in` or `out` declaration.
+pub struct Declaration {
+    // The whole declaration, through the `;`.
+    pub span: Range<usize>,
+    // `uniform`, `in` or `out`, and where that keyword is.
+    pub storage: String,
+    pub keyword: Range<usize>,
+    // The qualifiers inside `layout(...)`, if there is one.
+    pub layout: Option<Range<usize>>,
+    // The type, or the block name.
+    pub ty: String,
+    // Members of a block, between the braces.
+    pub body: Option<Range<usize>>,
+    pub declarators: Vec<Declarator>,
+}
+
+pub struct Declarator {
+    pub name: String,
+    // ` = value`, up to the following `,` or `;`.
+    pub initializer: Option<Range<usize>>,
+}
+
+// Global declarations of `code` (without comments). Function bodies are skipped.
+pub fn declarations(code: &str) -> Vec<Declaration> {
+    let tokens = tokenize(code);
+    let text = |i: usize| {
+        tokens
+            .get(i)
+            .map_or("", |t: &Range<usize>| &code[t.clone()])
+    };
+
+    let mut declarations = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut function = false;
+    for i in 0..tokens.len() {
+        match text(i) {
+            "{" => {
+                function |= depth == 0 && i > 0 && text(i - 1) == ")";
+                depth += 1;
+            }
+            "}" => {
+                depth -= 1;
+                if depth == 0 && function {
+                    function = false;
+                    start = i + 1;
+                }
+            }
+            ";" if depth == 0 => {
+                if let Some(decl) = parse_declaration(code, &tokens[start..=i]) {
+                    declarations.push(decl);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    declarations
+}
+
+fn parse_declaration(code: &str, tokens: &[Range<usize>]) -> Option<Declaration> {
+    let text = |i: usize| tokens.get(i).map_or("", |t| &code[t.clone()]);
+    let matching = |open: usize, left: &str, right: &str| {
+        let mut depth = 0;
+        (open..tokens.len()).find(|&i| {
+            if text(i) == left {
+                depth += 1;
+            } else if text(i) == right {
+                depth -= 1;
+            }
+            depth == 0
+        })
+    };
+    let qualifier = |t: &str| {
+        matches!(
+            t,
+            "highp" | "mediump" | "lowp" | "flat" | "smooth" | "noperspective" | "centroid"
+        )
+    };
+
+    let mut i = 0;
+    let mut layout = None;
+    if text(0) == "layout" && text(1) == "(" {
+        let close = matching(1, "(", ")")?;
+        layout = Some(tokens[1].end..tokens[close].start);
+        i = close + 1;
+    }
+    while qualifier(text(i)) {
+        i += 1;
+    }
+    let storage = text(i);
+    if !matches!(storage, "uniform" | "in" | "out") {
+        return None;
+    }
+    let keyword = tokens[i].clone();
+    i += 1;
+    while qualifier(text(i)) {
+        i += 1;
+    }
+    let span = tokens[0].start..tokens.last()?.end;
+
+    if text(i + 1) == "{" {
+        let close = matching(i + 1, "{", "}")?;
+        return Some(Declaration {
+            span,
+            storage: storage.to_string(),
+            keyword,
+            layout,
+            ty: text(i).to_string(),
+            body: Some(tokens[i + 1].end..tokens[close].start),
+            declarators: Vec::new(),
+        });
+    }
+
+    let ty = text(i).to_string();
+    i += 1;
+    let mut declarators = Vec::new();
+    while i < tokens.len() {
+        let name = text(i).to_string();
+        let mut name_end = tokens[i].end;
+        i += 1;
+        if text(i) == "[" {
+            let close = matching(i, "[", "]")?;
+            name_end = tokens[close].end;
+            i = close + 1;
+        }
+        let mut initializer = None;
+        if text(i) == "=" {
+            let mut depth = 0;
+            let end = (i..tokens.len()).find(|&j| {
+                match text(j) {
+                    "(" | "[" => depth += 1,
+                    ")" | "]" => depth -= 1,
+                    _ => {}
+                }
+                depth == 0 && matches!(text(j), "," | ";")
+            })?;
+            initializer = Some(name_end..tokens[end].start);
+            i = end;
+        }
+        declarators.push(Declarator { name, initializer });
+        if text(i) != "," {
+            break;
+        }
+        i += 1;
+    }
+    Some(Declaration {
+        span,
+        storage: storage.to_string(),
+        keyword,
+        layout,
+        ty,
+        body: None,
+        declarators,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn es_drops_multi_line_initializers_and_keeps_lines() {
+        let source = "#version 330 core\n\
+                      uniform mat3 basis = mat3(\n    1.0, 0.0, 0.0,\n    0.0, 1.0, 0.0,\n    \
+                      0.0, 0.0, 1.0);\n\
+                      uniform float gain = 0.5; // = 2.0 in the old look\n\
+                      void main() { float x = 1.0; }\n";
+        let es = to_es(source);
+        assert_eq!(
+            es,
+            "#version 300 es\n\
+             uniform mat3 basis\n\n\n;\n\
+             uniform float gain; // = 2.0 in the old look\n\
+             void main() { float x = 1.0; }\n"
+        );
+        assert_eq!(es.lines().count(), source.lines().count());
+    }
+
+    #[test]
+    fn es_drops_every_initializer_of_a_declaration() {
+        let es = to_es("#version 330 core\nuniform float a = 1.0, b = 2.0;\n");
+        assert_eq!(es, "#version 300 es\nuniform float a, b;\n");
+    }
+
+    #[test]
+    fn es_keeps_blocks_and_other_storage() {
+        let source = "#version 330 core\n\
+                      layout(std140) uniform Params { float gain; };\n\
+                      uniform sampler2D tex;\n\
+                      in vec2 uv;\n\
+                      const float k = 1.0;\n";
+        assert_eq!(to_es(source), source.replacen("#version 330 core", "#version 300 es", 1));
+    }
+
+    #[test]
+    fn declarations_split_declarators_and_initializers() {
+        let code = "uniform float a = 1.0, b = vec2(2.0, 3.0).x, c;\n\
+                    uniform vec3 d[2] = vec3[2](vec3(0.0), vec3(1.0));\n";
+        let decls = declarations(code);
+        assert_eq!(decls.len(), 2);
+        let declarators: Vec<(&str, Option<&str>)> = decls
+            .iter()
+            .flat_map(|decl| &decl.declarators)
+            .map(|d| (d.name.as_str(), d.initializer.clone().map(|range| &code[range])))
+            .collect();
+        assert_eq!(
+            declarators,
+            [
+                ("a", Some(" = 1.0")),
+                ("b", Some(" = vec2(2.0, 3.0).x")),
+                ("c", None),
+                ("d", Some(" = vec3[2](vec3(0.0), vec3(1.0))")),
+            ]
+        );
+        assert_eq!(decls[1].ty, "vec3");
+    }
+
+    #[test]
+    fn declarations_skip_function_bodies_and_read_layouts() {
+        let code = "layout(location = 0) out vec4 color;\n\
+                    void main() { uniform_like = 1; }\n\
+                    flat in int id;\n";
+        let decls = declarations(code);
+        let storage: Vec<(&str, &str)> =
+            decls.iter().map(|d| (d.storage.as_str(), d.ty.as_str())).collect();
+        assert_eq!(storage, [("out", "vec4"), ("in", "int")]);
+        assert_eq!(&code[decls[0].layout.clone().unwrap()], "location = 0");
+    }
+
+    #[test]
+    fn version_directive_ignores_comments() {
+        let text = "// #version 100\n  #  version 330 core // desktop\nvoid main() {}\n";
+        let directive = version_directive(text).unwrap();
+        assert_eq!(&text[directive], "  #  version 330 core");
+        assert!(version_directive("float a() { return 1.0; }\n").is_none());
+    }
+}
@@ -1,150 +1,397 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 use glow::HasContext;
 use std::borrow::Cow;
+use std::fmt;
+
+// Lines of source shown on each side of the line a diagnostic points at.
+const CONTEXT_LINES: u32 = 2;
+
 #[cfg(not(target_arch = "wasm32"))]
-use std::fs;
+const GLSL_VERSION: &str = "#version 330 core";
 #[cfg(target_arch = "wasm32")]
-use std::path::Path;
+const GLSL_VERSION: &str = "#version 300 es";
+
+// Stand-in for a pass whose shader does not build: flat magenta, which no real frame
+// looks like. It lives here rather than in `shader/` so a broken shader directory
+// cannot break it as well.
+const FALLBACK_VERTEX: &str = "layout(location = 0) in vec3 position;
+void main() {
+  gl_Position = vec4(position, 1.0);
+}
+";
+const FALLBACK_FRAGMENT: &str = "out vec4 fragColor;
+void main() {
+  fragColor = vec4(1.0, 0.0, 1.0, 1.0);
+}
+";
+
+// Compile-time switches of one program variant, emitted as `#define NAME VALUE`.
+// Every distinct list is compiled and cached as its own program, so values should
+// come from toggles and small integer settings, not continuous sliders.
+pub type Defines = Vec<(&'static str, i32)>;
+
+// One message from the shader compiler or linker.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    // Original file and line, when the driver reported a position.
+    pub location: Option<(String, u32)>,
+    // Numbered source lines around `location`.
+    pub context: Vec<(u32, String)>,
+}
+
+impl Diagnostic {
+    fn plain(message: String) -> Self {
+        Self {
+            message,
+            location: None,
+            context: Vec::new(),
+        }
+    }
+}
+
+// A failed compile or link, with the driver log split into per-line diagnostics.
+#[derive(Debug)]
+pub struct ShaderError {
+    stage: &'static str,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Shader {} failed:", self.stage)?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n{}", diagnostic.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Diagnostics for any error from building a program. Errors that did not come from
+// the driver (a missing file, a uniform block mismatch) become one plain message.
+pub fn diagnostics(error: &anyhow::Error) -> Vec<Diagnostic> {
+    match error.downcast_ref::<ShaderError>() {
+        Some(shader_error) => shader_error.diagnostics.clone(),
+        None => vec![Diagnostic::plain(format!("{:#}", error))],
+    }
+}
 
 pub unsafe fn create_shader_program(
     gl: &glow::Context,
-    vertex_path: &str,
-    fragment_path: &str,
+    vertex_name: &str,
+    fragment_name: &str,
+    defines: &[(&'static str, i32)],
 ) -> anyhow::Result<glow::Program> {
-    let vertex_src = load_shader_source(vertex_path)?;
-    let fragment_src = load_shader_source(fragment_path)?;
+    let mut vertex_src = load_shader_source(vertex_name)?;
+    vertex_src.define(defines);
+    let mut fragment_src = load_shader_source(fragment_name)?;
+    fragment_src.define(defines);
+    link_program(gl, &vertex_src, &fragment_src)
+}
 
-    let program = gl.create_program().map_err(|e| anyhow::anyhow!(e))?;
+pub unsafe fn create_fallback_program(gl: &glow::Context) -> anyhow::Result<glow::Program> {
+    let vertex_src = embedded_source("fallback.vert", FALLBACK_VERTEX)?;
+    let fragment_src = embedded_source("fallback.frag", FALLBACK_FRAGMENT)?;
+    link_program(gl, &vertex_src, &fragment_src)
+}
 
-    let vertex_shader = compile_shader(gl, glow::VERTEX_SHADER, vertex_src.as_ref())?;
-    gl.attach_shader(program, vertex_shader);
+unsafe fn link_program(
+    gl: &glow::Context,
+    vertex_src: &ShaderSource,
+    fragment_src: &ShaderSource,
+) -> anyhow::Result<glow::Program> {
+    let vertex_shader = compile_shader(gl, glow::VERTEX_SHADER, vertex_src)?;
+    let fragment_shader = match compile_shader(gl, glow::FRAGMENT_SHADER, fragment_src) {
+        Ok(shader) => shader,
+        Err(e) => {
+            gl.delete_shader(vertex_shader);
+            return Err(e);
+        }
+    };
 
-    let fragment_shader = compile_shader(gl, glow::FRAGMENT_SHADER, fragment_src.as_ref())?;
+    let program = gl.create_program().map_err(|e| anyhow::anyhow!(e))?;
+    gl.attach_shader(program, vertex_shader);
     gl.attach_shader(program, fragment_shader);
-
     gl.link_program(program);
 
-    if !gl.get_program_link_status(program) {
-        let log = gl.get_program_info_log(program);
-        anyhow::bail!("Shader linking failed: {}", log);
-    }
+    let linked = gl.get_program_link_status(program);
+    let log = if linked { String::new() } else { gl.get_program_info_log(program) };
 
     gl.detach_shader(program, vertex_shader);
     gl.delete_shader(vertex_shader);
     gl.detach_shader(program, fragment_shader);
     gl.delete_shader(fragment_shader);
 
+    if !linked {
+        gl.delete_program(program);
+        let diagnostics = log
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Diagnostic::plain(line.to_string()))
+            .collect();
+        return Err(ShaderError {
+            stage: "linking",
+            diagnostics,
+        }
+        .into());
+    }
     Ok(program)
 }
 
+// Every file the given shaders are built from, relative to the shader directory, for
+// hot reload. A file that fails to preprocess still lists itself.
 #[cfg(not(target_arch = "wasm32"))]
-fn load_shader_source(path: &str) -> anyhow::Result<Cow<'static, str>> {
-    Ok(Cow::Owned(fs::read_to_string(path)?))
+pub fn shader_files(names: &[&str]) -> Vec<String> {
+    let mut files: Vec<String> = Vec::new();
+    for name in names {
+        let names = match load_shader_source(name) {
+            Ok(source) => source.files,
+            Err(_) => vec![name.to_string()],
+        };
+        for name in names {
+            if !files.contains(&name) {
+                files.push(name);
+            }
+        }
+    }
+    files
 }
 
-#[cfg(target_arch = "wasm32")]
-fn load_shader_source(path: &str) -> anyhow::Result<Cow<'static, str>> {
-    let name = Path::new(path)
-        .file_name()
-        .and_then(|name| name.to_str())
-        .ok_or_else(|| anyhow::anyhow!("Invalid shader path: {}", path))?;
-    let source = shader_source_for_name(name)
-        .ok_or_else(|| anyhow::anyhow!("Unknown shader asset: {}", name))?;
-    Ok(Cow::Owned(normalize_shader_source(name, source)))
+struct SourceLine {
+    text: String,
+    // File index and 1-based line number; `None` for lines added by the loader.
+    origin: Option<(usize, u32)>,
 }
 
-#[cfg(target_arch = "wasm32")]
-fn shader_source_for_name(name: &str) -> Option<&'static str> {
-    match name {
-        "simple.vert" => Some(include_str!("../shader/simple.vert")),
-        "blackhole_main.frag" => Some(include_str!("../shader/blackhole_main.frag")),
-        "bloom_brightness_pass.frag" => Some(include_str!("../shader/bloom_brightness_pass.frag")),
-        "lens_flare.frag" => Some(include_str!("../shader/lens_flare.frag")),
-        "bloom_downsample.frag" => Some(include_str!("../shader/bloom_downsample.frag")),
-        "bloom_upsample.frag" => Some(include_str!("../shader/bloom_upsample.frag")),
-        "bloom_composite.frag" => Some(include_str!("../shader/bloom_composite.frag")),
-        "tonemapping.frag" => Some(include_str!("../shader/tonemapping.frag")),
-        "passthrough.frag" => Some(include_str!("../shader/passthrough.frag")),
-        _ => None,
-    }
+// A shader after `#include` expansion (see `shader_include.rs`). Every line remembers
+// the file and line it came from, so driver messages about the combined source can
+// name the original file.
+struct ShaderSource {
+    files: Vec<String>,
+    lines: Vec<SourceLine>,
 }
 
-#[cfg(target_arch = "wasm32")]
-fn normalize_shader_source(name: &str, source: &str) -> String {
-    let normalized = source.replace("#version 330 core", "#version 300 es");
-    let mut lines = normalized.lines();
-    let mut output = String::new();
+impl ShaderSource {
+    fn preprocess(
+        name: &str,
+        read: &dyn Fn(&str) -> anyhow::Result<Cow<'static, str>>,
+    ) -> anyhow::Result<Self> {
+        let expanded = crate::shader_include::expand(name, read)?;
+        let lines = expanded
+            .lines
+            .into_iter()
+            .map(|(text, file, line)| SourceLine {
+                text,
+                origin: Some((file, line)),
+            })
+            .collect();
+        Ok(Self {
+            files: expanded.files,
+            lines,
+        })
+    }
+
+    // Adds generated lines right after `#version`, which has to stay first.
+    fn insert_after_version(&mut self, lines: impl IntoIterator<Item = String>) {
+        let at = 1.min(self.lines.len());
+        let generated = lines.into_iter().map(|text| SourceLine { text, origin: None });
+        self.lines.splice(at..at, generated);
+    }
 
-    if let Some(first) = lines.next() {
-        output.push_str(first);
-        output.push('\n');
+    fn define(&mut self, defines: &[(&'static str, i32)]) {
+        let lines = defines
+            .iter()
+            .map(|(name, value)| format!("#define {} {}", name, value));
+        self.insert_after_version(lines);
     }
 
-    if name.ends_with(".frag") && !normalized.contains("precision ") {
-        output.push_str("precision highp float;\n");
-        output.push_str("precision highp sampler2D;\n");
-        output.push_str("precision highp sampler3D;\n");
-        output.push_str("precision highp samplerCube;\n");
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for line in &self.lines {
+            text.push_str(&line.text);
+            text.push('\n');
+        }
+        text
+    }
+
+    // File index and line of 1-based `line` in the combined source.
+    fn location(&self, line: usize) -> Option<(usize, u32)> {
+        self.lines.get(line.checked_sub(1)?)?.origin
+    }
+
+    // The lines of `file` around `line`, as written in the file.
+    fn context(&self, file: usize, line: u32) -> Vec<(u32, String)> {
+        let range = line.saturating_sub(CONTEXT_LINES)..=line + CONTEXT_LINES;
+        self.lines
+            .iter()
+            .filter_map(|source| match source.origin {
+                Some((f, n)) if f == file && range.contains(&n) => Some((n, source.text.clone())),
+                _ => None,
+            })
+            .collect()
     }
 
-    for line in lines {
-        if name.ends_with(".frag") {
-            output.push_str(&strip_uniform_initializer(line));
-        } else {
-            output.push_str(line);
+    // Splits a compile log into diagnostics, rewriting the `0:LINE` (Mesa, ANGLE) and
+    // `0(LINE)` (NVIDIA) positions to `file:line`.
+    fn diagnostics(&self, log: &str) -> Vec<Diagnostic> {
+        log.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| match self.remap_log_line(line) {
+                Some((message, file, file_line)) => Diagnostic {
+                    message,
+                    location: Some((self.files[file].clone(), file_line)),
+                    context: self.context(file, file_line),
+                },
+                None => Diagnostic::plain(line.to_string()),
+            })
+            .collect()
+    }
+
+    fn remap_log_line(&self, line: &str) -> Option<(String, usize, u32)> {
+        let bytes = line.as_bytes();
+        for start in 0..bytes.len().saturating_sub(2) {
+            if bytes[start] != b'0' || !matches!(bytes[start + 1], b':' | b'(') {
+                continue;
+            }
+            if start > 0 && bytes[start - 1].is_ascii_alphanumeric() {
+                continue;
+            }
+            let rest = &line[start + 2..];
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                continue;
+            }
+            let mut end = start + 2 + digits;
+            if bytes[start + 1] == b'(' {
+                if bytes.get(end) != Some(&b')') {
+                    continue;
+                }
+                end += 1;
+            }
+            let (file, file_line) = self.location(rest[..digits].parse().ok()?)?;
+            let name = &self.files[file];
+            let message = format!("{}{}:{}{}", &line[..start], name, file_line, &line[end..]);
+            return Some((message, file, file_line));
         }
-        output.push('\n');
+        None
     }
+}
 
-    output
+// Loads `name` (relative to `shader/`) through the asset store and expands its
+// includes.
+fn load_shader_source(name: &str) -> anyhow::Result<ShaderSource> {
+    let source = ShaderSource::preprocess(name, &|file| {
+        crate::assets::read_to_string(&format!("shader/{}", file))
+    })?;
+    #[cfg(target_arch = "wasm32")]
+    let source = add_precision(name, source);
+    Ok(source)
 }
 
+// A shader compiled from a string in the binary, e.g. the fallback pass. `body` is
+// everything after the `#version` line, which depends on the target.
+fn embedded_source(name: &str, body: &str) -> anyhow::Result<ShaderSource> {
+    let text = format!("{}\n{}", GLSL_VERSION, body);
+    let source = ShaderSource::preprocess(name, &|_| Ok(Cow::Owned(text.clone())))?;
+    #[cfg(target_arch = "wasm32")]
+    let source = add_precision(name, source);
+    Ok(source)
+}
+
+// ES fragment shaders have no default float precision.
 #[cfg(target_arch = "wasm32")]
-fn strip_uniform_initializer(line: &str) -> String {
-    let trimmed = line.trim_start();
-    if !trimmed.starts_with("uniform ") {
-        return line.to_string();
-    }
-    let eq_pos = match trimmed.find('=') {
-        Some(pos) => pos,
-        None => return line.to_string(),
-    };
-    let semi_pos = match trimmed.find(';') {
-        Some(pos) => pos,
-        None => return line.to_string(),
-    };
-    if eq_pos > semi_pos {
-        return line.to_string();
-    }
-    let indent_len = line.len() - trimmed.len();
-    let indent = &line[..indent_len];
-    let left = trimmed[..eq_pos].trim_end();
-    let suffix = if semi_pos + 1 < trimmed.len() {
-        &trimmed[semi_pos + 1..]
-    } else {
-        ""
-    };
-    let mut output = String::new();
-    output.push_str(indent);
-    output.push_str(left);
-    output.push(';');
-    output.push_str(suffix);
-    output
+fn add_precision(name: &str, mut source: ShaderSource) -> ShaderSource {
+    let fragment = name.ends_with(".frag");
+    let has_precision = source.lines.iter().any(|line| line.text.contains("precision "));
+    if fragment && !has_precision {
+        source.insert_after_version(
+            [
+                "precision highp float;",
+                "precision highp sampler2D;",
+                "precision highp sampler3D;",
+                "precision highp samplerCube;",
+            ]
+            .map(String::from),
+        );
+    }
+    source
 }
 
 unsafe fn compile_shader(
     gl: &glow::Context,
     shader_type: u32,
-    source: &str,
+    source: &ShaderSource,
 ) -> anyhow::Result<glow::Shader> {
     let shader = gl.create_shader(shader_type).map_err(|e| anyhow::anyhow!(e))?;
-    gl.shader_source(shader, source);
+    gl.shader_source(shader, &source.text());
     gl.compile_shader(shader);
 
     if !gl.get_shader_compile_status(shader) {
-        let log = gl.get_shader_info_log(shader);
-        anyhow::bail!("Shader compilation failed: {}", log);
+        let diagnostics = source.diagnostics(&gl.get_shader_info_log(shader));
+        gl.delete_shader(shader);
+        return Err(ShaderError {
+            stage: "compilation",
+            diagnostics,
+        }
+        .into());
     }
 
     Ok(shader)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // main.frag includes a.glsl, which includes b.glsl before its own code.
+    fn nested() -> ShaderSource {
+        let read = |name: &str| -> anyhow::Result<Cow<'static, str>> {
+            Ok(Cow::Borrowed(match name {
+                "main.frag" => {
+                    "#version 330 core\n#include \"common/a.glsl\"\nvoid main() {\n    x;\n}\n"
+                }
+                "common/a.glsl" => "#include \"common/b.glsl\"\nfloat a() { return 1.0; }\n",
+                "common/b.glsl" => "float b() {\n    oops\n}\n",
+                _ => anyhow::bail!("no file {}", name),
+            }))
+        };
+        let mut source = ShaderSource::preprocess("main.frag", &read).unwrap();
+        // Shifts every line of the combined source by one.
+        source.define(&[("STEPS", 4)]);
+        source
+    }
+
+    #[test]
+    fn mesa_positions_map_through_nested_includes() {
+        let source = nested();
+        let (message, file, line) = source
+            .remap_log_line("0:4(5): error: syntax error, unexpected '}'")
+            .unwrap();
+        assert_eq!(message, "common/b.glsl:2(5): error: syntax error, unexpected '}'");
+        assert_eq!((source.files[file].as_str(), line), ("common/b.glsl", 2));
+
+        let (message, _, _) = source.remap_log_line("0:6(1): error: a() redefined").unwrap();
+        assert_eq!(message, "common/a.glsl:2(1): error: a() redefined");
+    }
+
+    #[test]
+    fn nvidia_positions_map_through_nested_includes() {
+        let source = nested();
+        let (message, file, line) = source
+            .remap_log_line("0(8) : error C1008: undefined variable \"x\"")
+            .unwrap();
+        assert_eq!(message, "main.frag:4 : error C1008: undefined variable \"x\"");
+        assert_eq!((source.files[file].as_str(), line), ("main.frag", 4));
+    }
+
+    #[test]
+    fn generated_lines_and_plain_messages_are_not_remapped() {
+        let source = nested();
+        // Line 2 is the `#define` added by the loader.
+        assert!(source.remap_log_line("0:2(1): error: bad define").is_none());
+        assert!(source.remap_log_line("error: linking failed").is_none());
+        assert!(source.remap_log_line("10:4(1): error").is_none());
+    }
+}
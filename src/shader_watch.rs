@@ -0,0 +1,71 @@
+// Watches the shader directory so edited passes can be recompiled while the app runs.
+//
+// Modification times are polled a few times a second rather than subscribing to OS
+// file events; the directory holds a handful of small files, so a scan is cheap.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct ShaderWatcher {
+    root: PathBuf,
+    // Modification time of every file, keyed by path relative to `root`.
+    mtimes: HashMap<String, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(root: PathBuf) -> Self {
+        let mut mtimes = HashMap::new();
+        scan_dir(&root, "", &mut mtimes);
+        Self {
+            root,
+            mtimes,
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Files added, modified or removed since the last scan, as paths relative to the
+    // shader directory (the form `#include` uses). Scans at most every POLL_INTERVAL.
+    pub fn poll(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut current = HashMap::new();
+        scan_dir(&self.root, "", &mut current);
+        let mut changed: Vec<String> = current
+            .iter()
+            .filter(|&(name, mtime)| self.mtimes.get(name) != Some(mtime))
+            .map(|(name, _)| name.clone())
+            .collect();
+        changed.extend(self.mtimes.keys().filter(|name| !current.contains_key(*name)).cloned());
+        self.mtimes = current;
+        changed
+    }
+
+    // When the next scan is due, so an idle event loop can wake up for it.
+    pub fn next_poll(&self) -> Instant {
+        self.last_poll + POLL_INTERVAL
+    }
+}
+
+fn scan_dir(dir: &Path, prefix: &str, mtimes: &mut HashMap<String, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            scan_dir(&entry.path(), &format!("{}/", name), mtimes);
+        } else if let Ok(modified) = metadata.modified() {
+            mtimes.insert(name, modified);
+        }
+    }
+}
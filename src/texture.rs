@@ -1,15 +1,12 @@
 #![allow(unsafe_op_in_unsafe_fn)]
+use anyhow::Context;
 use glow::HasContext;
 use image::GenericImageView;
-use std::path::Path;
 
-use crate::noise_gen;
+use crate::{blue_noise, noise_gen};
 
 pub unsafe fn load_texture_2d(gl: &glow::Context, path: &str) -> anyhow::Result<glow::Texture> {
-    #[cfg(not(target_arch = "wasm32"))]
-    let img = image::open(path)?;
-    #[cfg(target_arch = "wasm32")]
-    let img = load_embedded_image(path)?;
+    let img = load_image(path)?;
     let (width, height) = img.dimensions();
     let data = img.to_rgba8();
 
@@ -50,14 +47,7 @@ pub unsafe fn load_cubemap(gl: &glow::Context, dir_path: &str) -> anyhow::Result
     ];
 
     for (filename, target) in faces.iter() {
-        let path = Path::new(dir_path).join(filename);
-        #[cfg(not(target_arch = "wasm32"))]
-        let img = image::open(&path)?;
-        #[cfg(target_arch = "wasm32")]
-        let img = {
-            let path = path.to_str().unwrap_or(filename);
-            load_embedded_image(path)?
-        };
+        let img = load_image(&format!("{}/{}", dir_path, filename))?;
         let (width, height) = img.dimensions();
         let data = img.to_rgba8();
 
@@ -121,28 +111,65 @@ pub unsafe fn create_color_texture(gl: &glow::Context, width: u32, height: u32)
     Ok(texture)
 }
 
-#[cfg(target_arch = "wasm32")]
-fn load_embedded_image(path: &str) -> anyhow::Result<image::DynamicImage> {
-    let name = Path::new(path)
-        .file_name()
-        .and_then(|name| name.to_str())
-        .ok_or_else(|| anyhow::anyhow!("Invalid texture path: {}", path))?;
-    let bytes: &[u8] = match name {
-        "color_map.png" => include_bytes!("../assets/color_map.png"),
-        "right.png" => include_bytes!("../assets/skybox_nebula_dark/right.png"),
-        "left.png" => include_bytes!("../assets/skybox_nebula_dark/left.png"),
-        "top.png" => include_bytes!("../assets/skybox_nebula_dark/top.png"),
-        "bottom.png" => include_bytes!("../assets/skybox_nebula_dark/bottom.png"),
-        "front.png" => include_bytes!("../assets/skybox_nebula_dark/front.png"),
-        "back.png" => include_bytes!("../assets/skybox_nebula_dark/back.png"),
-        _ => {
-            return Err(anyhow::anyhow!(
-                "Unknown embedded texture asset: {}",
-                name
-            ));
+// Like `create_color_texture`, but 32-bit float where possible: a running average
+// stops moving once each new sample's share drops below the format's precision.
+pub unsafe fn create_accumulation_texture(
+    gl: &glow::Context,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<glow::Texture> {
+    let texture = create_color_texture(gl, width, height)?;
+    let format = accumulation_format(gl);
+    if format == glow::RGBA8 {
+        return Ok(texture);
+    }
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        format as i32,
+        width as i32,
+        height as i32,
+        0,
+        glow::RGBA,
+        glow::FLOAT,
+        None,
+    );
+    Ok(texture)
+}
+
+// How many samples the accumulation texture can average before new ones stop
+// registering; progressive refinement stops there.
+pub fn accumulation_sample_limit(gl: &glow::Context) -> u32 {
+    match accumulation_format(gl) {
+        glow::RGBA32F => u32::MAX,
+        glow::RGBA16F => 64,
+        _ => 16,
+    }
+}
+
+// WebGL2 renders to float textures only with EXT_color_buffer_float, and filters
+// 32-bit ones only with OES_texture_float_linear; without them the web build falls
+// back to half-float, then to RGBA8.
+fn accumulation_format(gl: &glow::Context) -> u32 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let extensions = gl.supported_extensions();
+        if !extensions.contains("EXT_color_buffer_float") {
+            return glow::RGBA8;
+        }
+        if !extensions.contains("OES_texture_float_linear") {
+            return glow::RGBA16F;
         }
-    };
-    image::load_from_memory(bytes).map_err(|e| e.into())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = gl;
+    glow::RGBA32F
+}
+
+// Decodes an image from the asset store; `path` is relative to the project root.
+fn load_image(path: &str) -> anyhow::Result<image::DynamicImage> {
+    let bytes = crate::assets::read(path)?;
+    image::load_from_memory(&bytes).with_context(|| format!("Failed to decode {}", path))
 }
 
 pub unsafe fn create_noise_texture_3d(gl: &glow::Context) -> anyhow::Result<glow::Texture> {
@@ -175,3 +202,33 @@ pub unsafe fn create_noise_texture_3d(gl: &glow::Context) -> anyhow::Result<glow
 
     Ok(texture)
 }
+
+pub unsafe fn create_blue_noise_texture(gl: &glow::Context) -> anyhow::Result<glow::Texture> {
+    let size = blue_noise::BLUE_NOISE_SIZE as i32;
+    let data = blue_noise::generate_blue_noise();
+
+    let texture = gl.create_texture().map_err(|e| anyhow::anyhow!(e))?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+
+    gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        glow::R8 as i32,
+        size,
+        size,
+        0,
+        glow::RED,
+        glow::UNSIGNED_BYTE,
+        Some(&data),
+    );
+
+    // Sampled one texel per pixel; filtering would blur the pattern towards white noise.
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::REPEAT as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::REPEAT as i32);
+    gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+
+    Ok(texture)
+}
@@ -0,0 +1,457 @@
+// Keyframe animation. A timeline holds one track per animated numeric `AppState`
+// field (by the names from `AppState::numeric_fields_mut`) plus a camera track, and
+// is evaluated into the state every frame before rendering. The interpolation of a
+// key applies to the segment that starts at it.
+//
+// Camera keys hold a full `CameraPose` (target, yaw, pitch, distance, FOV) and
+// interpolate component-wise, so keyed orbits swing around the hole. Camera roll is
+// the ordinary `camera_roll` field track.
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::camera::CameraPose;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Linear,
+    #[default]
+    Smoothstep,
+    CatmullRom,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 3] = [Self::Linear, Self::Smoothstep, Self::CatmullRom];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Smoothstep => "Smoothstep",
+            Self::CatmullRom => "Catmull-Rom",
+        }
+    }
+}
+
+pub trait Animatable: Copy {
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: f32) -> Self;
+}
+
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+impl Animatable for f32 {
+    fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        a + (b - a) * t
+    }
+
+    fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+        catmull_rom(p0, p1, p2, p3, t)
+    }
+}
+
+impl Animatable for CameraPose {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        let (a, b) = (a.components(), b.components());
+        Self::from_components(std::array::from_fn(|i| f32::lerp(a[i], b[i], t)))
+    }
+
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: f32) -> Self {
+        let [c0, c1, c2, c3] = [p0, p1, p2, p3].map(|p| p.components());
+        Self::from_components(std::array::from_fn(|i| {
+            catmull_rom(c0[i], c1[i], c2[i], c3[i], t)
+        }))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+// Keys are kept sorted by time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Track<T> {
+    pub keys: Vec<Keyframe<T>>,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    // Adds a key, replacing one already at (almost) the same time.
+    pub fn insert(&mut self, time: f32, value: T, interpolation: Interpolation) {
+        let key = Keyframe {
+            time,
+            value,
+            interpolation,
+        };
+        match self.keys.iter().position(|k| (k.time - time).abs() < 1e-3) {
+            Some(i) => self.keys[i] = key,
+            None => {
+                let i = self.keys.partition_point(|k| k.time < time);
+                self.keys.insert(i, key);
+            }
+        }
+    }
+
+    pub fn sort(&mut self) {
+        self.keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    // Holds the first/last value outside the keyed range.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+        if time >= last.time {
+            return Some(last.value);
+        }
+
+        let i = self.keys.partition_point(|k| k.time <= time) - 1;
+        let (k1, k2) = (&self.keys[i], &self.keys[i + 1]);
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 {
+            (time - k1.time) / span
+        } else {
+            1.0
+        };
+        Some(match k1.interpolation {
+            Interpolation::Linear => T::lerp(k1.value, k2.value, t),
+            Interpolation::Smoothstep => T::lerp(k1.value, k2.value, t * t * (3.0 - 2.0 * t)),
+            Interpolation::CatmullRom => {
+                let p0 = if i > 0 {
+                    self.keys[i - 1].value
+                } else {
+                    k1.value
+                };
+                let p3 = self.keys.get(i + 2).map_or(k2.value, |k| k.value);
+                T::catmull_rom(p0, k1.value, k2.value, p3, t)
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FieldTrack {
+    pub field: String,
+    #[serde(flatten)]
+    pub track: Track<f32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeline {
+    pub duration: f32,
+    pub looping: bool,
+    pub camera: Track<CameraPose>,
+    pub tracks: Vec<FieldTrack>,
+    // Saved with the scene so a loaded scene (and `blackhole_render`) shows the
+    // frame it was saved at.
+    pub playhead: f32,
+    #[serde(skip)]
+    pub playing: bool,
+    #[serde(skip)]
+    seeked: bool,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            duration: 10.0,
+            looping: true,
+            camera: Track::default(),
+            tracks: Vec::new(),
+            playhead: 0.0,
+            playing: false,
+            seeked: false,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn is_empty(&self) -> bool {
+        self.camera.is_empty() && self.tracks.iter().all(|t| t.track.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.camera.keys.clear();
+        self.tracks.clear();
+        self.playing = false;
+        self.playhead = 0.0;
+    }
+
+    pub fn track_mut(&mut self, field: &str) -> &mut Track<f32> {
+        let i = match self.tracks.iter().position(|t| t.field == field) {
+            Some(i) => i,
+            None => {
+                self.tracks.push(FieldTrack {
+                    field: field.to_string(),
+                    track: Track::default(),
+                });
+                self.tracks.len() - 1
+            }
+        };
+        &mut self.tracks[i].track
+    }
+
+    // Keys the current value of `field` at the playhead.
+    pub fn key_field(&mut self, state: &mut AppState, field: &str, interpolation: Interpolation) {
+        let value = state
+            .numeric_fields_mut()
+            .into_iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, value)| *value);
+        if let Some(value) = value {
+            let time = self.playhead;
+            self.track_mut(field).insert(time, value, interpolation);
+        }
+    }
+
+    pub fn key_camera(&mut self, state: &AppState, interpolation: Interpolation) {
+        self.camera
+            .insert(self.playhead, state.camera.pose, interpolation);
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.playhead = time.clamp(0.0, self.duration.max(0.0));
+        self.seeked = true;
+    }
+
+    // Tracks drive the state only while playing or right after a seek, so animated
+    // fields stay editable (and keyable) while paused.
+    pub fn should_apply(&mut self) -> bool {
+        let seeked = std::mem::take(&mut self.seeked);
+        !self.is_empty() && (self.playing || seeked)
+    }
+
+    // Moves the playhead by `dt` seconds when playing (backwards for negative `dt`),
+    // wrapping or stopping at either end.
+    pub fn advance(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+        self.playhead += dt;
+        if self.playhead >= self.duration || self.playhead < 0.0 {
+            if self.looping && self.duration > 0.0 {
+                self.playhead = self.playhead.rem_euclid(self.duration);
+            } else {
+                self.playhead = self.playhead.clamp(0.0, self.duration);
+                self.playing = false;
+            }
+        }
+    }
+
+    pub fn apply(&self, state: &mut AppState, time: f32) {
+        for (name, value) in state.numeric_fields_mut() {
+            if let Some(v) = self
+                .tracks
+                .iter()
+                .find(|t| t.field == name)
+                .and_then(|t| t.track.sample(time))
+            {
+                *value = v;
+            }
+        }
+        if let Some(pose) = self.camera.sample(time) {
+            state.camera.pose = pose;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(keys: &[(f32, f32)], interpolation: Interpolation) -> Track<f32> {
+        let mut track = Track::default();
+        for &(time, value) in keys {
+            track.insert(time, value, interpolation);
+        }
+        track
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn playing(looping: bool, playhead: f32) -> Timeline {
+        Timeline {
+            looping,
+            playhead,
+            playing: true,
+            ..Timeline::default()
+        }
+    }
+
+    #[test]
+    fn empty_track_has_no_value() {
+        assert_eq!(Track::<f32>::default().sample(1.0), None);
+    }
+
+    #[test]
+    fn sample_hits_keys_and_holds_beyond_them() {
+        let track = track(&[(1.0, 2.0), (3.0, 6.0), (2.0, 5.0)], Interpolation::Linear);
+        assert_eq!(track.sample(0.0), Some(2.0));
+        assert_eq!(track.sample(1.0), Some(2.0));
+        assert_eq!(track.sample(2.0), Some(5.0));
+        assert_eq!(track.sample(3.0), Some(6.0));
+        assert_eq!(track.sample(10.0), Some(6.0));
+    }
+
+    #[test]
+    fn linear_and_smoothstep_between_keys() {
+        let linear = track(&[(0.0, 0.0), (2.0, 4.0)], Interpolation::Linear);
+        assert_near(linear.sample(0.5).unwrap(), 1.0);
+
+        // Smoothstep at a quarter of the segment: 3t² - 2t³ = 0.15625.
+        let smooth = track(&[(0.0, 0.0), (2.0, 4.0)], Interpolation::Smoothstep);
+        assert_near(smooth.sample(0.5).unwrap(), 0.625);
+        assert_near(smooth.sample(1.0).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn interpolation_comes_from_the_segment_start() {
+        let mut track = track(&[(0.0, 0.0), (1.0, 1.0)], Interpolation::Smoothstep);
+        track.insert(0.0, 0.0, Interpolation::Linear);
+        track.insert(2.0, 0.0, Interpolation::Smoothstep);
+        assert_near(track.sample(0.25).unwrap(), 0.25);
+        assert_near(track.sample(1.25).unwrap(), 1.0 - 0.15625);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_keys() {
+        let keys = [(0.0, 0.0), (1.0, 1.0), (2.0, 3.0), (3.0, 2.0)];
+        let track = track(&keys, Interpolation::CatmullRom);
+        for (time, value) in keys {
+            assert_near(track.sample(time).unwrap(), value);
+        }
+        // Interior segment, with both neighbours: 0.5 * (2 + 1.5 + 1.25 - 0.5).
+        assert_near(track.sample(1.5).unwrap(), 2.125);
+    }
+
+    #[test]
+    fn catmull_rom_end_segments_repeat_the_end_keys() {
+        let track = track(
+            &[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)],
+            Interpolation::CatmullRom,
+        );
+        // The first segment uses the first key as its missing neighbour, the last
+        // segment the last key, so the curve is symmetric here.
+        assert_near(track.sample(0.5).unwrap(), 0.5625);
+        assert_near(track.sample(1.5).unwrap(), 0.5625);
+
+        // With only two keys it is a smoothstep-like ease that stays in range.
+        let two = self::track(&[(0.0, 0.0), (1.0, 1.0)], Interpolation::CatmullRom);
+        assert_near(two.sample(0.5).unwrap(), 0.5);
+        for i in 0..=10 {
+            let value = two.sample(i as f32 / 10.0).unwrap();
+            assert!((0.0..=1.0).contains(&value));
+        }
+    }
+
+    #[test]
+    fn insert_replaces_a_key_at_the_same_time() {
+        let mut track = track(&[(0.0, 0.0), (1.0, 1.0)], Interpolation::Linear);
+        track.insert(1.0005, 5.0, Interpolation::Linear);
+        assert_eq!(track.keys.len(), 2);
+        assert_eq!(track.sample(1.0005), Some(5.0));
+    }
+
+    #[test]
+    fn advance_wraps_when_looping() {
+        let mut timeline = playing(true, 9.0);
+        timeline.advance(2.5);
+        assert_near(timeline.playhead, 1.5);
+        assert!(timeline.playing);
+
+        timeline.advance(-2.0);
+        assert_near(timeline.playhead, 9.5);
+        assert!(timeline.playing);
+    }
+
+    #[test]
+    fn advance_stops_at_the_ends_without_looping() {
+        let mut timeline = playing(false, 9.0);
+        timeline.advance(2.0);
+        assert_eq!(timeline.playhead, 10.0);
+        assert!(!timeline.playing);
+
+        let mut timeline = playing(false, 1.0);
+        timeline.advance(-2.0);
+        assert_eq!(timeline.playhead, 0.0);
+        assert!(!timeline.playing);
+    }
+
+    #[test]
+    fn advance_does_nothing_while_paused() {
+        let mut timeline = Timeline {
+            playhead: 3.0,
+            ..Timeline::default()
+        };
+        timeline.advance(1.0);
+        assert_eq!(timeline.playhead, 3.0);
+    }
+
+    #[test]
+    fn seek_clamps_and_applies_once() {
+        let mut timeline = Timeline::default();
+        timeline.seek(4.0);
+        // Nothing to apply without keys.
+        assert!(!timeline.should_apply());
+
+        timeline
+            .track_mut("spin")
+            .insert(0.0, 0.5, Interpolation::Linear);
+        timeline.seek(20.0);
+        assert_eq!(timeline.playhead, 10.0);
+        timeline.seek(-1.0);
+        assert_eq!(timeline.playhead, 0.0);
+        assert!(timeline.should_apply());
+        assert!(!timeline.should_apply());
+    }
+
+    #[test]
+    fn apply_sets_keyed_fields_only() {
+        let mut timeline = Timeline::default();
+        timeline
+            .track_mut("spin")
+            .insert(0.0, 0.1, Interpolation::Linear);
+        timeline
+            .track_mut("spin")
+            .insert(2.0, 0.5, Interpolation::Linear);
+        let mut state = AppState::default();
+        let expected = AppState {
+            spin: 0.3,
+            ..state.clone()
+        };
+        timeline.apply(&mut state, 1.0);
+        assert_near(state.spin, 0.3);
+        state.spin = 0.3;
+        assert!(state == expected);
+    }
+}
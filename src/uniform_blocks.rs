@@ -0,0 +1,238 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+// Per-frame parameters shared by the shaders as std140 uniform blocks.
+//
+// Each block is a `#[repr(C)]` struct laid out by hand to match std140 (vec3 and mat3
+// columns padded to 16 bytes) and uploaded in one call. Shaders declare the block with
+// the same member names in the same order; `bind_blocks` checks every linked program
+// against the struct before attaching the shared buffer.
+
+use bytemuck::{Pod, Zeroable};
+use glow::HasContext;
+use std::marker::PhantomData;
+
+pub trait UniformBlock: Pod {
+    const NAME: &'static str;
+    const BINDING: u32;
+    // GLSL name, GL type and byte offset in the struct of every member, in
+    // declaration order.
+    const MEMBERS: &'static [(&'static str, u32, usize)];
+}
+
+// Ray-march parameters for blackhole_main.frag.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct BlackHoleParams {
+    pub camera_pos: [f32; 3],
+    pub fov_scale: f32,
+    // Camera-to-world matrix, one padded column per row.
+    pub view: [[f32; 4]; 3],
+    pub jitter: [f32; 2],
+    pub time: f32,
+    pub spin: f32,
+    pub adisk_density_v: f32,
+    pub adisk_density_h: f32,
+    pub adisk_height: f32,
+    pub adisk_lit: f32,
+    pub adisk_noise_scale: f32,
+    pub adisk_speed: f32,
+    pub step_jitter: u32,
+    pub frame_index: f32,
+    pub dither_strength: f32,
+    pub step_scale: f32,
+    pub min_step: f32,
+    pub max_steps: f32,
+}
+
+impl UniformBlock for BlackHoleParams {
+    const NAME: &'static str = "BlackHoleParams";
+    const BINDING: u32 = 0;
+    const MEMBERS: &'static [(&'static str, u32, usize)] = &[
+        ("cameraPos", glow::FLOAT_VEC3, std::mem::offset_of!(Self, camera_pos)),
+        ("fovScale", glow::FLOAT, std::mem::offset_of!(Self, fov_scale)),
+        ("view", glow::FLOAT_MAT3, std::mem::offset_of!(Self, view)),
+        ("jitter", glow::FLOAT_VEC2, std::mem::offset_of!(Self, jitter)),
+        ("time", glow::FLOAT, std::mem::offset_of!(Self, time)),
+        ("spin", glow::FLOAT, std::mem::offset_of!(Self, spin)),
+        ("adiskDensityV", glow::FLOAT, std::mem::offset_of!(Self, adisk_density_v)),
+        ("adiskDensityH", glow::FLOAT, std::mem::offset_of!(Self, adisk_density_h)),
+        ("adiskHeight", glow::FLOAT, std::mem::offset_of!(Self, adisk_height)),
+        ("adiskLit", glow::FLOAT, std::mem::offset_of!(Self, adisk_lit)),
+        ("adiskNoiseScale", glow::FLOAT, std::mem::offset_of!(Self, adisk_noise_scale)),
+        ("adiskSpeed", glow::FLOAT, std::mem::offset_of!(Self, adisk_speed)),
+        ("stepJitter", glow::BOOL, std::mem::offset_of!(Self, step_jitter)),
+        ("frameIndex", glow::FLOAT, std::mem::offset_of!(Self, frame_index)),
+        ("ditherStrength", glow::FLOAT, std::mem::offset_of!(Self, dither_strength)),
+        ("stepScale", glow::FLOAT, std::mem::offset_of!(Self, step_scale)),
+        ("minStep", glow::FLOAT, std::mem::offset_of!(Self, min_step)),
+        ("maxSteps", glow::FLOAT, std::mem::offset_of!(Self, max_steps)),
+    ];
+}
+
+// Settings of the post chain, shared by the upscale, composite and tonemapping passes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct PostParams {
+    pub tonemapping_enabled: u32,
+    pub gamma: f32,
+    pub time: f32,
+    pub chroma_strength: f32,
+    pub grain_strength: f32,
+    pub saturation: f32,
+    pub output_dither: f32,
+    pub tone: f32,
+    pub bloom_strength: f32,
+    pub flare_strength: f32,
+    pub filter_mode: i32,
+    pub _pad: f32,
+}
+
+impl UniformBlock for PostParams {
+    const NAME: &'static str = "PostParams";
+    const BINDING: u32 = 1;
+    const MEMBERS: &'static [(&'static str, u32, usize)] = &[
+        ("tonemappingEnabled", glow::BOOL, std::mem::offset_of!(Self, tonemapping_enabled)),
+        ("gamma", glow::FLOAT, std::mem::offset_of!(Self, gamma)),
+        ("time", glow::FLOAT, std::mem::offset_of!(Self, time)),
+        ("chromaStrength", glow::FLOAT, std::mem::offset_of!(Self, chroma_strength)),
+        ("grainStrength", glow::FLOAT, std::mem::offset_of!(Self, grain_strength)),
+        ("saturation", glow::FLOAT, std::mem::offset_of!(Self, saturation)),
+        ("outputDither", glow::FLOAT, std::mem::offset_of!(Self, output_dither)),
+        ("tone", glow::FLOAT, std::mem::offset_of!(Self, tone)),
+        ("bloomStrength", glow::FLOAT, std::mem::offset_of!(Self, bloom_strength)),
+        ("flareStrength", glow::FLOAT, std::mem::offset_of!(Self, flare_strength)),
+        ("filterMode", glow::INT, std::mem::offset_of!(Self, filter_mode)),
+    ];
+}
+
+// A mat3 as std140 stores it: three columns, each padded to a vec4.
+pub fn std140_mat3(m: glam::Mat3) -> [[f32; 4]; 3] {
+    [m.x_axis.extend(0.0), m.y_axis.extend(0.0), m.z_axis.extend(0.0)].map(|c| c.to_array())
+}
+
+// Base alignment and size of a member type under std140.
+fn std140_align_size(gl_type: u32) -> Option<(usize, usize)> {
+    match gl_type {
+        glow::FLOAT | glow::INT | glow::UNSIGNED_INT | glow::BOOL => Some((4, 4)),
+        glow::FLOAT_VEC2 => Some((8, 8)),
+        glow::FLOAT_VEC3 => Some((16, 12)),
+        glow::FLOAT_VEC4 => Some((16, 16)),
+        glow::FLOAT_MAT3 => Some((16, 48)),
+        glow::FLOAT_MAT4 => Some((16, 64)),
+        _ => None,
+    }
+}
+
+// Checks `T` against std140 and against what the linker reports for the block: its
+// data size, and the name and type of every active member. glow has no binding for
+// glGetActiveUniformsiv, so member offsets are checked by laying the reported types
+// out with the std140 rules rather than read back directly.
+unsafe fn check_layout<T: UniformBlock>(
+    gl: &glow::Context,
+    program: glow::Program,
+    index: u32,
+) -> anyhow::Result<()> {
+    let mut offset = 0usize;
+    for &(name, gl_type, rust_offset) in T::MEMBERS {
+        let (align, size) = std140_align_size(gl_type)
+            .ok_or_else(|| anyhow::anyhow!("{}.{} has no std140 layout", T::NAME, name))?;
+        offset = offset.next_multiple_of(align);
+        if rust_offset != offset {
+            anyhow::bail!(
+                "{}.{} is at byte {} in the Rust struct but std140 puts it at {}",
+                T::NAME,
+                name,
+                rust_offset,
+                offset
+            );
+        }
+        offset += size;
+    }
+    let rust_size = std::mem::size_of::<T>();
+    if offset.next_multiple_of(16) != rust_size {
+        anyhow::bail!(
+            "{} is {} bytes in Rust but {} in std140",
+            T::NAME,
+            rust_size,
+            offset.next_multiple_of(16)
+        );
+    }
+
+    let gl_size =
+        gl.get_active_uniform_block_parameter_i32(program, index, glow::UNIFORM_BLOCK_DATA_SIZE);
+    if gl_size as usize != rust_size {
+        anyhow::bail!("{} is {} bytes in the shader but {} in Rust", T::NAME, gl_size, rust_size);
+    }
+
+    let count = gl.get_active_uniform_block_parameter_i32(
+        program,
+        index,
+        glow::UNIFORM_BLOCK_ACTIVE_UNIFORMS,
+    );
+    let mut indices = vec![0; count.max(0) as usize];
+    gl.get_active_uniform_block_parameter_i32_slice(
+        program,
+        index,
+        glow::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES,
+        &mut indices,
+    );
+    for uniform in indices {
+        let Some(active) = gl.get_active_uniform(program, uniform as u32) else {
+            continue;
+        };
+        let name = active.name.trim_start_matches(&format!("{}.", T::NAME));
+        match T::MEMBERS.iter().find(|(member, _, _)| *member == name) {
+            Some(&(_, gl_type, _)) if gl_type == active.utype => {}
+            Some(_) => anyhow::bail!("{}.{} has a different type in the shader", T::NAME, name),
+            None => anyhow::bail!("{}.{} has no field in the Rust struct", T::NAME, name),
+        }
+    }
+    Ok(())
+}
+
+unsafe fn bind_block<T: UniformBlock>(
+    gl: &glow::Context,
+    program: glow::Program,
+) -> anyhow::Result<()> {
+    if let Some(index) = gl.get_uniform_block_index(program, T::NAME) {
+        check_layout::<T>(gl, program, index)?;
+        gl.uniform_block_binding(program, index, T::BINDING);
+    }
+    Ok(())
+}
+
+// Points the blocks a freshly linked program declares at their shared buffers.
+pub unsafe fn bind_blocks(gl: &glow::Context, program: glow::Program) -> anyhow::Result<()> {
+    bind_block::<BlackHoleParams>(gl, program)?;
+    bind_block::<PostParams>(gl, program)?;
+    Ok(())
+}
+
+pub struct UniformBuffer<T> {
+    buffer: glow::Buffer,
+    _block: PhantomData<T>,
+}
+
+impl<T: UniformBlock> UniformBuffer<T> {
+    pub unsafe fn new(gl: &glow::Context) -> anyhow::Result<Self> {
+        let buffer = gl.create_buffer().map_err(|e| anyhow::anyhow!(e))?;
+        gl.bind_buffer(glow::UNIFORM_BUFFER, Some(buffer));
+        gl.buffer_data_size(
+            glow::UNIFORM_BUFFER,
+            std::mem::size_of::<T>() as i32,
+            glow::DYNAMIC_DRAW,
+        );
+        gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+        Ok(Self {
+            buffer,
+            _block: PhantomData,
+        })
+    }
+
+    // Uploads `value` and (re)binds the buffer to the block's binding point, which
+    // other GL users such as the GUI may have changed since the last frame.
+    pub unsafe fn upload(&self, gl: &glow::Context, value: &T) {
+        gl.bind_buffer_base(glow::UNIFORM_BUFFER, T::BINDING, Some(self.buffer));
+        gl.buffer_sub_data_u8_slice(glow::UNIFORM_BUFFER, 0, bytemuck::bytes_of(value));
+        gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+    }
+}